@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+
+use codee::string::JsonSerdeCodec;
+use leptos_use::{core::ConnectionReadyState, use_websocket_with_options, UseWebSocketOptions, UseWebSocketReturn};
+use thrw_shared::{app::state::{client::LoginContext, shared::LoginState}, downloader::{api::{cancel_download, get_available_formats, get_download_jobs, retry_download}, shared::{PubAudioCodec, PubAvailableFormat, PubContainer, PubDownloadFormat, PubDownloadJob, PubDownloadPhase, PubDownloadProgress, PubDownloadState, PubPlaylistProgress, PubPostOutput, PubPostProcessing, PubPostStep}}, ws::ThrwSocketMessage};
+
+use crate::prelude::*;
+
+pub(self) mod consts {
+	pub const RECENT_JOB_LIMIT: i64 = 10;
+	pub const SOCKET_URL: &str = "/ws/chat";
+	pub const MAX_HEIGHTS: &[i32] = &[2160, 1440, 1080, 720, 480, 360];
+	/// the values of the step select, in the order they are offered
+	pub const POST_STEP_KINDS: &[(&str, &str)] = &[
+		("convert", "convert to audio"),
+		("trim", "trim silence"),
+		("sponsors", "remove sponsors"),
+		("uploader", "move into uploader folder"),
+	];
+}
+
+fn format_speed(speed: f64) -> String {
+	match speed {
+		speed if speed >= 1024.0 * 1024.0 => format!("{:.1} MiB/s", speed / 1024.0 / 1024.0),
+		speed if speed >= 1024.0 => format!("{:.1} KiB/s", speed / 1024.0),
+		speed => format!("{speed:.0} B/s"),
+	}
+}
+
+pub fn format_size(size: i64) -> String {
+	match size {
+		size if size >= 1024 * 1024 * 1024 => format!("{:.1} GiB", size as f64 / 1024.0 / 1024.0 / 1024.0),
+		size if size >= 1024 * 1024 => format!("{:.1} MiB", size as f64 / 1024.0 / 1024.0),
+		size => format!("{:.0} KiB", size as f64 / 1024.0),
+	}
+}
+
+fn describe_format(format: &PubAvailableFormat) -> String {
+	let mut parts = vec![format.format_id.clone()];
+	parts.push(format.ext.clone().unwrap_or_default());
+	match (format.width, format.height) {
+		(Some(width), Some(height)) => parts.push(format!("{width}x{height}")),
+		(None, Some(height)) => parts.push(format!("{height}p")),
+		_ => (),
+	}
+	if let Some(fps) = format.fps {
+		parts.push(format!("{fps:.0}fps"));
+	}
+	parts.push(match (&format.vcodec, &format.acodec) {
+		(Some(vcodec), Some(acodec)) => format!("{vcodec} + {acodec}"),
+		(Some(vcodec), None) => format!("{vcodec} (video only)"),
+		(None, Some(acodec)) => format!("{acodec} (audio only)"),
+		(None, None) => "unknown codecs".to_string(),
+	});
+	if let Some(tbr) = format.tbr {
+		parts.push(format!("{tbr:.0}k"));
+	}
+	if let Some(size) = format.filesize {
+		parts.push(format_size(size));
+	}
+	if let Some(note) = &format.note {
+		parts.push(note.clone());
+	}
+	parts.join(" ")
+}
+
+/// quality and conversion options for a download, with an optional picker of the formats a url offers
+#[component]
+pub fn download_format_picker(
+	format: RwSignal<PubDownloadFormat>,
+	#[prop(into)]
+	url: Signal<String>,
+	#[prop(into)]
+	audio_only: Signal<bool>,
+) -> impl IntoView {
+	let available = RwSignal::new(Vec::<PubAvailableFormat>::new());
+	let loading = RwSignal::new(false);
+
+	// formats listed for another url don't apply
+	Effect::new(move |_| {
+		url.track();
+		available.set(vec![]);
+		format.update(|format| format.format_id = None);
+	});
+
+	let list_formats = move |_| {
+		loading.set(true);
+		spawn_local(async move {
+			match get_available_formats(url.get_untracked()).await {
+				Ok(formats) => available.set(formats),
+				Err(err) => {
+					log::debug!("unable to list formats: {err:?}");
+					available.set(vec![]);
+				},
+			}
+			loading.set(false);
+		});
+	};
+
+	view! {
+		<div class="download_format">
+			<Show when=move || !audio_only.get()>
+				<select on:change=move |ev| {
+					let height = event_target_value(&ev).parse::<i32>().ok();
+					format.update(|format| format.max_height = height);
+				}>
+					<option value="" selected=move || format.with(|format| format.max_height.is_none())>"any resolution"</option>
+					{consts::MAX_HEIGHTS.iter().map(|height| view! {
+						<option value=height.to_string() selected=move || format.with(|format| format.max_height == Some(*height))>
+							{format!("up to {height}p")}
+						</option>
+					}).collect_view()}
+				</select>
+				<select on:change=move |ev| {
+					let value = event_target_value(&ev);
+					let container = PubContainer::ALL.into_iter().find(|container| container.to_string() == value);
+					format.update(|format| format.container = container);
+				}>
+					<option value="" selected=move || format.with(|format| format.container.is_none())>"any container"</option>
+					{PubContainer::ALL.into_iter().map(|container| view! {
+						<option value=container.to_string() selected=move || format.with(|format| format.container == Some(container))>
+							{container.to_string()}
+						</option>
+					}).collect_view()}
+				</select>
+			</Show>
+			<Show when=move || audio_only.get()>
+				<select on:change=move |ev| {
+					let value = event_target_value(&ev);
+					let codec = PubAudioCodec::ALL.into_iter().find(|codec| codec.to_string() == value);
+					format.update(|format| format.audio_codec = codec);
+				}>
+					{PubAudioCodec::ALL.into_iter().map(|codec| view! {
+						<option value=codec.to_string() selected=move || format.with(|format| format.audio_codec.unwrap_or(PubAudioCodec::Best) == codec)>
+							{codec.to_string()}
+						</option>
+					}).collect_view()}
+				</select>
+				<input
+					type="number"
+					min="8"
+					max="512"
+					placeholder="kbit/s"
+					prop:value=move || format.with(|format| format.audio_bitrate.map(|bitrate| bitrate.to_string()).unwrap_or_default())
+					on:input=move |ev| {
+						let bitrate = event_target_value(&ev).parse::<i32>().ok();
+						format.update(|format| format.audio_bitrate = bitrate);
+					}
+				/>
+			</Show>
+			<label>
+				<input
+					type="checkbox"
+					prop:checked=move || format.with(|format| format.embed_thumbnail)
+					on:change=move |ev| {
+						let checked = event_target_checked(&ev);
+						format.update(|format| format.embed_thumbnail = checked);
+					}
+				/>
+				"embed thumbnail"
+			</label>
+			<label>
+				<input
+					type="checkbox"
+					prop:checked=move || format.with(|format| format.embed_metadata)
+					on:change=move |ev| {
+						let checked = event_target_checked(&ev);
+						format.update(|format| format.embed_metadata = checked);
+					}
+				/>
+				"embed metadata"
+			</label>
+			<button on:click=list_formats disabled=move || loading.get()>
+				{move || if loading.get() { "listing formats..." } else { "list formats" }}
+			</button>
+			<Show when=move || !available.with(Vec::is_empty)>
+				<select on:change=move |ev| {
+					let value = event_target_value(&ev);
+					format.update(|format| format.format_id = Some(value).filter(|value| !value.is_empty()));
+				}>
+					<option value="" selected=move || format.with(|format| format.format_id.is_none())>"automatic"</option>
+					{move || available.get().into_iter().map(|available| {
+						let selector = available.selector();
+						let selected_selector = selector.clone();
+						view! {
+							<option
+								value=selector
+								selected=move || format.with(|format| format.format_id.as_ref() == Some(&selected_selector))
+							>
+								{describe_format(&available)}
+							</option>
+						}
+					}).collect_view()}
+				</select>
+			</Show>
+		</div>
+	}
+}
+
+/// ordered steps run once a download is in the vfs
+#[component]
+pub fn post_processing_picker(
+	post_processing: RwSignal<PubPostProcessing>,
+) -> impl IntoView {
+	let step_kind = RwSignal::new(consts::POST_STEP_KINDS[0].0.to_string());
+	let codec = RwSignal::new(PubAudioCodec::Mp3);
+	let bitrate = RwSignal::new("".to_string());
+
+	let add_step = move |_| {
+		let step = match step_kind.get_untracked().as_str() {
+			"convert" => PubPostStep::ConvertAudio {
+				codec: codec.get_untracked(),
+				bitrate: bitrate.get_untracked().trim().parse::<i32>().ok(),
+			},
+			"trim" => PubPostStep::TrimSilence,
+			"sponsors" => PubPostStep::RemoveSponsors,
+			_ => PubPostStep::MoveByUploader,
+		};
+		post_processing.update(|post_processing| post_processing.steps.push(step));
+	};
+
+	view! {
+		<div class="post_processing">
+			<ol>
+			{move || post_processing.with(|post_processing| post_processing.steps.clone())
+				.into_iter()
+				.enumerate()
+				.map(|(index, step)| view! {
+					<li>
+						{step.to_string()}
+						<button on:click=move |_| post_processing.update(|post_processing| {
+							post_processing.steps.remove(index);
+						})>
+							remove
+						</button>
+					</li>
+				})
+				.collect_view()
+			}
+			</ol>
+			<select on:change=move |ev| step_kind.set(event_target_value(&ev))>
+				{consts::POST_STEP_KINDS.iter().map(|(value, label)| view! {
+					<option value=*value selected=move || step_kind.with(|kind| kind.as_str() == *value)>{*label}</option>
+				}).collect_view()}
+			</select>
+			<Show when=move || step_kind.with(|kind| kind == "convert")>
+				<select on:change=move |ev| {
+					let value = event_target_value(&ev);
+					if let Some(picked) = PubAudioCodec::ALL.into_iter().find(|codec| codec.to_string() == value) {
+						codec.set(picked);
+					}
+				}>
+					// converting needs a codec to convert to
+					{PubAudioCodec::ALL.into_iter().filter(|codec| *codec != PubAudioCodec::Best).map(|option| view! {
+						<option value=option.to_string() selected=move || codec.get() == option>
+							{option.to_string()}
+						</option>
+					}).collect_view()}
+				</select>
+				<input bind:value=bitrate type="number" min="8" max="512" placeholder="kbit/s" />
+			</Show>
+			<button on:click=add_step>add step</button>
+			<select on:change=move |ev| {
+				let output = match event_target_value(&ev).as_str() {
+					"version" => PubPostOutput::Version,
+					_ => PubPostOutput::Sibling,
+				};
+				post_processing.update(|post_processing| post_processing.output = output);
+			}>
+				<option value="sibling" selected=move || post_processing.with(|post_processing| post_processing.output == PubPostOutput::Sibling)>
+					"keep the download"
+				</option>
+				<option value="version" selected=move || post_processing.with(|post_processing| post_processing.output == PubPostOutput::Version)>
+					"replace the download"
+				</option>
+			</select>
+		</div>
+	}
+}
+
+fn format_playlist(playlist: &PubPlaylistProgress) -> String {
+	format!("{}/{} downloaded, {} failed", playlist.completed, playlist.total, playlist.failed)
+}
+
+fn playlist_percent(playlist: &PubPlaylistProgress) -> Option<f64> {
+	(playlist.total > 0).then(|| (playlist.completed + playlist.failed) as f64 / playlist.total as f64 * 100.0)
+}
+
+fn format_progress(progress: &PubDownloadProgress) -> String {
+	match &progress.phase {
+		PubDownloadPhase::Queued => "queued".to_string(),
+		PubDownloadPhase::Downloading => {
+			let mut parts = vec![match progress.percent {
+				Some(percent) => format!("{percent:.1}%"),
+				None => "downloading".to_string(),
+			}];
+			if let Some(speed) = progress.speed {
+				parts.push(format_speed(speed));
+			}
+			if let Some(eta) = progress.eta {
+				parts.push(format!("{}:{:02} left", eta / 60, eta % 60));
+			}
+			parts.join(" - ")
+		},
+		PubDownloadPhase::PostProcessing(step) => format!("processing ({step})"),
+		PubDownloadPhase::Importing => "importing".to_string(),
+		PubDownloadPhase::Completed => "completed".to_string(),
+		PubDownloadPhase::Failed(err) => format!("failed: {err}"),
+		PubDownloadPhase::Cancelled => "cancelled".to_string(),
+		PubDownloadPhase::RetryScheduled(delay) => format!("retrying in {delay}s"),
+		PubDownloadPhase::Playlist(playlist) => format_playlist(playlist),
+	}
+}
+
+#[component]
+fn download_job_row(
+	job: PubDownloadJob,
+	progress: RwSignal<HashMap<uuid::Uuid, PubDownloadProgress>>,
+) -> impl IntoView {
+	let download_review = ReviewEvent::<{DOWNLOAD_IDS}>::use_provided();
+	let job_id = job.id;
+	let fallback = match &job.state {
+		PubDownloadState::Queued => match job.next_attempt_at {
+			Some(at) => format!("retrying at {}", at.with_timezone(&chrono::Local).format("%H:%M:%S")),
+			None => "queued".to_string(),
+		},
+		PubDownloadState::Running => "running".to_string(),
+		PubDownloadState::Completed => match &job.error {
+			Some(err) => format!("completed, {err}"),
+			None => "completed".to_string(),
+		},
+		PubDownloadState::Failed => format!("failed: {}", job.error.clone().unwrap_or_default()),
+		PubDownloadState::Cancelled => "cancelled".to_string(),
+		PubDownloadState::Expanded => job.playlist
+			.as_ref()
+			.map(format_playlist)
+			.unwrap_or("playlist".to_string()),
+	};
+	let fallback_percent = job.playlist.as_ref().and_then(playlist_percent);
+	let can_cancel = job.state.can_cancel();
+	let can_retry = job.state.can_retry();
+	let retry_count = job.retry_count;
+	let percent = move || progress.with(|map| map
+		.get(&job_id)
+		.and_then(|progress| progress.percent)
+		.or(fallback_percent)
+	);
+
+	view! {
+		<li class="download_job">
+			<p class="download_url">{job.title.clone().unwrap_or(job.url.clone())}</p>
+			<Show when=move || percent().is_some()>
+				<progress max="100" value=move || percent().unwrap_or_default() />
+			</Show>
+			<p class="download_status">
+				{move || progress.with(|map| map
+					.get(&job_id)
+					.map(format_progress)
+					.unwrap_or(fallback.clone())
+				)}
+				<Show when=move || { retry_count > 0 }>
+					" (retries: "{retry_count}")"
+				</Show>
+			</p>
+			<Show when=move || can_cancel>
+				<button
+					on:click=move |_| {
+						spawn_local(async move {
+							if let Err(err) = cancel_download(job_id).await {
+								log::debug!("unable to cancel download: {err:?}");
+							}
+							download_review.invalidate();
+						});
+					}
+				>
+					cancel
+				</button>
+			</Show>
+			<Show when=move || can_retry>
+				<button
+					on:click=move |_| {
+						spawn_local(async move {
+							if let Err(err) = retry_download(job_id).await {
+								log::debug!("unable to retry download: {err:?}");
+							}
+							download_review.invalidate();
+						});
+					}
+				>
+					retry
+				</button>
+			</Show>
+		</li>
+	}
+}
+
+/// active and recent downloads of the current user, updated live over the websocket
+#[component]
+pub fn downloads_panel(
+	/// called whenever a download finished successfully
+	#[prop(into)]
+	on_completed: Callback<()>,
+) -> impl IntoView {
+	let download_review = ReviewEvent::<{DOWNLOAD_IDS}>::use_provided();
+	let login_ctx = use_context::<LoginContext>().expect("login context missing");
+	let progress = RwSignal::new(HashMap::<uuid::Uuid, PubDownloadProgress>::new());
+
+	let jobs_res = Resource::new(download_review.subscribe(), async |_| {
+		get_download_jobs(Some(consts::RECENT_JOB_LIMIT))
+			.await
+			.unwrap_or_default()
+	});
+
+	let UseWebSocketReturn {
+		message,
+		ready_state,
+		send,
+		..
+	} = use_websocket_with_options::<ThrwSocketMessage, ThrwSocketMessage, JsonSerdeCodec, _, _>(
+		consts::SOCKET_URL,
+		UseWebSocketOptions::default()
+			.on_error(|err| {
+				log::debug!("download socket err: {err}");
+			})
+	);
+
+	// the server only relays messages after an introduction
+	Effect::new(move |_| {
+		if !matches!(ready_state.get(), ConnectionReadyState::Open) {
+			return;
+		}
+		if let LoginState::LoggedIn(id, _, _) = login_ctx.login_state.get_untracked() {
+			send(&ThrwSocketMessage::Intoduce(id));
+		}
+	});
+
+	Effect::new(move |_| {
+		let Some(ThrwSocketMessage::DownloadProgress(update)) = message.get() else {
+			return;
+		};
+
+		if !update.phase.is_finished() {
+			progress.update(|map| {
+				map.insert(update.job_id, update);
+			});
+			return;
+		}
+
+		progress.update(|map| {
+			map.remove(&update.job_id);
+		});
+		download_review.invalidate();
+		if update.phase == PubDownloadPhase::Completed {
+			on_completed.run(());
+		}
+	});
+
+	view! {
+		<div class="downloads_panel">
+			<h3>Downloads</h3>
+			<Transition fallback=move || view! { <p>Loading...</p> }>
+			{move || jobs_res.get().map(|jobs| {
+				if jobs.is_empty() {
+					return view! { <p>No downloads yet</p> }.into_any();
+				}
+				view! {
+					<ul>
+					{jobs.into_iter().map(|job| view! {
+						<DownloadJobRow job progress />
+					}).collect_view()}
+					</ul>
+				}.into_any()
+			})}
+			</Transition>
+		</div>
+	}
+}
@@ -1 +1,4 @@
-pub mod navbar;
\ No newline at end of file
+pub mod navbar;
+pub mod player;
+pub mod downloads;
+pub mod subscriptions;
@@ -16,7 +16,10 @@ pub fn Header() -> impl IntoView {
 			<div class="links_r">
 				<Show when=move || !matches!(check_login(None), Some(true)) fallback=move||view! {
 					<A href="/vfs/root">VFS</A>
-					<A href="/admin">Admin</A>
+					<A href="/history">History</A>
+					<Show when=move || matches!(check_admin(), Some(true))>
+						<A href="/admin">Admin</A>
+					</Show>
 					<A href="/account">Account</A>
 					<button
 						on:click= move |_| {
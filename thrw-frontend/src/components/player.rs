@@ -0,0 +1,346 @@
+use thrw_shared::{downloader::{api::get_source_info, shared::PubSourceInfo}, media::{api::get_media_analysis, shared::PubMediaAnalysis}, playback::api::update_playback, vfs::{api::get_media_extras, shared::{PubVfsChapter, PubVfsNode}}};
+
+use crate::prelude::*;
+
+pub(self) mod consts {
+	/// seconds of playback between periodic saves
+	pub const SAVE_INTERVAL: f64 = 10.0;
+	/// height of the waveform view box, peaks are scaled to it
+	pub const WAVEFORM_HEIGHT: f64 = 100.0;
+}
+
+/// playback volume for a replay gain in dB; boosting is not possible with the volume alone
+fn volume_for_gain(gain: Option<f64>) -> f64 {
+	gain
+		.map(|gain| 10f64.powf(gain / 20.0))
+		.unwrap_or(1.0)
+		.clamp(0.0, 1.0)
+}
+
+/// the media node currently opened in the player
+#[derive(Debug, Clone, Copy)]
+pub struct ActiveMedia(pub RwSignal<Option<PubVfsNode>>);
+impl ActiveMedia {
+	pub fn provide_new() {
+		provide_context(Self(RwSignal::new(None)));
+	}
+
+	pub fn use_provided() -> Option<Self> {
+		use_context::<Self>()
+	}
+
+	pub fn open(&self, node: PubVfsNode) {
+		self.0.set(Some(node));
+	}
+
+	pub fn close(&self) {
+		self.0.set(None);
+	}
+}
+
+fn save_playback(node: uuid::Uuid, position: f64, duration: Option<f64>, completed: bool, started: bool) {
+	spawn_local(async move {
+		if let Err(err) = update_playback(node, position, duration, completed, started).await {
+			log::debug!("error saving playback state: {err:?}");
+		}
+	});
+}
+
+#[component]
+pub fn media_player(
+	node: PubVfsNode,
+) -> impl IntoView {
+	let media_ref = NodeRef::<leptos::html::Video>::new();
+	let node_id = node.id;
+	let last_saved = StoredValue::new(0.0_f64);
+	let started = StoredValue::new(false);
+	let resume_at = node.playback
+		.as_ref()
+		.filter(|state| !state.completed)
+		.map(|state| state.position)
+		.unwrap_or(0.0)
+	;
+
+	let extras_res = Resource::new(move || node_id, async |node| {
+		get_media_extras(node)
+			.await
+			.unwrap_or_default()
+	});
+
+	let analysis_res = Resource::new(move || node_id, async |node| {
+		get_media_analysis(node)
+			.await
+			.ok()
+			.flatten()
+	});
+
+	let source_res = Resource::new(move || node_id, async |node| {
+		get_source_info(node)
+			.await
+			.ok()
+			.flatten()
+	});
+	let current_time = RwSignal::new(0.0_f64);
+	let duration = RwSignal::new(node.duration.unwrap_or(0.0));
+	let normalize = RwSignal::new(true);
+
+	// apply the normalization whenever the analysis arrives or it is toggled
+	Effect::new(move |_| {
+		let gain = analysis_res.get()
+			.flatten()
+			.and_then(|analysis| analysis.replay_gain)
+		;
+		let volume = match normalize.get() {
+			true => volume_for_gain(gain),
+			false => 1.0,
+		};
+		if let Some(el) = media_ref.get() {
+			el.set_volume(volume);
+		}
+	});
+
+	let get_position = move || {
+		media_ref.get_untracked().map(|el| {
+			let duration = el.duration();
+			(el.current_time(), duration.is_finite().then_some(duration))
+		})
+	};
+
+	view! {
+		<div class="media_player">
+			<p>{node.name.clone()}</p>
+			<video
+				node_ref=media_ref
+				src=node.file_path.clone().unwrap_or_default()
+				poster=node.thumbnail.clone().unwrap_or_default()
+				controls=true
+				autoplay=true
+				on:loadedmetadata=move |_| {
+					let Some(el) = media_ref.get_untracked() else {
+						return;
+					};
+					if el.duration().is_finite() {
+						duration.set(el.duration());
+					}
+					if resume_at > 0.0 {
+						el.set_current_time(resume_at);
+						last_saved.set_value(resume_at);
+					}
+				}
+				on:play=move |_| {
+					if started.get_value() {
+						return;
+					}
+					started.set_value(true);
+					if let Some((position, duration)) = get_position() {
+						save_playback(node_id, position, duration, false, true);
+					}
+				}
+				on:timeupdate=move |_| {
+					let Some((position, duration)) = get_position() else {
+						return;
+					};
+					current_time.set(position);
+					if (position - last_saved.get_value()).abs() < consts::SAVE_INTERVAL {
+						return;
+					}
+					last_saved.set_value(position);
+					save_playback(node_id, position, duration, false, false);
+				}
+				on:pause=move |_| {
+					if let Some((position, duration)) = get_position() {
+						last_saved.set_value(position);
+						save_playback(node_id, position, duration, false, false);
+					}
+				}
+				on:ended=move |_| {
+					if let Some((position, duration)) = get_position() {
+						save_playback(node_id, position, duration, true, false);
+					}
+					// replaying counts as a new play
+					started.set_value(false);
+				}
+			>
+				<Transition fallback=move || view! {}>
+				{move || extras_res.get().map(|extras| {
+					extras.tracks.into_iter().map(|track| view! {
+						<track
+							kind=track.kind.to_string()
+							src=track.src
+							srclang=track.lang.unwrap_or_default()
+							label=track.label
+						/>
+					}).collect_view()
+				})}
+				</Transition>
+			</video>
+			<Transition fallback=move || view! {}>
+			{move || analysis_res.get().flatten().map(|analysis| view! {
+				<Waveform analysis node_id current_time duration media_ref />
+			})}
+			</Transition>
+			<label>
+				<input type="checkbox" bind:checked=normalize />
+				"normalize volume"
+			</label>
+			<Transition fallback=move || view! {}>
+			{move || extras_res.get().map(|extras| view! {
+				<ChapterList chapters=extras.chapters media_ref />
+			})}
+			</Transition>
+			<Transition fallback=move || view! {}>
+			{move || source_res.get().flatten().map(|source| view! {
+				<SourceInfo source />
+			})}
+			</Transition>
+		</div>
+	}
+}
+
+/// waveform of the media that doubles as a seek bar
+#[component]
+fn waveform(
+	analysis: PubMediaAnalysis,
+	node_id: uuid::Uuid,
+	current_time: RwSignal<f64>,
+	duration: RwSignal<f64>,
+	media_ref: NodeRef<leptos::html::Video>,
+) -> impl IntoView {
+	let container_ref = NodeRef::<leptos::html::Div>::new();
+	let peak_count = analysis.peaks.len().max(1) as f64;
+	let mid = consts::WAVEFORM_HEIGHT / 2.0;
+	let wave_path: String = analysis.peaks
+		.iter()
+		.enumerate()
+		.map(|(index, peak)| {
+			let half = (*peak as f64 / 255.0 * mid).max(0.5);
+			format!("M{index} {:.1}V{:.1}", mid - half, mid + half)
+		})
+		.collect()
+	;
+	let clip_id = format!("waveform_clip_{node_id}");
+	let clip_url = format!("url(#{clip_id})");
+	let progress_width = move || {
+		let duration = duration.get();
+		match duration > 0.0 {
+			true => current_time.get() / duration * peak_count,
+			false => 0.0,
+		}
+	};
+
+	view! {
+		<div
+			class="waveform"
+			node_ref=container_ref
+			on:click=move |ev| {
+				let (Some(container), Some(el)) = (container_ref.get_untracked(), media_ref.get_untracked()) else {
+					return;
+				};
+				let width = container.client_width() as f64;
+				let duration = duration.get_untracked();
+				if width > 0.0 && duration > 0.0 {
+					el.set_current_time(ev.offset_x() as f64 / width * duration);
+				}
+			}
+		>
+			<svg
+				viewBox=format!("0 0 {peak_count} {}", consts::WAVEFORM_HEIGHT)
+				preserveAspectRatio="none"
+			>
+				<clipPath id=clip_id>
+					<rect x="0" y="0" width=progress_width height=consts::WAVEFORM_HEIGHT />
+				</clipPath>
+				<path class="waveform_base" d=wave_path.clone() />
+				<path class="waveform_played" d=wave_path clip-path=clip_url />
+			</svg>
+		</div>
+	}
+}
+
+#[component]
+fn chapter_list(
+	chapters: Vec<PubVfsChapter>,
+	media_ref: NodeRef<leptos::html::Video>,
+) -> impl IntoView {
+	if chapters.is_empty() {
+		return ().into_any();
+	}
+
+	view! {
+		<ol class="chapter_list">
+		{chapters.into_iter().map(|chapter| {
+			let start = chapter.start;
+			let timestamp = format!("{}:{:02}", (start / 60.0) as i64, (start % 60.0) as i64);
+			view! {
+				<li>
+					<button
+						on:click=move |_| {
+							if let Some(el) = media_ref.get_untracked() {
+								el.set_current_time(start);
+							}
+						}
+					>
+						{timestamp}
+					</button>
+					" "{chapter.title.unwrap_or_default()}
+				</li>
+			}
+		}).collect_view()}
+		</ol>
+	}
+	.into_any()
+}
+
+/// where the media was downloaded from
+#[component]
+fn source_info(
+	source: PubSourceInfo,
+) -> impl IntoView {
+	let mut details = vec![source.extractor.clone()];
+	if let Some(uploader) = &source.uploader {
+		details.push(format!("by {uploader}"));
+	}
+	if let Some(date) = source.upload_date {
+		details.push(format!("uploaded {}", date.format("%Y-%m-%d")));
+	}
+	details.push(format!("downloaded {}", source.downloaded_at.with_timezone(&chrono::Local).format("%Y-%m-%d")));
+
+	view! {
+		<div class="source_info">
+			<p>
+				{match source.webpage_url.clone() {
+					Some(url) => {
+						let href = url.clone();
+						view! { <a href=href target="_blank" rel="noopener noreferrer">{url}</a> }.into_any()
+					},
+					None => view! { <span>{source.source_id.clone()}</span> }.into_any(),
+				}}
+			</p>
+			<p>{details.join(", ")}</p>
+			{source.description.filter(|desc| !desc.trim().is_empty()).map(|desc| view! {
+				<details>
+					<summary>description</summary>
+					<p class="source_description">{desc}</p>
+				</details>
+			})}
+		</div>
+	}
+}
+
+/// player for the [`ActiveMedia`] in context, if one is selected
+#[component]
+pub fn active_player() -> impl IntoView {
+	let Some(active) = ActiveMedia::use_provided() else {
+		return ().into_any();
+	};
+
+	view! {
+		{move || active.0.get().map(|node| view! {
+			<div class="active_player">
+				<button on:click=move |_| active.close()>X</button>
+				<MediaPlayer node />
+			</div>
+		})}
+	}
+	.into_any()
+}
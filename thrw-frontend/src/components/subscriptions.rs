@@ -0,0 +1,192 @@
+use thrw_shared::{downloader::{api::{check_subscription_now, create_subscription, delete_subscription, get_subscriptions, set_subscription_enabled}, shared::{PubPostProcessing, PubSubscription, PubSubscriptionArgs, PubSubscriptionFilter}}, vfs::shared::VfsTarget};
+
+use crate::{components::downloads::PostProcessingPicker, prelude::*};
+
+pub mod consts {
+	pub const SUBSCRIPTION_LIST_ID: i32 = crate::prelude::DOWNLOAD_IDS + 1;
+
+	pub const DEFAULT_CHECK_HOURS: &str = "6";
+}
+
+fn format_time(time: chrono::DateTime<chrono::Utc>) -> String {
+	time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string()
+}
+
+#[component]
+fn subscription_row(
+	subscription: PubSubscription,
+) -> impl IntoView {
+	let subscription_review = ReviewEvent::<{consts::SUBSCRIPTION_LIST_ID}>::use_provided();
+	let sub_id = subscription.id;
+	let enabled = subscription.enabled;
+
+	let mut details = vec![format!("every {}h", subscription.check_interval / 60 / 60)];
+	if subscription.audio_only {
+		details.push("audio only".to_string());
+	}
+	if let Some(max) = subscription.filter.max_duration {
+		details.push(format!("up to {} min", max / 60));
+	}
+	if let Some(regex) = &subscription.filter.title_regex {
+		details.push(format!("titles matching '{regex}'"));
+	}
+	if !subscription.post_processing.steps.is_empty() {
+		let steps: Vec<String> = subscription.post_processing.steps.iter().map(ToString::to_string).collect();
+		details.push(format!("then {}", steps.join(", ")));
+	}
+	if let Some(target) = &subscription.target {
+		details.push(format!("into {target}"));
+	}
+	let status = match (&subscription.last_error, subscription.last_checked_at) {
+		(Some(err), _) => format!("check failed: {err}"),
+		(None, Some(at)) => format!("checked {}, next {}", format_time(at), format_time(subscription.next_check_at)),
+		(None, None) => "not checked yet".to_string(),
+	};
+
+	view! {
+		<li class="subscription">
+			<p class="download_url">{subscription.title.clone().unwrap_or(subscription.url.clone())}</p>
+			<p class="download_status">{details.join(", ")}</p>
+			<p class="download_status">{status}</p>
+			<button
+				on:click=move |_| {
+					spawn_local(async move {
+						if let Err(err) = set_subscription_enabled(sub_id, !enabled).await {
+							log::debug!("unable to toggle subscription: {err:?}");
+						}
+						subscription_review.invalidate();
+					});
+				}
+			>
+				{if enabled { "pause" } else { "resume" }}
+			</button>
+			<Show when=move || enabled>
+				<button
+					on:click=move |_| {
+						spawn_local(async move {
+							if let Err(err) = check_subscription_now(sub_id).await {
+								log::debug!("unable to check subscription: {err:?}");
+							}
+							subscription_review.invalidate();
+						});
+					}
+				>
+					check now
+				</button>
+			</Show>
+			<button
+				on:click=move |_| {
+					spawn_local(async move {
+						if let Err(err) = delete_subscription(sub_id).await {
+							log::debug!("unable to delete subscription: {err:?}");
+						}
+						subscription_review.invalidate();
+					});
+				}
+			>
+				unsubscribe
+			</button>
+		</li>
+	}
+}
+
+/// channels and playlists whose new entries are downloaded automatically
+#[component]
+pub fn subscriptions_panel(
+	/// the folder new subscriptions download into
+	#[prop(into)]
+	target: Signal<VfsTarget>,
+) -> impl IntoView {
+	let subscription_review = ReviewEvent::<{consts::SUBSCRIPTION_LIST_ID}>::use_provided();
+	let url = RwSignal::new("".to_string());
+	let audio_only = RwSignal::new(true);
+	let max_minutes = RwSignal::new("".to_string());
+	let title_regex = RwSignal::new("".to_string());
+	let check_hours = RwSignal::new(consts::DEFAULT_CHECK_HOURS.to_string());
+	let post_processing = RwSignal::new(PubPostProcessing::default());
+	let error = RwSignal::new(None::<String>);
+
+	let subscriptions_res = Resource::new(subscription_review.subscribe(), async |_| {
+		get_subscriptions()
+			.await
+			.unwrap_or_default()
+	});
+
+	let subscribe = move |_| {
+		let Ok(hours) = check_hours.get_untracked().trim().parse::<f64>() else {
+			error.set(Some("invalid check interval".to_string()));
+			return;
+		};
+		let max_duration = match max_minutes.get_untracked().trim() {
+			"" => None,
+			minutes => match minutes.parse::<i32>() {
+				Ok(minutes) => Some(minutes * 60),
+				Err(_) => {
+					error.set(Some("invalid max duration".to_string()));
+					return;
+				},
+			},
+		};
+		let args = PubSubscriptionArgs {
+			url: url.get_untracked(),
+			audio_only: audio_only.get_untracked(),
+			filter: PubSubscriptionFilter {
+				max_duration,
+				title_regex: Some(title_regex.get_untracked()).filter(|regex| !regex.trim().is_empty()),
+			},
+			check_interval: (hours * 60.0 * 60.0) as i32,
+			post_processing: post_processing.get_untracked(),
+		};
+
+		spawn_local(async move {
+			match create_subscription(args, Some(target.get_untracked())).await {
+				Ok(sub_id) => {
+					log::debug!("subscribed as {sub_id}");
+					error.set(None);
+					url.set("".to_string());
+					subscription_review.invalidate();
+				},
+				Err(err) => error.set(Some(err.to_string())),
+			}
+		});
+	};
+
+	view! {
+		<div class="downloads_panel">
+			<h3>Subscriptions</h3>
+			<div>
+				<input bind:value=url placeholder="channel or playlist url" />
+				<label>
+					<input bind:checked=audio_only type="checkbox" />
+					"audio only"
+				</label>
+				<input bind:value=max_minutes type="number" min="1" placeholder="max minutes" />
+				<input bind:value=title_regex placeholder="title regex" />
+				<label>
+					"every "
+					<input bind:value=check_hours type="number" min="0.25" step="0.25" />
+					" hours"
+				</label>
+				<PostProcessingPicker post_processing />
+				<button on:click=subscribe>subscribe here</button>
+				<Show when=move || error.get().is_some()>
+					<p class="download_status">{move || error.get()}</p>
+				</Show>
+			</div>
+			<Transition fallback=move || view! { <p>Loading...</p> }>
+			{move || subscriptions_res.get().map(|subscriptions| {
+				if subscriptions.is_empty() {
+					return view! { <p>No subscriptions yet</p> }.into_any();
+				}
+				view! {
+					<ul>
+					{subscriptions.into_iter().map(|subscription| view! {
+						<SubscriptionRow subscription />
+					}).collect_view()}
+					</ul>
+				}.into_any()
+			})}
+			</Transition>
+		</div>
+	}
+}
@@ -0,0 +1,121 @@
+use thrw_shared::user::api::{create_api_token, get_api_tokens, revoke_api_token, ApiScope, ApiToken};
+
+use crate::{prelude::*, routes::account::{consts::TOKEN_LIST_ID, sessions::format_time}};
+
+#[component]
+fn token_row(
+	token: ApiToken,
+) -> impl IntoView {
+	let token_review = ReviewEvent::<{TOKEN_LIST_ID}>::use_provided();
+	let token_id = token.id;
+	let scopes: Vec<String> = token.scopes.iter().map(ToString::to_string).collect();
+	let description = format!(
+		"{} ({}), expires {}, last used {}",
+		token.name,
+		scopes.join(", "),
+		token.expires_at.map(format_time).unwrap_or("never".to_string()),
+		token.last_used_at.map(format_time).unwrap_or("never".to_string()),
+	);
+
+	view! {
+		<li>
+			{description}
+			<button
+				on:click=move |_| {
+					spawn_local(async move {
+						if let Err(err) = revoke_api_token(token_id).await {
+							log::debug!("unable to revoke api token: {err:?}");
+						}
+						token_review.invalidate();
+					});
+				}
+			>
+				revoke
+			</button>
+		</li>
+	}
+}
+
+/// tokens for scripts, sent as `Authorization: Bearer`
+#[component]
+pub fn api_tokens() -> impl IntoView {
+	let token_review = ReviewEvent::<{TOKEN_LIST_ID}>::use_provided();
+	let name = RwSignal::new("".to_string());
+	let expires_days = RwSignal::new("".to_string());
+	let scopes = RwSignal::new(vec![ApiScope::Downloads]);
+	let secret = RwSignal::new(None::<String>);
+	let error = RwSignal::new(None::<String>);
+
+	let tokens_res = Resource::new(token_review.subscribe(), async |_| {
+		get_api_tokens()
+			.await
+			.unwrap_or_default()
+	});
+
+	let create = move |_| {
+		let expires_in_days = match expires_days.get_untracked().trim() {
+			"" => None,
+			days => match days.parse::<i64>() {
+				Ok(days) => Some(days),
+				Err(_) => {
+					error.set(Some("invalid expiry".to_string()));
+					return;
+				},
+			},
+		};
+		let (name_val, scopes_val) = (name.get_untracked(), scopes.get_untracked());
+		spawn_local(async move {
+			match create_api_token(name_val, scopes_val, expires_in_days).await {
+				Ok(new_token) => {
+					error.set(None);
+					name.set("".to_string());
+					secret.set(Some(new_token.secret));
+					token_review.invalidate();
+				},
+				Err(err) => error.set(Some(err.to_string())),
+			}
+		});
+	};
+
+	view! {
+		<h3>API tokens</h3>
+		<div>
+			<input bind:value=name placeholder="token name" />
+			{ApiScope::ALL.into_iter().map(|scope| view! {
+				<label>
+					<input
+						type="checkbox"
+						prop:checked=move || scopes.get().contains(&scope)
+						on:change=move |ev| {
+							let checked = event_target_checked(&ev);
+							scopes.update(|scopes| {
+								scopes.retain(|other| *other != scope);
+								if checked {
+									scopes.push(scope);
+								}
+							});
+						}
+					/>
+					{scope.to_string()}
+				</label>
+			}).collect_view()}
+			<input bind:value=expires_days type="number" min="1" placeholder="expires after days, empty for never" />
+			<button on:click=create>create token</button>
+		</div>
+		<Show when=move || secret.get().is_some()>
+			<p>"Copy the token now, it is not shown again: "<code>{move || secret.get()}</code></p>
+		</Show>
+		<Show when=move || error.get().is_some()>
+			<p>{move || error.get()}</p>
+		</Show>
+		<Transition fallback=move || view! { <p>Loading...</p> }>
+		{move || tokens_res.get().map(|tokens| view! {
+			<ul>
+			{tokens.into_iter().map(|token| view! {
+				<TokenRow token />
+			}).collect_view()}
+			</ul>
+		})}
+		</Transition>
+	}
+}
@@ -0,0 +1,110 @@
+use thrw_shared::downloader::{api::{delete_download_credential, get_download_credentials, set_download_cookies, set_download_login}, shared::PubDownloadCredential};
+
+use crate::{prelude::*, routes::account::consts::CREDENTIAL_LIST_ID};
+
+#[component]
+fn credential_row(
+	credential: PubDownloadCredential,
+) -> impl IntoView {
+	let credential_review = ReviewEvent::<{CREDENTIAL_LIST_ID}>::use_provided();
+	let credential_id = credential.id;
+	let description = match &credential.username {
+		Some(username) => format!("{}: {} as {username}", credential.site, credential.kind.to_string()),
+		None => format!("{}: {}", credential.site, credential.kind.to_string()),
+	};
+
+	view! {
+		<li>
+			{description}
+			<button
+				on:click=move |_| {
+					spawn_local(async move {
+						if let Err(err) = delete_download_credential(credential_id).await {
+							log::debug!("unable to delete credential: {err:?}");
+						}
+						credential_review.invalidate();
+					});
+				}
+			>
+				X
+			</button>
+		</li>
+	}
+}
+
+/// cookies and logins the downloads of the user authenticate with, the secrets never come back from the server
+#[component]
+pub fn site_logins() -> impl IntoView {
+	let credential_review = ReviewEvent::<{CREDENTIAL_LIST_ID}>::use_provided();
+	let site = RwSignal::new("".to_string());
+	let cookies = RwSignal::new("".to_string());
+	let username = RwSignal::new("".to_string());
+	let password = RwSignal::new("".to_string());
+	let error = RwSignal::new(None::<String>);
+
+	let credentials_res = Resource::new(credential_review.subscribe(), async |_| {
+		get_download_credentials()
+			.await
+			.unwrap_or_default()
+	});
+
+	let save_cookies = move |_| {
+		let (site_val, cookies_val) = (site.get_untracked(), cookies.get_untracked());
+		spawn_local(async move {
+			match set_download_cookies(site_val, cookies_val).await {
+				Ok(_) => {
+					error.set(None);
+					cookies.set("".to_string());
+					credential_review.invalidate();
+				},
+				Err(err) => error.set(Some(err.to_string())),
+			}
+		});
+	};
+	let save_login = move |_| {
+		let (site_val, username_val, password_val) = (site.get_untracked(), username.get_untracked(), password.get_untracked());
+		spawn_local(async move {
+			match set_download_login(site_val, username_val, password_val).await {
+				Ok(_) => {
+					error.set(None);
+					password.set("".to_string());
+					credential_review.invalidate();
+				},
+				Err(err) => error.set(Some(err.to_string())),
+			}
+		});
+	};
+
+	view! {
+		<h3>Site logins</h3>
+		<Transition fallback=move || view! { <p>Loading...</p> }>
+		{move || credentials_res.get().map(|credentials| {
+			if credentials.is_empty() {
+				return view! { <p>No site logins yet</p> }.into_any();
+			}
+			view! {
+				<ul>
+				{credentials.into_iter().map(|credential| view! {
+					<CredentialRow credential />
+				}).collect_view()}
+				</ul>
+			}.into_any()
+		})}
+		</Transition>
+		<div>
+			<input bind:value=site placeholder="site, e.g. youtube.com" />
+		</div>
+		<div>
+			<textarea bind:value=cookies placeholder="Netscape cookies file" />
+			<button on:click=save_cookies>save cookies</button>
+		</div>
+		<div>
+			<input bind:value=username placeholder="username" autocomplete="off" />
+			<input bind:value=password type="password" placeholder="password" autocomplete="new-password" />
+			<button on:click=save_login>save login</button>
+		</div>
+		<Show when=move || error.get().is_some()>
+			<p>{move || error.get()}</p>
+		</Show>
+	}
+}
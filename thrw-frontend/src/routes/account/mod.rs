@@ -1,20 +1,78 @@
 
-use crate::{prelude::*};
+use thrw_shared::downloader::api::get_download_quota;
+
+use crate::{components::downloads::format_size, prelude::*, routes::account::{api_tokens::ApiTokens, credentials::SiteLogins, sessions::SessionList, settings::AccountSettings, two_factor::TwoFactor}};
+
+mod api_tokens;
+mod credentials;
+pub(crate) mod sessions;
+mod settings;
+mod two_factor;
 
 pub(self) mod consts {
 	pub const CHAR_LIST_ID: i32 = crate::prelude::ACC_IDS + 1;
+	pub const CREDENTIAL_LIST_ID: i32 = crate::prelude::ACC_IDS + 2;
+	pub const ACCOUNT_INFO_ID: i32 = crate::prelude::ACC_IDS + 3;
+	pub const SESSION_LIST_ID: i32 = crate::prelude::ACC_IDS + 4;
+	pub const TOTP_STATUS_ID: i32 = crate::prelude::ACC_IDS + 5;
+	pub const TOKEN_LIST_ID: i32 = crate::prelude::ACC_IDS + 6;
+}
+
+fn format_limit(used: String, limit: Option<String>) -> String {
+	match limit {
+		Some(limit) => format!("{used} of {limit}"),
+		None => format!("{used}, unlimited"),
+	}
+}
+
+/// the download limits of the user and how much of them is used up
+#[component]
+fn download_quota() -> impl IntoView {
+	let quota_res = Resource::new(|| (), async |_| {
+		get_download_quota()
+			.await
+			.ok()
+	});
+
+	view! {
+		<h3>Downloads</h3>
+		<Transition fallback=move || view! { <p>Loading...</p> }>
+		{move || quota_res.get().flatten().map(|quota| {
+			let limits = quota.limits;
+			let usage = quota.usage;
+			view! {
+				<ul>
+					<li>"Running: "{format_limit(usage.running.to_string(), limits.max_concurrent.map(|max| max.to_string()))}</li>
+					<li>"Requested in the last 24 hours: "{format_limit(usage.daily_jobs.to_string(), limits.max_daily_jobs.map(|max| max.to_string()))}</li>
+					<li>"Stored: "{format_limit(format_size(usage.total_bytes), limits.max_total_bytes.map(format_size))}</li>
+					<li>"Largest file: "{limits.max_file_bytes.map(format_size).unwrap_or("unlimited".to_string())}</li>
+				</ul>
+			}
+		})}
+		</Transition>
+	}
 }
 
 #[component]
 pub fn Account() -> impl IntoView {
 	view! {
-		Accout stuff
+		<AccountSettings />
+		<TwoFactor />
+		<SessionList />
+		<ApiTokens />
+		<DownloadQuota />
+		<SiteLogins />
 	}
 }
 
 #[component(transparent)]
 pub fn AccountRoutes() -> impl MatchNestedRoutes + Clone {
 	ReviewEvent::<{consts::CHAR_LIST_ID}>::provide_new();
+	ReviewEvent::<{consts::CREDENTIAL_LIST_ID}>::provide_new();
+	ReviewEvent::<{consts::ACCOUNT_INFO_ID}>::provide_new();
+	ReviewEvent::<{consts::SESSION_LIST_ID}>::provide_new();
+	ReviewEvent::<{consts::TOTP_STATUS_ID}>::provide_new();
+	ReviewEvent::<{consts::TOKEN_LIST_ID}>::provide_new();
 
 	view! {
 		<ProtectedParentRoute
@@ -0,0 +1,86 @@
+use thrw_shared::{app::state::{client::LoginContext, shared::LoginState}, user::api::{get_sessions, revoke_other_sessions, revoke_session, UserSession}};
+
+use crate::{prelude::*, routes::account::consts::SESSION_LIST_ID};
+
+pub fn format_time(time: chrono::DateTime<chrono::Utc>) -> String {
+	time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string()
+}
+
+/// where and when a session was created, the same for the account and admin lists
+pub fn describe_session(session: &UserSession) -> String {
+	format!(
+		"{} from {}, since {}, expires {}",
+		session.user_agent.as_deref().unwrap_or("unknown client"),
+		session.ip_address.as_deref().unwrap_or("unknown address"),
+		session.created_at.map(format_time).unwrap_or("unknown".to_string()),
+		format_time(session.expires_at),
+	)
+}
+
+#[component]
+fn session_row(
+	session: UserSession,
+) -> impl IntoView {
+	let session_review = ReviewEvent::<{SESSION_LIST_ID}>::use_provided();
+	let login = use_context::<LoginContext>().expect("login context missing").login_state;
+	let session_id = session.id;
+	let current = session.current;
+
+	view! {
+		<li>
+			{describe_session(&session)}
+			<Show when=move || current>
+				" (this session)"
+			</Show>
+			<button
+				on:click=move |_| {
+					spawn_local(async move {
+						match revoke_session(session_id).await {
+							Ok(_) if current => login.set(LoginState::LoggedOut),
+							Ok(_) => session_review.invalidate(),
+							Err(err) => log::debug!("unable to revoke session: {err:?}"),
+						}
+					});
+				}
+			>
+				{if current { "log out" } else { "revoke" }}
+			</button>
+		</li>
+	}
+}
+
+#[component]
+pub fn session_list() -> impl IntoView {
+	let session_review = ReviewEvent::<{SESSION_LIST_ID}>::use_provided();
+	let sessions_res = Resource::new(session_review.subscribe(), async |_| {
+		get_sessions()
+			.await
+			.unwrap_or_default()
+	});
+
+	view! {
+		<h3>Sessions</h3>
+		<button
+			on:click=move |_| {
+				spawn_local(async move {
+					match revoke_other_sessions().await {
+						Ok(count) => log::debug!("revoked {count} sessions"),
+						Err(err) => log::debug!("unable to revoke sessions: {err:?}"),
+					}
+					session_review.invalidate();
+				});
+			}
+		>
+			log out everywhere else
+		</button>
+		<Transition fallback=move || view! { <p>Loading...</p> }>
+		{move || sessions_res.get().map(|sessions| view! {
+			<ul>
+			{sessions.into_iter().map(|session| view! {
+				<SessionRow session />
+			}).collect_view()}
+			</ul>
+		})}
+		</Transition>
+	}
+}
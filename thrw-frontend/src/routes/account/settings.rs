@@ -0,0 +1,185 @@
+use thrw_shared::user::api::{change_email, change_password, get_account_info, get_user_settings, resend_verification_email, set_display_name, set_user_settings, UserSettings};
+
+use crate::{prelude::*, routes::account::consts::ACCOUNT_INFO_ID};
+
+#[component]
+fn account_info() -> impl IntoView {
+	let account_review = ReviewEvent::<{ACCOUNT_INFO_ID}>::use_provided();
+	let account_res = Resource::new(account_review.subscribe(), async |_| {
+		get_account_info()
+			.await
+			.ok()
+	});
+
+	view! {
+		<Transition fallback=move || view! { <p>Loading...</p> }>
+		{move || account_res.get().flatten().map(|account| view! {
+			<ul>
+				<li>
+					"Email: "{account.email}
+					{match account.email_verified {
+						true => view! { " (verified)" }.into_any(),
+						false => view! {
+							" (not verified) "
+							<button
+								on:click=move |_| {
+									spawn_local(async move {
+										if let Err(err) = resend_verification_email().await {
+											log::debug!("resend verification error: {err}");
+										}
+									});
+								}
+							>
+								send verification mail again
+							</button>
+						}.into_any(),
+					}}
+				</li>
+				<li>"Display name: "{account.display_name.unwrap_or("none".to_string())}</li>
+				<li>"Level: "{account.level_name}</li>
+			</ul>
+		})}
+		</Transition>
+	}
+}
+
+#[component]
+fn profile_form() -> impl IntoView {
+	let account_review = ReviewEvent::<{ACCOUNT_INFO_ID}>::use_provided();
+	let display_name = RwSignal::new("".to_string());
+	let email = RwSignal::new("".to_string());
+	let email_pw = RwSignal::new("".to_string());
+	let error = RwSignal::new(None::<String>);
+
+	let save_display_name = move |_| {
+		let name = Some(display_name.get_untracked()).filter(|name| !name.trim().is_empty());
+		spawn_local(async move {
+			match set_display_name(name).await {
+				Ok(_) => {
+					error.set(None);
+					display_name.set("".to_string());
+					account_review.invalidate();
+				},
+				Err(err) => error.set(Some(err.to_string())),
+			}
+		});
+	};
+	let save_email = move |_| {
+		let (email_val, pw) = (email.get_untracked(), email_pw.get_untracked());
+		spawn_local(async move {
+			match change_email(email_val, pw).await {
+				Ok(_) => {
+					error.set(None);
+					email.set("".to_string());
+					email_pw.set("".to_string());
+					account_review.invalidate();
+				},
+				Err(err) => error.set(Some(err.to_string())),
+			}
+		});
+	};
+
+	view! {
+		<div>
+			<input bind:value=display_name placeholder="display name, empty removes it" />
+			<button on:click=save_display_name>save display name</button>
+		</div>
+		<div>
+			<input bind:value=email type="email" placeholder="new email" />
+			<input bind:value=email_pw type="password" placeholder="current password" />
+			<button on:click=save_email>change email</button>
+		</div>
+		<Show when=move || error.get().is_some()>
+			<p>{move || error.get()}</p>
+		</Show>
+	}
+}
+
+#[component]
+fn password_form() -> impl IntoView {
+	let current_pw = RwSignal::new("".to_string());
+	let new_pw = RwSignal::new("".to_string());
+	let repeat_pw = RwSignal::new("".to_string());
+	let status = RwSignal::new(None::<String>);
+
+	let save = move |_| {
+		let (current, new, repeat) = (current_pw.get_untracked(), new_pw.get_untracked(), repeat_pw.get_untracked());
+		if new != repeat {
+			status.set(Some("the new passwords don't match".to_string()));
+			return;
+		}
+		spawn_local(async move {
+			match change_password(current, new).await {
+				Ok(_) => {
+					status.set(Some("password changed, other sessions were logged out and api tokens revoked".to_string()));
+					current_pw.set("".to_string());
+					new_pw.set("".to_string());
+					repeat_pw.set("".to_string());
+				},
+				Err(err) => status.set(Some(err.to_string())),
+			}
+		});
+	};
+
+	view! {
+		<h3>Password</h3>
+		<div>
+			<input bind:value=current_pw type="password" placeholder="current password" autocomplete="current-password" />
+			<input bind:value=new_pw type="password" placeholder="new password" autocomplete="new-password" />
+			<input bind:value=repeat_pw type="password" placeholder="repeat new password" autocomplete="new-password" />
+			<button on:click=save>change password</button>
+		</div>
+		<Show when=move || status.get().is_some()>
+			<p>{move || status.get()}</p>
+		</Show>
+	}
+}
+
+#[component]
+fn settings_form() -> impl IntoView {
+	let strict_ip = RwSignal::new(false);
+	let settings_res = Resource::new(|| (), async |_| {
+		get_user_settings()
+			.await
+			.ok()
+	});
+	Effect::new(move |_| {
+		if let Some(Some(settings)) = settings_res.get() {
+			strict_ip.set(settings.strict_ip);
+		}
+	});
+
+	let save = move |_| {
+		let settings = UserSettings {
+			strict_ip: strict_ip.get_untracked(),
+		};
+		spawn_local(async move {
+			if let Err(err) = set_user_settings(settings).await {
+				log::debug!("unable to save settings: {err:?}");
+			}
+			settings_res.refetch();
+		});
+	};
+
+	view! {
+		<h3>Settings</h3>
+		<div>
+			<label>
+				<input bind:checked=strict_ip type="checkbox" />
+				"only allow sessions from the address they were logged in from"
+			</label>
+			<button on:click=save>save settings</button>
+		</div>
+	}
+}
+
+#[component]
+pub fn account_settings() -> impl IntoView {
+	view! {
+		<h3>Account</h3>
+		<AccountInfo />
+		<ProfileForm />
+		<PasswordForm />
+		<SettingsForm />
+	}
+}
@@ -0,0 +1,171 @@
+use qrcode::{render::svg, QrCode};
+use thrw_shared::user::api::{begin_totp_enrollment, confirm_totp_enrollment, disable_totp, get_totp_status, regenerate_recovery_codes, TotpEnrollment};
+
+use crate::{prelude::*, routes::account::consts::TOTP_STATUS_ID};
+
+/// the otpauth uri as an svg, so authenticators can scan it
+fn qr_svg(uri: &str) -> String {
+	QrCode::new(uri.as_bytes())
+		.map(|code| code
+			.render::<svg::Color>()
+			.min_dimensions(200, 200)
+			.build()
+		)
+		.unwrap_or_default()
+}
+
+/// shown once after they were generated
+#[component]
+fn recovery_codes(
+	codes: RwSignal<Vec<String>>,
+) -> impl IntoView {
+	view! {
+		<Show when=move || !codes.get().is_empty()>
+			<p>"Recovery codes, each works once in place of a code. They are not shown again:"</p>
+			<ul>
+			{move || codes.get().into_iter().map(|code| view! { <li><code>{code}</code></li> }).collect_view()}
+			</ul>
+		</Show>
+	}
+}
+
+#[component]
+fn enrollment_form(
+	codes: RwSignal<Vec<String>>,
+) -> impl IntoView {
+	let totp_review = ReviewEvent::<{TOTP_STATUS_ID}>::use_provided();
+	let pw = RwSignal::new("".to_string());
+	let code = RwSignal::new("".to_string());
+	let enrollment = RwSignal::new(None::<TotpEnrollment>);
+	let error = RwSignal::new(None::<String>);
+
+	let begin = move |_| {
+		let pw_val = pw.get_untracked();
+		spawn_local(async move {
+			match begin_totp_enrollment(pw_val).await {
+				Ok(new_enrollment) => {
+					error.set(None);
+					pw.set("".to_string());
+					enrollment.set(Some(new_enrollment));
+				},
+				Err(err) => error.set(Some(err.to_string())),
+			}
+		});
+	};
+	let confirm = move |_| {
+		let code_val = code.get_untracked();
+		spawn_local(async move {
+			match confirm_totp_enrollment(code_val).await {
+				Ok(new_codes) => {
+					error.set(None);
+					enrollment.set(None);
+					codes.set(new_codes);
+					totp_review.invalidate();
+				},
+				Err(err) => error.set(Some(err.to_string())),
+			}
+			code.set("".to_string());
+		});
+	};
+
+	view! {
+		<Show
+			when=move || enrollment.get().is_some()
+			fallback=move || view! {
+				<div>
+					<input bind:value=pw type="password" placeholder="current password" autocomplete="current-password" />
+					<button on:click=begin>set up two-factor authentication</button>
+				</div>
+			}
+		>
+			{move || enrollment.get().map(|enrollment| view! {
+				<p>"Scan the code with your authenticator or enter the secret by hand:"</p>
+				<div class="totp_qr" inner_html=qr_svg(&enrollment.otpauth_uri) />
+				<p><code>{enrollment.secret}</code></p>
+				<p><a href=enrollment.otpauth_uri>open in authenticator</a></p>
+			})}
+			<div>
+				<input bind:value=code placeholder="code from the authenticator" autocomplete="one-time-code" />
+				<button on:click=confirm>confirm</button>
+			</div>
+		</Show>
+		<Show when=move || error.get().is_some()>
+			<p>{move || error.get()}</p>
+		</Show>
+	}
+}
+
+#[component]
+fn enabled_form(
+	codes: RwSignal<Vec<String>>,
+	recovery_codes_left: i64,
+) -> impl IntoView {
+	let totp_review = ReviewEvent::<{TOTP_STATUS_ID}>::use_provided();
+	let pw = RwSignal::new("".to_string());
+	let code = RwSignal::new("".to_string());
+	let error = RwSignal::new(None::<String>);
+
+	let regenerate = move |_| {
+		let (pw_val, code_val) = (pw.get_untracked(), code.get_untracked());
+		spawn_local(async move {
+			match regenerate_recovery_codes(pw_val, code_val).await {
+				Ok(new_codes) => {
+					error.set(None);
+					codes.set(new_codes);
+					totp_review.invalidate();
+				},
+				Err(err) => error.set(Some(err.to_string())),
+			}
+			code.set("".to_string());
+		});
+	};
+	let disable = move |_| {
+		let (pw_val, code_val) = (pw.get_untracked(), code.get_untracked());
+		spawn_local(async move {
+			match disable_totp(pw_val, code_val).await {
+				Ok(_) => {
+					error.set(None);
+					codes.set(vec![]);
+					totp_review.invalidate();
+				},
+				Err(err) => error.set(Some(err.to_string())),
+			}
+			code.set("".to_string());
+		});
+	};
+
+	view! {
+		<p>{format!("Two-factor authentication is on, {recovery_codes_left} recovery codes left")}</p>
+		<div>
+			<input bind:value=pw type="password" placeholder="current password" autocomplete="current-password" />
+			<input bind:value=code placeholder="code or recovery code" autocomplete="one-time-code" />
+			<button on:click=regenerate>new recovery codes</button>
+			<button on:click=disable>turn off</button>
+		</div>
+		<Show when=move || error.get().is_some()>
+			<p>{move || error.get()}</p>
+		</Show>
+	}
+}
+
+#[component]
+pub fn two_factor() -> impl IntoView {
+	let totp_review = ReviewEvent::<{TOTP_STATUS_ID}>::use_provided();
+	let codes = RwSignal::new(Vec::<String>::new());
+	let status_res = Resource::new(totp_review.subscribe(), async |_| {
+		get_totp_status()
+			.await
+			.ok()
+	});
+
+	view! {
+		<h3>Two-factor authentication</h3>
+		<Transition fallback=move || view! { <p>Loading...</p> }>
+		{move || status_res.get().flatten().map(|status| match status.enabled {
+			true => view! { <EnabledForm codes recovery_codes_left=status.recovery_codes_left /> }.into_any(),
+			false => view! { <EnrollmentForm codes /> }.into_any(),
+		})}
+		</Transition>
+		<RecoveryCodes codes />
+	}
+}
@@ -0,0 +1,89 @@
+use thrw_shared::media::{api::{get_media_binaries, update_media_binary}, shared::PubBinaryStatus};
+
+use crate::{prelude::*, routes::admin::consts::BINARY_LIST_ID};
+
+#[component]
+fn binary_row(
+	status: PubBinaryStatus,
+) -> impl IntoView {
+	let binary_list_ev = ReviewEvent::<{BINARY_LIST_ID}>::use_provided();
+	let updating = RwSignal::new(false);
+	let update_error = RwSignal::new(None::<String>);
+	let kind = status.kind.clone();
+	let managed = status.managed;
+
+	view! {
+		<tr>
+			<td>{format!("{:?}", status.kind)}</td>
+			<td>{status.version.clone().unwrap_or("unavailable".to_string())}</td>
+			<td>{status.path.clone()}</td>
+			<td>{if managed { "managed" } else { "system" }}</td>
+			<td>{status.error.clone().unwrap_or_default()}{move || update_error.get()}</td>
+			<td>
+				<Show when=move || managed>
+					<button
+						disabled=move || updating.get()
+						on:click={
+							let kind = kind.clone();
+							move |_| {
+								let kind = kind.clone();
+								updating.set(true);
+								spawn_local(async move {
+									match update_media_binary(kind).await {
+										Ok(_) => update_error.set(None),
+										Err(err) => update_error.set(Some(err.to_string())),
+									}
+									updating.set(false);
+									binary_list_ev.invalidate();
+								});
+							}
+						}
+					>
+						{move || if updating.get() { "Updating..." } else { "Update" }}
+					</button>
+				</Show>
+			</td>
+		</tr>
+	}
+}
+
+#[component]
+pub fn BinaryManager() -> impl IntoView {
+	let binary_list_ev = ReviewEvent::<{BINARY_LIST_ID}>::use_provided();
+	let recheck = RwSignal::new(false);
+	let binary_res = Resource::new(
+		move || (binary_list_ev.subscribe()(), recheck.get()),
+		async |(_, recheck)| {
+			get_media_binaries(recheck)
+				.await
+				.unwrap_or(vec![])
+		}
+	);
+
+	view! {
+		<Transition fallback=move || view! { <p>Loading...</p>}>
+		{move || binary_res.get().map(|binaries| view! {
+			<table>
+				<tr>
+					<th>Binary</th>
+					<th>Version</th>
+					<th>Path</th>
+					<th>Source</th>
+					<th>Error</th>
+					<th></th>
+				</tr>
+				{binaries.into_iter().map(|status| view! { <BinaryRow status /> }).collect_view()}
+			</table>
+		})}
+		</Transition>
+
+		<button
+			on:click=move |_| {
+				recheck.set(true);
+				binary_list_ev.invalidate();
+			}
+		>
+			Check again
+		</button>
+	}
+}
@@ -0,0 +1,53 @@
+use thrw_shared::user::api::{get_auth_events, AuthEvent};
+
+use crate::{prelude::*, routes::account::sessions::format_time};
+
+fn describe_event(event: &AuthEvent) -> String {
+	let mut parts = vec![
+		format_time(event.created_at),
+		event.event.to_string(),
+	];
+	if let Some(email) = &event.email {
+		parts.push(email.clone());
+	}
+	if let Some(user_id) = event.user_id {
+		parts.push(format!("user {user_id}"));
+	}
+	if let Some(ip) = &event.ip_address {
+		parts.push(format!("from {ip}"));
+	}
+	if let Some(detail) = &event.detail {
+		parts.push(detail.clone());
+	}
+	parts.join(", ")
+}
+
+/// logins, lockouts and registrations, newest first
+#[component]
+pub fn AuthEventLog() -> impl IntoView {
+	// the id the current page starts before, `None` for the newest events
+	let before = RwSignal::new(None::<i64>);
+	let events_res = Resource::new(move || before.get(), async |before| {
+		get_auth_events(before)
+			.await
+			.unwrap_or_default()
+	});
+
+	view! {
+		<h3>Auth events</h3>
+		<button on:click=move |_| before.set(None)>newest</button>
+		<Transition fallback=move || view! { <p>Loading...</p> }>
+		{move || events_res.get().map(|events| {
+			let oldest = events.last().map(|event| event.id);
+			view! {
+				<ul>
+				{events.iter().map(|event| view! { <li>{describe_event(event)}</li> }).collect_view()}
+				</ul>
+				<Show when=move || oldest.is_some()>
+					<button on:click=move |_| before.set(oldest)>older</button>
+				</Show>
+			}
+		})}
+		</Transition>
+	}
+}
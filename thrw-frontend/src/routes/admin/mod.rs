@@ -1,14 +1,51 @@
-use crate::{prelude::*, routes::{admin::{consts::KEY_LIST_ID, keys::KeyManager}, EmptyParent, EmptyView}, util::check_login_raw};
+use thrw_shared::user::api::Permission;
+
+use crate::{prelude::*, routes::{admin::{binaries::BinaryManager, events::AuthEventLog, consts::{BINARY_LIST_ID, KEY_LIST_ID, QUOTA_LIST_ID, ROLE_LIST_ID, SESSION_LIST_ID}, keys::KeyManager, quotas::QuotaManager, roles::RoleManager, sessions::SessionManager, users::UserManager}, EmptyParent}};
 
 mod keys;
+mod binaries;
+mod events;
+mod quotas;
+mod roles;
+mod sessions;
+mod users;
 
 pub(self) mod consts {
 	pub const KEY_LIST_ID: i32 = crate::prelude::ADMIN_IDS + 1;
+	pub const BINARY_LIST_ID: i32 = crate::prelude::ADMIN_IDS + 2;
+	pub const QUOTA_LIST_ID: i32 = crate::prelude::ADMIN_IDS + 3;
+	pub const SESSION_LIST_ID: i32 = crate::prelude::ADMIN_IDS + 4;
+	pub const ROLE_LIST_ID: i32 = crate::prelude::ADMIN_IDS + 5;
+}
+
+#[component]
+fn AdminIndex() -> impl IntoView {
+	view! {
+		<ul>
+			{[
+				(Permission::ManageKeys, "/admin/keys", "Keys"),
+				(Permission::ManageServer, "/admin/binaries", "Media binaries"),
+				(Permission::ManageUsers, "/admin/quotas", "Download quotas"),
+				(Permission::ManageUsers, "/admin/sessions", "Sessions"),
+				(Permission::ManageUsers, "/admin/users", "Users"),
+				(Permission::ManageRoles, "/admin/roles", "Roles"),
+				(Permission::ManageUsers, "/admin/events", "Auth events"),
+			].into_iter().map(|(permission, href, label)| view! {
+				<Show when=move || matches!(check_permission(permission), Some(true))>
+					<li><A href=href>{label}</A></li>
+				</Show>
+			}).collect_view()}
+		</ul>
+	}
 }
 
 #[component(transparent)]
 pub fn AdminRoutes() -> impl MatchNestedRoutes + Clone {
 	ReviewEvent::<{KEY_LIST_ID}>::provide_new();
+	ReviewEvent::<{BINARY_LIST_ID}>::provide_new();
+	ReviewEvent::<{QUOTA_LIST_ID}>::provide_new();
+	ReviewEvent::<{SESSION_LIST_ID}>::provide_new();
+	ReviewEvent::<{ROLE_LIST_ID}>::provide_new();
 
 	view! {
 		<ProtectedParentRoute
@@ -17,8 +54,14 @@ pub fn AdminRoutes() -> impl MatchNestedRoutes + Clone {
 			condition=check_admin
 			redirect_path=||"/"
 		>
-			<Route path=path!("/") view=EmptyView />
+			<Route path=path!("/") view=AdminIndex />
 			<Route path=path!("/keys") view=KeyManager />
+			<Route path=path!("/binaries") view=BinaryManager />
+			<Route path=path!("/quotas") view=QuotaManager />
+			<Route path=path!("/sessions") view=SessionManager />
+			<Route path=path!("/users") view=UserManager />
+			<Route path=path!("/roles") view=RoleManager />
+			<Route path=path!("/events") view=AuthEventLog />
 		</ProtectedParentRoute>
 	}
 	.into_inner()
@@ -0,0 +1,225 @@
+use thrw_shared::downloader::{api::{get_level_quotas, get_user_quotas, set_level_quota, set_user_quota}, shared::{PubDownloadLimits, PubDownloadQuota}};
+
+use crate::{components::downloads::format_size, prelude::*, routes::admin::consts::QUOTA_LIST_ID};
+
+mod consts {
+	pub const MIB: i64 = 1024 * 1024;
+}
+
+fn format_optional<T: ToString>(value: Option<T>) -> String {
+	value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// empty inputs are unset limits
+fn parse_optional<T: std::str::FromStr>(value: &str, what: &str) -> Result<Option<T>, String> {
+	match value.trim() {
+		"" => Ok(None),
+		value => value.parse::<T>()
+			.map(Some)
+			.map_err(|_| format!("invalid {what}")),
+	}
+}
+
+fn parse_limits(
+	max_concurrent: &str,
+	max_daily_jobs: &str,
+	max_total_mib: &str,
+	max_file_mib: &str,
+) -> Result<PubDownloadLimits, String> {
+	Ok(PubDownloadLimits {
+		max_concurrent: parse_optional(max_concurrent, "concurrent downloads")?,
+		max_daily_jobs: parse_optional(max_daily_jobs, "daily downloads")?,
+		max_total_bytes: parse_optional::<i64>(max_total_mib, "storage")?
+			.map(|mib| mib * consts::MIB),
+		max_file_bytes: parse_optional::<i64>(max_file_mib, "file size")?
+			.map(|mib| mib * consts::MIB),
+	})
+}
+
+fn format_usage(quota: &PubDownloadQuota) -> String {
+	let of = |used: String, limit: Option<String>| match limit {
+		Some(limit) => format!("{used} of {limit}"),
+		None => used,
+	};
+	format!(
+		"{} running, {} today, {} stored",
+		of(quota.usage.running.to_string(), quota.limits.max_concurrent.map(|max| max.to_string())),
+		of(quota.usage.daily_jobs.to_string(), quota.limits.max_daily_jobs.map(|max| max.to_string())),
+		of(format_size(quota.usage.total_bytes), quota.limits.max_total_bytes.map(format_size)),
+	)
+}
+
+/// one input per limit, byte limits are entered in MiB
+#[component]
+fn limits_form(
+	limits: PubDownloadLimits,
+	/// what an empty input means
+	empty_label: &'static str,
+	#[prop(into)]
+	on_save: Callback<PubDownloadLimits>,
+) -> impl IntoView {
+	let max_concurrent = RwSignal::new(format_optional(limits.max_concurrent));
+	let max_daily_jobs = RwSignal::new(format_optional(limits.max_daily_jobs));
+	let max_total_mib = RwSignal::new(format_optional(limits.max_total_bytes.map(|bytes| bytes / consts::MIB)));
+	let max_file_mib = RwSignal::new(format_optional(limits.max_file_bytes.map(|bytes| bytes / consts::MIB)));
+	let error = RwSignal::new(None::<String>);
+
+	let save = move |_| {
+		let limits = parse_limits(
+			&max_concurrent.get_untracked(),
+			&max_daily_jobs.get_untracked(),
+			&max_total_mib.get_untracked(),
+			&max_file_mib.get_untracked(),
+		);
+		match limits {
+			Ok(limits) => {
+				error.set(None);
+				on_save.run(limits);
+			},
+			Err(err) => error.set(Some(err)),
+		}
+	};
+
+	view! {
+		<td><input bind:value=max_concurrent type="number" min="1" placeholder=empty_label /></td>
+		<td><input bind:value=max_daily_jobs type="number" min="0" placeholder=empty_label /></td>
+		<td><input bind:value=max_total_mib type="number" min="0" placeholder=empty_label /></td>
+		<td><input bind:value=max_file_mib type="number" min="1" placeholder=empty_label /></td>
+		<td>
+			<button on:click=save>Save</button>
+			{move || error.get()}
+		</td>
+	}
+}
+
+#[component]
+fn limits_header() -> impl IntoView {
+	view! {
+		<th>Concurrent</th>
+		<th>Per day</th>
+		<th>Storage (MiB)</th>
+		<th>File size (MiB)</th>
+		<th></th>
+	}
+}
+
+#[component]
+fn level_quotas() -> impl IntoView {
+	let quota_list_ev = ReviewEvent::<{QUOTA_LIST_ID}>::use_provided();
+	let levels_res = Resource::new(
+		quota_list_ev.subscribe(),
+		async |_| {
+			get_level_quotas()
+				.await
+				.unwrap_or(vec![])
+		}
+	);
+
+	view! {
+		<Transition fallback=move || view! { <p>Loading...</p>}>
+		{move || levels_res.get().map(|levels| view! {
+			<table>
+				<tr>
+					<th>Level</th>
+					<LimitsHeader />
+				</tr>
+				{levels.into_iter().map(|level| {
+					let level_id = level.level_id;
+					view! {
+						<tr>
+							<td>{level.level_name}</td>
+							<LimitsForm
+								limits=level.limits
+								empty_label="unlimited"
+								on_save=Callback::new(move |limits: PubDownloadLimits| {
+									spawn_local(async move {
+										if let Err(err) = set_level_quota(level_id, limits).await {
+											log::debug!("unable to set level quota: {err:?}");
+										}
+										quota_list_ev.invalidate();
+									});
+								})
+							/>
+						</tr>
+					}
+				}).collect_view()}
+			</table>
+		})}
+		</Transition>
+	}
+}
+
+#[component]
+fn user_quotas() -> impl IntoView {
+	let quota_list_ev = ReviewEvent::<{QUOTA_LIST_ID}>::use_provided();
+	let users_res = Resource::new(
+		quota_list_ev.subscribe(),
+		async |_| {
+			get_user_quotas()
+				.await
+				.unwrap_or(vec![])
+		}
+	);
+
+	view! {
+		<Transition fallback=move || view! { <p>Loading...</p>}>
+		{move || users_res.get().map(|users| view! {
+			<table>
+				<tr>
+					<th>User</th>
+					<th>Level</th>
+					<th>Usage</th>
+					<LimitsHeader />
+					<th></th>
+				</tr>
+				{users.into_iter().map(|user| {
+					let user_id = user.user_id;
+					view! {
+						<tr>
+							<td>{user.email}</td>
+							<td>{user.level_name}</td>
+							<td>{format_usage(&user.quota)}</td>
+							<LimitsForm
+								limits=user.overrides
+								empty_label="level"
+								on_save=Callback::new(move |overrides: PubDownloadLimits| {
+									spawn_local(async move {
+										if let Err(err) = set_user_quota(user_id, Some(overrides)).await {
+											log::debug!("unable to set user quota: {err:?}");
+										}
+										quota_list_ev.invalidate();
+									});
+								})
+							/>
+							<td>
+								<button
+									on:click=move |_| {
+										spawn_local(async move {
+											if let Err(err) = set_user_quota(user_id, None).await {
+												log::debug!("unable to reset user quota: {err:?}");
+											}
+											quota_list_ev.invalidate();
+										});
+									}
+								>
+									Reset
+								</button>
+							</td>
+						</tr>
+					}
+				}).collect_view()}
+			</table>
+		})}
+		</Transition>
+	}
+}
+
+#[component]
+pub fn QuotaManager() -> impl IntoView {
+	view! {
+		<h3>Levels</h3>
+		<LevelQuotas />
+		<h3>Users</h3>
+		<UserQuotas />
+	}
+}
@@ -0,0 +1,108 @@
+use thrw_shared::user::api::{create_role, get_roles, set_role_permissions, Permission, UserRole};
+
+use crate::{prelude::*, routes::admin::consts::ROLE_LIST_ID};
+
+fn describe_permission(permission: Permission) -> &'static str {
+	match permission {
+		Permission::Download => "download",
+		Permission::Upload => "add files",
+		Permission::ViewAllFiles => "see hidden files",
+		Permission::ManageKeys => "manage keys",
+		Permission::ManageUsers => "manage users",
+		Permission::ManageRoles => "manage roles",
+		Permission::ManageServer => "manage the server",
+	}
+}
+
+/// every checkbox saves the whole set of the role right away
+#[component]
+fn role_row(role: UserRole) -> impl IntoView {
+	let role_list_ev = ReviewEvent::<{ROLE_LIST_ID}>::use_provided();
+	let status = RwSignal::new(None::<String>);
+	let level_id = role.level_id;
+	let current = role.permissions.clone();
+
+	view! {
+		<li>
+			{role.name.clone()}" ("{role.members}" users): "
+			{Permission::ALL.into_iter().map(|permission| {
+				let current = current.clone();
+				let checked = current.contains(&permission);
+				view! {
+					<label>
+						<input type="checkbox"
+							prop:checked=checked
+							on:change=move |_| {
+								let permissions: Vec<Permission> = match checked {
+									true => current.iter().copied().filter(|other| *other != permission).collect(),
+									false => current.iter().copied().chain([permission]).collect(),
+								};
+								spawn_local(async move {
+									match set_role_permissions(level_id, permissions).await {
+										Ok(()) => role_list_ev.invalidate(),
+										Err(err) => status.set(Some(err.to_string())),
+									}
+								});
+							}
+						/>
+						{describe_permission(permission)}
+					</label>
+				}
+			}).collect_view()}
+			<Show when=move || status.get().is_some()>
+				<p>{move || status.get()}</p>
+			</Show>
+		</li>
+	}
+}
+
+#[component]
+fn role_list() -> impl IntoView {
+	let role_list_ev = ReviewEvent::<{ROLE_LIST_ID}>::use_provided();
+	let role_res = Resource::new(role_list_ev.subscribe(), async |_| {
+		get_roles()
+			.await
+			.unwrap_or_default()
+	});
+
+	view! {
+		<Transition fallback=move || view! { <p>Loading...</p> }>
+		{move || role_res.get().map(|roles| view! {
+			<ul>
+			{roles.into_iter().map(|role| view! { <RoleRow role /> }).collect_view()}
+			</ul>
+		})}
+		</Transition>
+	}
+}
+
+#[component]
+pub fn RoleManager() -> impl IntoView {
+	let role_list_ev = ReviewEvent::<{ROLE_LIST_ID}>::use_provided();
+	let name = RwSignal::new("".to_string());
+	let status = RwSignal::new(None::<String>);
+
+	let create = move |_| {
+		let name_val = name.get_untracked();
+		spawn_local(async move {
+			match create_role(name_val).await {
+				Ok(_) => {
+					name.set("".to_string());
+					status.set(None);
+					role_list_ev.invalidate();
+				},
+				Err(err) => status.set(Some(err.to_string())),
+			}
+		});
+	};
+
+	view! {
+		<h3>Roles</h3>
+		<RoleList />
+		<input bind:value=name placeholder="new role" />
+		<button on:click=create>create</button>
+		<Show when=move || status.get().is_some()>
+			<p>{move || status.get()}</p>
+		</Show>
+	}
+}
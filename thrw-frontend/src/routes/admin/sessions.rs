@@ -0,0 +1,48 @@
+use thrw_shared::user::api::{get_all_sessions, kill_session};
+
+use crate::{prelude::*, routes::{account::sessions::describe_session, admin::consts::SESSION_LIST_ID}};
+
+#[component]
+pub fn SessionManager() -> impl IntoView {
+	let session_list_ev = ReviewEvent::<{SESSION_LIST_ID}>::use_provided();
+	let user_filter = RwSignal::new("".to_string());
+	let sessions_res = Resource::new(
+		move || (session_list_ev.subscribe()(), user_filter.get()),
+		async |(_, user_filter)| {
+			get_all_sessions(user_filter.trim().parse::<i32>().ok())
+				.await
+				.unwrap_or_default()
+		}
+	);
+
+	view! {
+		<h3>Sessions</h3>
+		<input bind:value=user_filter type="number" placeholder="user id, empty for everyone" />
+		<Transition fallback=move || view! { <p>Loading...</p> }>
+		{move || sessions_res.get().map(|sessions| view! {
+			<ul>
+			{sessions.into_iter().map(|session| {
+				let session_id = session.id;
+				view! {
+					<li>
+						{format!("{} (user {}): {}", session.email, session.user_id, describe_session(&session))}
+						<button
+							on:click=move |_| {
+								spawn_local(async move {
+									if let Err(err) = kill_session(session_id).await {
+										log::debug!("unable to kill session: {err:?}");
+									}
+									session_list_ev.invalidate();
+								});
+							}
+						>
+							X
+						</button>
+					</li>
+				}
+			}).collect_view()}
+			</ul>
+		})}
+		</Transition>
+	}
+}
@@ -0,0 +1,92 @@
+use thrw_shared::user::api::{get_roles, reset_user_totp, set_user_role};
+
+use crate::prelude::*;
+
+/// for users locked out of their second factor
+#[component]
+fn totp_reset() -> impl IntoView {
+	let user_id = RwSignal::new("".to_string());
+	let status = RwSignal::new(None::<String>);
+
+	let reset = move |_| {
+		let Ok(id) = user_id.get_untracked().trim().parse::<i32>() else {
+			status.set(Some("invalid user id".to_string()));
+			return;
+		};
+		spawn_local(async move {
+			match reset_user_totp(id).await {
+				Ok(_) => {
+					status.set(Some(format!("two-factor authentication of user {id} was reset")));
+					user_id.set("".to_string());
+				},
+				Err(err) => status.set(Some(err.to_string())),
+			}
+		});
+	};
+
+	view! {
+		<h3>Two-factor authentication</h3>
+		<input bind:value=user_id type="number" placeholder="user id" />
+		<button on:click=reset>reset</button>
+		<Show when=move || status.get().is_some()>
+			<p>{move || status.get()}</p>
+		</Show>
+	}
+}
+
+#[component]
+fn role_assignment() -> impl IntoView {
+	let user_id = RwSignal::new("".to_string());
+	let level_id = RwSignal::new("".to_string());
+	let status = RwSignal::new(None::<String>);
+	let role_res = Resource::new(|| (), async |_| {
+		get_roles()
+			.await
+			.unwrap_or_default()
+	});
+
+	let assign = move |_| {
+		let Ok(id) = user_id.get_untracked().trim().parse::<i32>() else {
+			status.set(Some("invalid user id".to_string()));
+			return;
+		};
+		let Ok(level) = level_id.get_untracked().parse::<i16>() else {
+			status.set(Some("pick a role".to_string()));
+			return;
+		};
+		spawn_local(async move {
+			match set_user_role(id, level).await {
+				Ok(_) => {
+					status.set(Some(format!("user {id} got the new role")));
+					user_id.set("".to_string());
+				},
+				Err(err) => status.set(Some(err.to_string())),
+			}
+		});
+	};
+
+	view! {
+		<h3>Role</h3>
+		<input bind:value=user_id type="number" placeholder="user id" />
+		<select bind:value=level_id>
+			<option value="">"role"</option>
+			<Transition>
+			{move || role_res.get().map(|roles| roles.into_iter().map(|role| view! {
+				<option value=role.level_id.to_string()>{role.name}</option>
+			}).collect_view())}
+			</Transition>
+		</select>
+		<button on:click=assign>assign</button>
+		<Show when=move || status.get().is_some()>
+			<p>{move || status.get()}</p>
+		</Show>
+	}
+}
+
+#[component]
+pub fn UserManager() -> impl IntoView {
+	view! {
+		<TotpReset />
+		<RoleAssignment />
+	}
+}
@@ -0,0 +1,112 @@
+use thrw_shared::user::api::{request_password_reset, reset_password, verify_email};
+
+use crate::prelude::*;
+
+/// the token of a link that was mailed out
+fn use_token() -> Memo<Option<String>> {
+	let query = leptos_router::hooks::use_query_map();
+	Memo::new(move |_| query.read().get("token"))
+}
+
+#[component]
+pub fn ForgotPassword() -> impl IntoView {
+	let mail = RwSignal::new("".to_string());
+	let sent = RwSignal::new(false);
+
+	view! {
+		<Show
+			when=move || sent.get()
+			fallback=move || view! {
+				<p>"Enter the email of your account to get a link for choosing a new password"</p>
+				<input type="email"
+					bind:value=mail
+				/>
+				<button
+					on:click=move |_| {
+						let mail_val = mail.get_untracked();
+						spawn_local(async move {
+							match request_password_reset(mail_val).await {
+								Ok(()) => sent.set(true),
+								Err(err) => log::debug!("password reset error: {err}"),
+							};
+						});
+					}
+				>
+					Send link
+				</button>
+			}
+		>
+			<p>"If the email belongs to an account, a link is on its way"</p>
+		</Show>
+	}
+}
+
+#[component]
+pub fn ResetPassword() -> impl IntoView {
+	let token = use_token();
+	let pass = RwSignal::new("".to_string());
+	let pass_c = RwSignal::new("".to_string());
+	let done = RwSignal::new(false);
+
+	view! {
+		<Show
+			when=move || done.get()
+			fallback=move || view! {
+				<p>"Choose a new password, all sessions are logged out and api tokens revoked afterwards"</p>
+				<input type="password"
+					bind:value=pass
+				/>
+				<input type="password"
+					bind:value=pass_c
+				/>
+				<button
+					on:click=move |_| {
+						let (Some(token), pass_val) = (token.get_untracked(), pass.get_untracked()) else {
+							return;
+						};
+						if pass_val != pass_c.get_untracked() {
+							//TODO: show an error
+							return;
+						}
+						spawn_local(async move {
+							match reset_password(token, pass_val).await {
+								Ok(()) => done.set(true),
+								Err(err) => log::debug!("password reset error: {err}"),
+							};
+						});
+					}
+				>
+					Set password
+				</button>
+			}
+		>
+			<p>"Your password was changed"</p>
+			<A href="/login">Log in</A>
+		</Show>
+	}
+}
+
+#[component]
+pub fn VerifyEmail() -> impl IntoView {
+	let token = use_token();
+	let verify_res = Resource::new(move || token.get(), async |token| {
+		let Some(token) = token else {
+			return Err("the link is missing its token".to_string());
+		};
+		verify_email(token)
+			.await
+			.map_err(|err| err.to_string())
+	});
+
+	view! {
+		<Transition fallback=move || view! { <p>Verifying...</p> }>
+		{move || verify_res.get().map(|res| match res {
+			Ok(()) => view! { <p>"Your email is verified"</p> }.into_any(),
+			Err(err) => {
+				log::debug!("verify email error: {err}");
+				view! { <p>"The link is invalid or expired, you can request a new one from your account page"</p> }.into_any()
+			},
+		})}
+		</Transition>
+	}
+}
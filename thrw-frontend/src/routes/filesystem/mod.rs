@@ -1,9 +1,9 @@
 use std::{ffi::OsStr, path::{Path, PathBuf}};
 
 use serde::{Deserialize, Serialize};
-use thrw_shared::{downloader::api::download_media, vfs::{api::{create_vfs_node, get_vfs_nodes}, shared::{PubVfsNode, VfsTarget}}};
+use thrw_shared::{downloader::{api::{download_media, link_existing_download}, shared::{PubDownloadFormat, PubDownloadRequest, PubExistingDownload, PubPostProcessing}}, user::api::Permission, vfs::{api::{create_vfs_node, get_vfs_nodes}, shared::{PubVfsNode, PubVfsNodeType, VfsTarget}}};
 
-use crate::prelude::*;
+use crate::{components::{downloads::{DownloadFormatPicker, DownloadsPanel, PostProcessingPicker}, player::{ActiveMedia, ActivePlayer}, subscriptions::{self, SubscriptionsPanel}}, prelude::*};
 
 pub mod consts {
 	pub const NODE_LIST_ID: i32 = crate::prelude::VFS_IDS + 1;
@@ -48,8 +48,9 @@ pub fn vfs_entry(
 	let name_link = name.clone();
 	let href = move||path_href.clone();
 	let when = move||matches!(node_for_when.node_type, thrw_shared::vfs::shared::PubVfsNodeType::Folder);
+	let node_for_thumb = node.clone();
 	let thumb_src = move||{
-		let node = node.clone();
+		let node = node_for_thumb.clone();
 		node.thumbnail.unwrap_or(match node.node_type {
 			thrw_shared::vfs::shared::PubVfsNodeType::Folder => "/icons/folder.png",
 			thrw_shared::vfs::shared::PubVfsNodeType::Video => "/icons/video-file.png",
@@ -59,9 +60,35 @@ pub fn vfs_entry(
 		}.to_string())
 	};
 
+	let node_for_open = node.clone();
+	let active_media = ActiveMedia::use_provided();
+	let open_media = move |_: leptos::ev::MouseEvent| {
+		let Some(active) = active_media else {
+			return;
+		};
+		if matches!(node_for_open.node_type, PubVfsNodeType::Video | PubVfsNodeType::Audio) {
+			active.open(node_for_open.clone());
+		}
+	};
+	let is_media = matches!(node.node_type, PubVfsNodeType::Video | PubVfsNodeType::Audio);
+	let badge = match node.playback.clone() {
+		_ if !is_media => None,
+		None => Some(view! { <span class="vfs_badge unwatched">unwatched</span> }.into_any()),
+		Some(state) if state.completed => Some(view! { <span class="vfs_badge watched">watched</span> }.into_any()),
+		Some(state) => {
+			let percent = node.duration
+				.filter(|duration| *duration > 0.0)
+				.map(|duration| format!("{:.0}%", state.position / duration * 100.0))
+				.unwrap_or("started".to_string())
+			;
+			Some(view! { <span class="vfs_badge in_progress">{percent}</span> }.into_any())
+		},
+	};
+
 	let fallback = move|| view! {
 		<p
 			class="vfs_link"
+			on:click=open_media.clone()
 		>
 			{name.clone()}
 		</p>
@@ -86,6 +113,7 @@ pub fn vfs_entry(
 			>
 			{main_view.clone()()}
 			</Show>
+			{badge}
 		</div>
 	}
 }
@@ -94,10 +122,16 @@ pub fn vfs_entry(
 pub fn vfs_path_handler() -> impl IntoView {
 	let location = leptos_router::hooks::use_location();
 	let vfs_node_review = ReviewEvent::<{VFS_IDS}>::use_provided();
+	let download_review = ReviewEvent::<{DOWNLOAD_IDS}>::use_provided();
+	ActiveMedia::provide_new();
 	let path_signal = RwSignal::new(VfsRoute::Invalid);
 	let node_text = RwSignal::new("".to_string());
 	let vid_url = RwSignal::new("".to_string());
 	let video_check = RwSignal::new(false);
+	let download_format = RwSignal::new(PubDownloadFormat::default());
+	let post_processing = RwSignal::new(PubPostProcessing::default());
+	// earlier downloads of the requested url, waiting for the user to pick one or download anyway
+	let existing_downloads = RwSignal::new(Vec::<PubExistingDownload>::new());
 
 	let node_res = Resource::new(path_signal, async |path| {
 		// log::debug!("nodes refreshing...");
@@ -165,6 +199,33 @@ pub fn vfs_path_handler() -> impl IntoView {
 		refresh_path_parts();
 	});
 
+	let request_download = move |allow_duplicate: bool| {
+		spawn_local(async move {
+			let url = vid_url.get_untracked();
+			log::debug!("downloading media at {}...", url);
+			let dl_res = download_media(
+				url,
+				!video_check.get_untracked(),
+				download_format.get_untracked(),
+				post_processing.get_untracked(),
+				Some(path_signal.get_untracked().into()),
+				allow_duplicate
+			)
+				.await
+			;
+
+			match dl_res {
+				Ok(PubDownloadRequest::Queued(job)) => {
+					log::debug!("download queued as job {job}");
+					existing_downloads.set(vec![]);
+					download_review.invalidate();
+				},
+				Ok(PubDownloadRequest::AlreadyDownloaded(existing)) => existing_downloads.set(existing),
+				Err(err) => log::debug!("media download failed: {err:?}"),
+			};
+		});
+	};
+
 	let get_path_string = Memo::new(move |_prev| {
 		match path_signal() {
 			VfsRoute::Invalid => "path invalid!".to_string(),
@@ -174,6 +235,8 @@ pub fn vfs_path_handler() -> impl IntoView {
 	});
     
 	view! {
+		<ActivePlayer />
+
 		// path visibliity
 		<div>
 			<Transition fallback=move || view! {  }>
@@ -256,6 +319,7 @@ pub fn vfs_path_handler() -> impl IntoView {
 			</Transition>
 		</div>
 		// add node
+		<Show when=move || matches!(check_permission(Permission::Upload), Some(true))>
 		<div>
 			<input bind:value=node_text />
 			<button
@@ -274,43 +338,74 @@ pub fn vfs_path_handler() -> impl IntoView {
 				add new
 			</button>
 		</div>
+		</Show>
 		// download
+		<Show when=move || matches!(check_permission(Permission::Download), Some(true))>
 		<div>
 			<input bind:value=vid_url />
 			<input bind:value=video_check type="checkbox" />
-			<button
-				on:click=move|_|{
-					spawn_local(async move {
-						let url = vid_url.get_untracked();
-						log::debug!("downloading media at {}...", url);
-						let dl_res = download_media(
-							url,
-							!video_check.get_untracked(),
-							Some(path_signal.get_untracked().into())
-						)
-							.await
-						;
-						
-						match dl_res {
-							Ok(_id) => {
-								log::debug!("media downloaded, refreshing...");
-								vfs_node_review.invalidate();
-								node_res.refetch();
-							},
-							Err(err) => log::debug!("media download failed: {err:?}"),
-						};
-					});
-				}
-			>
+			<button on:click=move |_| request_download(false)>
 				download
 			</button>
+			<DownloadFormatPicker
+				format=download_format
+				url=vid_url
+				audio_only=Signal::derive(move || !video_check.get())
+			/>
+			<PostProcessingPicker post_processing />
+			<Show when=move || !existing_downloads.with(Vec::is_empty)>
+				<div class="existing_downloads">
+					<p>Already downloaded:</p>
+					<ul>
+					{move || existing_downloads.get().into_iter().map(|existing| {
+						let file = existing.file;
+						view! {
+							<li>
+								{existing.path.clone()}
+								{if existing.audio_only { " (audio)" } else { "" }}
+								<button
+									on:click=move |_| {
+										spawn_local(async move {
+											match link_existing_download(file, Some(path_signal.get_untracked().into())).await {
+												Ok(_node) => {
+													existing_downloads.set(vec![]);
+													vfs_node_review.invalidate();
+													node_res.refetch();
+												},
+												Err(err) => log::debug!("unable to link download: {err:?}"),
+											}
+										});
+									}
+								>
+									link here
+								</button>
+							</li>
+						}
+					}).collect_view()}
+					</ul>
+					<button on:click=move |_| request_download(true)>
+						download anyway
+					</button>
+					<button on:click=move |_| existing_downloads.set(vec![])>
+						cancel
+					</button>
+				</div>
+			</Show>
 		</div>
+		<DownloadsPanel on_completed=move || {
+			vfs_node_review.invalidate();
+			node_res.refetch();
+		} />
+		<SubscriptionsPanel target=Signal::derive(move || path_signal().into()) />
+		</Show>
 	}
 }
 
 #[component(transparent)]
 pub fn FilesystemRoutes() -> impl MatchNestedRoutes + Clone {
 	ReviewEvent::<{VFS_IDS}>::provide_new();
+	ReviewEvent::<{DOWNLOAD_IDS}>::provide_new();
+	ReviewEvent::<{subscriptions::consts::SUBSCRIPTION_LIST_ID}>::provide_new();
 	
 	view! {
 		<ProtectedParentRoute
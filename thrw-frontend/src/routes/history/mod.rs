@@ -0,0 +1,60 @@
+use thrw_shared::playback::{api::{get_continue_watching, get_recently_played}, shared::PlaybackEntry};
+
+use crate::{components::player::{ActiveMedia, ActivePlayer}, prelude::*, routes::filesystem::VfsEntry};
+
+#[component]
+fn playback_list(
+	title: &'static str,
+	entries: Resource<Vec<PlaybackEntry>>,
+) -> impl IntoView {
+	view! {
+		<h3>{title}</h3>
+		<Transition fallback=move || view! { <p>Loading...</p> }>
+		{move || entries.get().map(|entries| {
+			if entries.is_empty() {
+				return view! { <p>Nothing here yet</p> }.into_any();
+			}
+			entries.iter().map(|entry| {
+				let node = entry.node.clone();
+				view! { <VfsEntry node /> }
+			}).collect_view().into_any()
+		})}
+		</Transition>
+	}
+}
+
+#[component]
+pub fn History() -> impl IntoView {
+	ActiveMedia::provide_new();
+	let continue_res = Resource::new(|| (), async |_| {
+		get_continue_watching(None)
+			.await
+			.unwrap_or(vec![])
+	});
+	let recent_res = Resource::new(|| (), async |_| {
+		get_recently_played(None)
+			.await
+			.unwrap_or(vec![])
+	});
+
+	view! {
+		<ActivePlayer />
+		<PlaybackList title="Continue watching" entries=continue_res />
+		<PlaybackList title="Recently played" entries=recent_res />
+	}
+}
+
+#[component(transparent)]
+pub fn HistoryRoutes() -> impl MatchNestedRoutes + Clone {
+	view! {
+		<ProtectedParentRoute
+			path=path!("/history")
+			view=EmptyParent
+			condition=check_login_raw
+			redirect_path=||"/"
+		>
+			<Route path=path!("/") view=History />
+		</ProtectedParentRoute>
+	}
+	.into_inner()
+}
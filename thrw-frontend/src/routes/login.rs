@@ -1,4 +1,4 @@
-use thrw_shared::{app::state::{client::LoginContext, shared::LoginState}, user::api::log_in};
+use thrw_shared::{app::state::{client::LoginContext, shared::{AccountLevel, LoginState}}, user::api::{log_in, verify_login, LoginResult}};
 
 use crate::prelude::*;
 
@@ -8,36 +8,79 @@ pub fn Login() -> impl IntoView {
 	let login = login_ctx.login_state;
 	let (mail, set_mail) = signal("test".to_string());
 	let (pass, set_pass) = signal("".to_string());
+	// set once the password was accepted and a second factor is required
+	let challenge = RwSignal::new(None::<uuid::Uuid>);
+	let code = RwSignal::new("".to_string());
 	// *login_ctx.login_state.write() = LoginState::LoggedOut;
 	view! {
-		<input type="text"
-			bind:value=(mail, set_mail)
-		/>
-		<input type="password"
-			bind:value=(pass, set_pass)
-		/>
-		<p>"Test: " {mail}</p>
-		<button
-			on:click=move |_| {
-				let (mail, pass) = (
-					mail(),
-					pass()
-				);
-				let login = login;
-				spawn_local(async move {
-					let res = log_in(mail, pass).await;
-					match res {
-						Ok((id, uuid, level_name)) => {
-							login.set(LoginState::LoggedIn(id, uuid, level_name.into()));
-						},
-						Err(err) => {
-							log::debug!("login error: {err}");
-						},
-					};
-				});
+		<Show
+			when=move || challenge.get().is_some()
+			fallback=move || view! {
+				<input type="text"
+					bind:value=(mail, set_mail)
+				/>
+				<input type="password"
+					bind:value=(pass, set_pass)
+				/>
+				<p>"Test: " {mail}</p>
+				<button
+					on:click=move |_| {
+						let (mail, pass) = (
+							mail(),
+							pass()
+						);
+						let login = login;
+						spawn_local(async move {
+							let res = log_in(mail, pass).await;
+							match res {
+								Ok(LoginResult::LoggedIn(id, uuid, level_name, permissions)) => {
+									login.set(LoginState::LoggedIn(id, uuid, AccountLevel::new(level_name, permissions)));
+								},
+								Ok(LoginResult::SecondFactor(id)) => {
+									challenge.set(Some(id));
+								},
+								Err(err) => {
+									log::debug!("login error: {err}");
+								},
+							};
+						});
+					}
+				>
+					Log in
+				</button>
+				<A href="/forgot-password">Forgot your password?</A>
 			}
 		>
-			Log in
-		</button>
+			<p>"Enter the code of your authenticator or a recovery code"</p>
+			<input type="text"
+				bind:value=code
+				autocomplete="one-time-code"
+			/>
+			<button
+				on:click=move |_| {
+					let Some(id) = challenge.get_untracked() else {
+						return;
+					};
+					let code_val = code.get_untracked();
+					spawn_local(async move {
+						match verify_login(id, code_val).await {
+							Ok((id, uuid, level_name, permissions)) => {
+								login.set(LoginState::LoggedIn(id, uuid, AccountLevel::new(level_name, permissions)));
+							},
+							Err(err) => {
+								log::debug!("second factor error: {err}");
+								code.set("".to_string());
+								// expired or used up challenges have to start over
+								if !err.to_string().contains("WrongCode") {
+									challenge.set(None);
+								}
+							},
+						};
+					});
+				}
+			>
+				Verify
+			</button>
+		</Show>
 	}
-}
\ No newline at end of file
+}
@@ -1,5 +1,5 @@
-use crate::{components::navbar::Header, prelude::*, routes::{account::AccountRoutes, admin::AdminRoutes, chat::ChatRoutes, filesystem::FilesystemRoutes, home::Home, invalid::NotFound, login::Login, register::Register}, storage::init_storage};
-use thrw_shared::{app::state::{client::LoginContext, shared::LoginState}, user::api::is_logged_in};
+use crate::{components::navbar::Header, prelude::*, routes::{account::AccountRoutes, admin::AdminRoutes, chat::ChatRoutes, filesystem::FilesystemRoutes, history::HistoryRoutes, home::Home, invalid::NotFound, login::Login, register::Register, email::{ForgotPassword, ResetPassword, VerifyEmail}}, storage::init_storage};
+use thrw_shared::{app::state::{client::LoginContext, shared::{AccountLevel, LoginState}}, user::api::is_logged_in};
 
 pub mod helpers {
 	pub use super::{EmptyParent, EmptyView};
@@ -9,10 +9,12 @@ pub mod invalid;
 pub mod home;
 pub mod login;
 pub mod register;
+pub mod email;
 pub mod account;
 pub mod admin;
 pub mod chat;
 pub mod filesystem;
+pub mod history;
 
 pub fn shell(options: LeptosOptions) -> impl IntoView {
 	view! {
@@ -73,10 +75,10 @@ pub fn App() -> impl IntoView {
 	add_context();
 
 	let get_login_state = Resource::new(||(), async |_| {
-		let Ok(Some((id, token, level_name))) = is_logged_in().await else {
+		let Ok(Some((id, token, level_name, permissions))) = is_logged_in().await else {
 			return None;
 		};
-		Some((id, token, level_name))
+		Some((id, token, level_name, permissions))
 	});
 
 	// get the initial login state
@@ -87,10 +89,10 @@ pub fn App() -> impl IntoView {
 			let login = ctx.login_state;
 
 			login.set(match get_login_state.await {
-				Some((id, token, level_name)) => LoginState::LoggedIn(
+				Some((id, token, level_name, permissions)) => LoginState::LoggedIn(
 					id,
 					token,
-					level_name.into()
+					AccountLevel::new(level_name, permissions)
 				),
 				None => LoginState::LoggedOut,
 			});
@@ -111,6 +113,9 @@ pub fn App() -> impl IntoView {
 						<Route path=path!("/") view=Home/>
 						<Route path=path!("/login") view=Login/>
 						<Route path=path!("/register") view=Register />
+						<Route path=path!("/forgot-password") view=ForgotPassword />
+						<Route path=path!("/reset-password") view=ResetPassword />
+						<Route path=path!("/verify-email") view=VerifyEmail />
 
 						<FilesystemRoutes />
 
@@ -119,6 +124,8 @@ pub fn App() -> impl IntoView {
 						<AdminRoutes />
 
 						<AccountRoutes />
+
+						<HistoryRoutes />
 					</ParentRoute>
 				</Routes>
 			</main>
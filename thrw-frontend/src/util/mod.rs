@@ -1,4 +1,4 @@
-use thrw_shared::app::state::{client::LoginContext, shared::{AccountLevel, LoginState}};
+use thrw_shared::{app::state::{client::LoginContext, shared::LoginState}, user::api::Permission};
 
 use crate::prelude::*;
 
@@ -6,6 +6,7 @@ pub mod consts {
 	pub const ACC_IDS: i32 = 20000;
 	pub const ADMIN_IDS: i32 = 30000;
 	pub const VFS_IDS: i32 = 40000;
+	pub const DOWNLOAD_IDS: i32 = 50000;
 }
 
 
@@ -63,6 +64,16 @@ pub fn check_admin() -> Option<bool> {
 	match ctx.login_state.get() {
 		LoginState::Unverified => None,
 		LoginState::LoggedOut => Some(false),
-		LoginState::LoggedIn(_, _, account_level) => Some(matches!(account_level, AccountLevel::Admin)),
+		LoginState::LoggedIn(_, _, account_level) => Some(account_level.is_admin()),
+	}
+}
+
+/// only for hiding what can't be used, the server checks again
+pub fn check_permission(permission: Permission) -> Option<bool> {
+	let ctx = use_context::<LoginContext>().expect("login context missing");
+	match ctx.login_state.get() {
+		LoginState::Unverified => None,
+		LoginState::LoggedOut => Some(false),
+		LoginState::LoggedIn(_, _, account_level) => Some(account_level.has(permission)),
 	}
 }
\ No newline at end of file
@@ -0,0 +1,46 @@
+use std::{fmt::Debug, sync::Arc};
+
+use futures::future::BoxFuture;
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+	pub to: String,
+	pub subject: String,
+	/// plain text only
+	pub body: String,
+}
+
+#[derive(Debug)]
+pub enum MailError {
+	/// the mailer is missing or has a broken configuration
+	Config(String),
+	Address(String),
+	Send(String),
+}
+
+/// sends mails on behalf of the server, the webserver picks the implementation on startup
+pub trait Mailer: Send + Sync + Debug {
+	fn send<'a>(&'a self, mail: Mail) -> BoxFuture<'a, Result<(), MailError>>;
+}
+
+/// logs who a mail would have gone to, used when no mail server is configured; the body holds tokens and is never logged
+#[derive(Debug, Clone, Default)]
+pub struct LogMailer;
+impl Mailer for LogMailer {
+	fn send<'a>(&'a self, mail: Mail) -> BoxFuture<'a, Result<(), MailError>> {
+		Box::pin(async move {
+			println!("mail not sent to '{}': {}", mail.to, mail.subject);
+			Ok(())
+		})
+	}
+}
+
+/// sends the mail without making the request wait on the mail server
+pub fn send_in_background(mailer: Arc<dyn Mailer>, mail: Mail) {
+	tokio::spawn(async move {
+		let to = mail.to.clone();
+		if let Err(err) = mailer.send(mail).await {
+			println!("unable to send mail to '{to}': {err:?}");
+		}
+	});
+}
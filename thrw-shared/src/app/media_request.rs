@@ -1,28 +1,89 @@
-use serde::{Deserialize, Serialize};
-use tokio::sync::{mpsc::{UnboundedReceiver, UnboundedSender}, oneshot};
-use youtube_dl::{SingleVideo, YoutubeDlOutput};
+use std::{path::PathBuf, sync::Arc};
 
-use crate::{downloader::shared::DownloaderError, vfs::util::FileRef};
+use tokio::sync::{broadcast, Notify};
+use youtube_dl::SingleVideo;
 
-#[derive(Debug)]
-pub enum MediaRequestError {
-	Dl(DownloaderError)
+use crate::{downloader::shared::{PubDownloadFormat, PubDownloadProgress}, vfs::util::FileRef};
+
+mod consts {
+	pub const PROGRESS_CHANNEL_SIZE: usize = 256;
+	pub const CANCEL_CHANNEL_SIZE: usize = 64;
 }
 
 #[derive(Debug, Clone)]
 pub struct DownloaderContext {
-	pub request_channel: UnboundedSender<MediaRequest>,
+	/// wakes the downloader after a job has been queued
+	pub job_notify: Arc<Notify>,
+	/// wakes the subscription scheduler after a subscription became due
+	pub subscription_notify: Arc<Notify>,
+	/// progress of running jobs along with the id of the requesting user
+	pub progress: broadcast::Sender<(i32, PubDownloadProgress)>,
+	/// ids of jobs that were cancelled while running
+	pub cancel: broadcast::Sender<uuid::Uuid>,
+}
+impl Default for DownloaderContext {
+	fn default() -> Self {
+		Self {
+			job_notify: Default::default(),
+			subscription_notify: Default::default(),
+			progress: broadcast::channel(consts::PROGRESS_CHANNEL_SIZE).0,
+			cancel: broadcast::channel(consts::CANCEL_CHANNEL_SIZE).0,
+		}
+	}
+}
+impl DownloaderContext {
+	pub fn notify_job(&self) {
+		self.job_notify.notify_one();
+	}
+
+	pub fn notify_subscriptions(&self) {
+		self.subscription_notify.notify_one();
+	}
+
+	pub fn report_progress(&self, user_id: i32, progress: PubDownloadProgress) {
+		// nobody listening is fine
+		let _ = self.progress.send((user_id, progress));
+	}
+
+	pub fn cancel_job(&self, job_id: uuid::Uuid) {
+		// no worker listening means the job is not running here
+		let _ = self.cancel.send(job_id);
+	}
 }
 
 #[derive(Debug, Clone)]
 pub struct YtdlRequest {
 	pub url: String,
 	pub audio_only: bool,
+	pub format: PubDownloadFormat,
 }
 
-#[derive(Debug)]
-pub enum MediaRequest {
-	Ytdl(YtdlRequest, oneshot::Sender<Result<YtdlResult, MediaRequestError>>),
+/// files handing a user's site logins to yt-dlp, only ever written into the directory of one of their jobs
+#[derive(Debug, Clone, Default)]
+pub struct YtdlAuth {
+	pub cookies: Option<PathBuf>,
+	/// a config file with the username and password, which keeps them off the command line
+	pub config: Option<PathBuf>,
+}
+impl YtdlAuth {
+	pub fn args(&self) -> Vec<String> {
+		let mut args = vec![];
+		if let Some(cookies) = &self.cookies {
+			args.push("--cookies".to_string());
+			args.push(cookies.to_string_lossy().into_owned());
+		}
+		if let Some(config) = &self.config {
+			args.push("--config-locations".to_string());
+			args.push(config.to_string_lossy().into_owned());
+		}
+		args
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct YtdlSubtitle {
+	pub lang: String,
+	pub file: FileRef,
 }
 
 #[derive(Debug, Clone)]
@@ -30,4 +91,16 @@ pub struct YtdlResult {
 	pub output: SingleVideo,
 	pub media: FileRef,
 	pub thumbnail: Option<FileRef>,
-}
\ No newline at end of file
+	pub subtitles: Vec<YtdlSubtitle>,
+}
+
+/// a file pulled straight from a url, without yt-dlp
+#[derive(Debug, Clone)]
+pub struct DirectFile {
+	pub url: String,
+	/// the name of the file in the url, without its extension
+	pub name: String,
+	pub file: FileRef,
+	/// as reported by the server, only trusted where the content can't be inferred
+	pub mime_type: Option<String>,
+}
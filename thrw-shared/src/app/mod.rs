@@ -2,4 +2,6 @@
 pub mod state;
 pub mod cookie;
 #[cfg(feature = "server")]
-pub mod media_request;
\ No newline at end of file
+pub mod media_request;
+#[cfg(feature = "server")]
+pub mod mail;
\ No newline at end of file
@@ -16,6 +16,8 @@ pub mod client {
 }
 
 pub mod shared { 
+	use crate::user::api::Permission;
+
 	#[derive(Debug, Clone, Default)]
 	pub enum LoginState {
 		#[default]
@@ -24,20 +26,27 @@ pub mod shared {
 		LoggedIn(i32, String, AccountLevel),
 	}
 
+	/// the role of the logged in user, the server checks the permissions again on every request
 	#[derive(Debug, Clone, Default)]
-	pub enum AccountLevel {
-		#[default]
-		User,
-		Admin
+	pub struct AccountLevel {
+		pub name: String,
+		pub permissions: Vec<Permission>,
 	}
 
-	impl From<String> for AccountLevel {
-		fn from(value: String) -> Self {
-			match value.as_str() {
-				"user" => Self::User,
-				"admin" => Self::Admin,
-				_ => Self::User
-			}
+	impl AccountLevel {
+		pub fn new(name: String, permissions: Vec<Permission>) -> Self {
+			Self { name, permissions }
+		}
+
+		pub fn has(&self, permission: Permission) -> bool {
+			self.permissions.contains(&permission)
+		}
+
+		/// whether any of the admin pages is of use
+		pub fn is_admin(&self) -> bool {
+			Permission::ADMIN
+				.iter()
+				.any(|permission| self.has(*permission))
 		}
 	}
 }
@@ -50,12 +59,11 @@ pub mod server {
 	use leptos::{config::LeptosOptions, prelude::{use_context, ServerFnError}};
 	use sqlx::{Pool, Postgres};
 
-	use crate::app::media_request::DownloaderContext;
+	use crate::app::{mail::Mailer, media_request::DownloaderContext};
 
 	#[derive(Debug, Clone, Default)]
 	pub struct UserData {
 		pub name_inspection_lock: Arc<Mutex<()>>,
-		pub admin_level: Arc<Mutex<Option<i32>>>,
 	}
 
 	#[derive(axum::extract::FromRef, Debug, Clone)]
@@ -64,6 +72,7 @@ pub mod server {
 		pub leptos_options: LeptosOptions,
 		pub user_data: UserData,
 		pub dl_context: DownloaderContext,
+		pub mailer: Arc<dyn Mailer>,
 	}
 
 	#[derive(Debug)]
@@ -1,116 +1,332 @@
 use super::prelude::*;
 use crate::prelude::*;
 use crate::user::prelude::*;
-use crate::vfs::prelude::*;
-use crate::media::prelude::*;
 use crate::vfs::shared::VfsTarget;
 
+mod consts {
+	pub const DEFAULT_JOB_LIMIT: i64 = 20;
+}
 
+/// queue a download, unless the same source was downloaded before and duplicates are not allowed
 #[server]
 pub async fn download_media(
 	url: String,
 	audio_only: bool,
+	format: PubDownloadFormat,
+	post_processing: PubPostProcessing,
 	vfs_target: Option<VfsTarget>,
-) -> Result<uuid::Uuid, ServerFnError> {
-	use crate::app::state::server::extract_state;
-	use tokio::sync::oneshot;
-	let _ = require_auth().await?;
+	allow_duplicate: bool,
+) -> Result<PubDownloadRequest, ServerFnError> {
+	use crate::app::{media_request::YtdlRequest, state::server::extract_state};
+	let (user_id, _) = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
 	let state = extract_state()?;
 
-	let (res_send, res_recv) = oneshot::channel();
-
-	// TODO: check if url has been downloaded prior 
+	if !allow_duplicate {
+		match find_existing_downloads(&state.db_pool, &url).await {
+			Ok(existing) if !existing.is_empty() => return Ok(PubDownloadRequest::AlreadyDownloaded(existing)),
+			Ok(_) => (),
+			// the job reports whatever is wrong with the url
+			Err(err) => println!("unable to check '{url}' for earlier downloads: {err:?}"),
+		}
+	}
 
-	state.dl_context.request_channel.send(crate::app::media_request::MediaRequest::Ytdl(
-		crate::app::media_request::YtdlRequest{
+	let job_id = enqueue_download_job(
+		&state.db_pool,
+		user_id,
+		YtdlRequest {
 			url,
-			audio_only
+			audio_only,
+			format,
 		},
-		res_send
-	))?;
-
-	let ytdl_res = match res_recv.await {
-		Ok(Ok(res)) => res,
-		Ok(Err(dl_err)) => return Err(make_server_err(dl_err)),
-		Err(send_err) => return Err(make_server_err(send_err)),
-	};
-	// println!("dl okay: {ytdl_res:?}");
-
-	let name = ytdl_res.output.title.unwrap_or("UNKNOWN TITLE??".to_string());
-	
-	let get_ftype = async |(file, infer_type): (FileRef, infer::MatcherType)| {
-		match infer_type {
-			  infer::MatcherType::Video
-			| infer::MatcherType::Audio => Ok(VFSFileType::Multimedia(
-				get_media_file_metadata(file.path.clone()).await?
-			)),
-			infer::MatcherType::Image => Ok(VFSFileType::Image(
-				get_media_file_metadata(file.path.clone()).await?
-			)),
-			_ => {
-				println!("invalid file type received from ytdl, removing");
-				let _ = file.delete_file();
-				return Err(MediaError::InvalidType);
-			},
-		}
-	};
-
-	let media_file_id = {
-		let file_data = VfsFileData {
-			name: name.clone(),
-			file: ytdl_res.media.clone(),
-			file_type: get_ftype((ytdl_res.media.clone(), infer::MatcherType::Audio))
-				.await
-				.map_err(make_server_err)?,
-			hide: false,
-		};
-
-		let (file_id, _) = commit_file_to_vfs(
-			file_data,
-			&state.db_pool,
-			vfs_target.clone()
-		)
-			.await
-			.map_err(make_server_err)?
-		;
-
-		file_id
-	};
-
-	if let Some(thumb_file) = ytdl_res.thumbnail.clone() {
-		let file_data = VfsFileData {
-			name: name.clone() + " Thumbnail",
-			file: thumb_file.clone(),
-			file_type: get_ftype((thumb_file, infer::MatcherType::Image))
-				.await
-				.map_err(make_server_err)?,
-			hide: true,
-		};
-
-		// println!("adding thumbnail; {file_data:?}");
-
-		let (file_id, _) = commit_file_to_vfs(
-			file_data,
-			&state.db_pool,
-			vfs_target.clone()
-		)
-			.await
-			.map_err(make_server_err)?
-		;
-
-		set_thumbnail(&state.db_pool, media_file_id, file_id)
-			.await
-			.map_err(make_server_err)?
-		;
-	};
-
-	#[cfg(debug_assertions)]
-	{
-		let _ = crate::util::copy_dir_all(
-			std::path::PathBuf::from("./site"),
-			std::path::PathBuf::from("./target/site")
-		);
+		post_processing,
+		vfs_target
+	)
+		.await
+		.map_err(make_server_err)?
+	;
+	state.dl_context.notify_job();
+
+	Ok(PubDownloadRequest::Queued(job_id))
+}
+
+/// the formats yt-dlp offers for the url, to pick one before downloading
+#[server]
+pub async fn get_available_formats(
+	url: String,
+) -> Result<Vec<PubAvailableFormat>, ServerFnError> {
+	let _ = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
+
+	get_available_formats_internal(&url)
+		.await
+		.map_err(make_server_err)
+}
+
+/// add a node for an earlier download to the target folder, returning the new node
+#[server]
+pub async fn link_existing_download(
+	file: uuid::Uuid,
+	vfs_target: Option<VfsTarget>,
+) -> Result<uuid::Uuid, ServerFnError> {
+	let _ = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
+	let db = extract_db()?;
+
+	link_existing_download_internal(&db, file, vfs_target)
+		.await
+		.map_err(make_server_err)
+}
+
+/// where the file of a node was downloaded from, if it was downloaded
+#[server]
+pub async fn get_source_info(
+	node: uuid::Uuid,
+) -> Result<Option<PubSourceInfo>, ServerFnError> {
+	let _ = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
+	let db = extract_db()?;
+
+	get_source_info_internal(&db, node)
+		.await
+		.map_err(make_server_err)
+}
+
+#[server]
+pub async fn get_download_job(
+	job: uuid::Uuid,
+) -> Result<Option<PubDownloadJob>, ServerFnError> {
+	let (user_id, _) = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
+	let db = extract_db()?;
+
+	get_download_job_internal(&db, user_id, job)
+		.await
+		.map_err(make_server_err)
+}
+
+#[server]
+pub async fn get_download_jobs(
+	limit: Option<i64>,
+) -> Result<Vec<PubDownloadJob>, ServerFnError> {
+	let (user_id, _) = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
+	let db = extract_db()?;
+
+	get_download_jobs_internal(&db, user_id, limit.unwrap_or(consts::DEFAULT_JOB_LIMIT))
+		.await
+		.map_err(make_server_err)
+}
+
+/// cancel a queued, running or expanded download, running ones are stopped and their partial files removed
+#[server]
+pub async fn cancel_download(
+	job: uuid::Uuid,
+) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_state;
+	let (user_id, _) = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
+	let state = extract_state()?;
+
+	let running = cancel_download_job_internal(&state.db_pool, user_id, job)
+		.await
+		.map_err(make_server_err)?
+	;
+	for job in running {
+		state.dl_context.cancel_job(job);
 	}
 
-	Ok(media_file_id)
-}
\ No newline at end of file
+	Ok(())
+}
+
+#[server]
+pub async fn retry_download(
+	job: uuid::Uuid,
+) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_state;
+	let (user_id, _) = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
+	let state = extract_state()?;
+
+	retry_download_job_internal(&state.db_pool, user_id, job)
+		.await
+		.map_err(make_server_err)?
+	;
+	state.dl_context.notify_job();
+
+	Ok(())
+}
+
+#[server]
+pub async fn get_subscriptions() -> Result<Vec<PubSubscription>, ServerFnError> {
+	let (user_id, _) = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
+	let db = extract_db()?;
+
+	get_subscriptions_internal(&db, user_id)
+		.await
+		.map_err(make_server_err)
+}
+
+/// subscribe to a channel or playlist, entries uploaded after the first check are downloaded into the target folder
+#[server]
+pub async fn create_subscription(
+	args: PubSubscriptionArgs,
+	vfs_target: Option<VfsTarget>,
+) -> Result<uuid::Uuid, ServerFnError> {
+	use crate::app::state::server::extract_state;
+	let (user_id, _) = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
+	let state = extract_state()?;
+
+	let subscription = create_subscription_internal(&state.db_pool, user_id, args, vfs_target)
+		.await
+		.map_err(make_server_err)?
+	;
+	state.dl_context.notify_subscriptions();
+
+	Ok(subscription)
+}
+
+#[server]
+pub async fn delete_subscription(
+	subscription: uuid::Uuid,
+) -> Result<(), ServerFnError> {
+	let (user_id, _) = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
+	let db = extract_db()?;
+
+	delete_subscription_internal(&db, user_id, subscription)
+		.await
+		.map_err(make_server_err)
+}
+
+#[server]
+pub async fn set_subscription_enabled(
+	subscription: uuid::Uuid,
+	enabled: bool,
+) -> Result<(), ServerFnError> {
+	let (user_id, _) = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
+	let db = extract_db()?;
+
+	set_subscription_enabled_internal(&db, user_id, subscription, enabled)
+		.await
+		.map_err(make_server_err)
+}
+
+#[server]
+pub async fn check_subscription_now(
+	subscription: uuid::Uuid,
+) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_state;
+	let (user_id, _) = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
+	let state = extract_state()?;
+
+	check_subscription_now_internal(&state.db_pool, user_id, subscription)
+		.await
+		.map_err(make_server_err)?
+	;
+	state.dl_context.notify_subscriptions();
+
+	Ok(())
+}
+
+/// the download limits of the logged in user and how much of them is used up
+#[server]
+pub async fn get_download_quota() -> Result<PubDownloadQuota, ServerFnError> {
+	let (user_id, _) = require_scoped_permission(ApiScope::Downloads, Permission::Download).await?;
+	let db = extract_db()?;
+
+	get_download_quota_internal(&db, user_id)
+		.await
+		.map_err(make_server_err)
+}
+
+#[server]
+pub async fn get_level_quotas() -> Result<Vec<PubLevelQuota>, ServerFnError> {
+	require_permission(Permission::ManageUsers).await?;
+	let db = extract_db()?;
+
+	get_level_quotas_internal(&db)
+		.await
+		.map_err(make_server_err)
+}
+
+#[server]
+pub async fn set_level_quota(
+	level_id: i16,
+	limits: PubDownloadLimits,
+) -> Result<(), ServerFnError> {
+	require_permission(Permission::ManageUsers).await?;
+	let db = extract_db()?;
+
+	set_level_quota_internal(&db, level_id, limits)
+		.await
+		.map_err(make_server_err)
+}
+
+#[server]
+pub async fn get_user_quotas() -> Result<Vec<PubUserQuota>, ServerFnError> {
+	require_permission(Permission::ManageUsers).await?;
+	let db = extract_db()?;
+
+	get_user_quotas_internal(&db)
+		.await
+		.map_err(make_server_err)
+}
+
+/// override the limits of a single user, `None` removes the overrides
+#[server]
+pub async fn set_user_quota(
+	user_id: i32,
+	overrides: Option<PubDownloadLimits>,
+) -> Result<(), ServerFnError> {
+	let (admin_id, _) = require_permission(Permission::ManageUsers).await?;
+	let db = extract_db()?;
+
+	check_role_grantable(&db, admin_id, get_user_role_id(&db, user_id).await?).await?;
+	set_user_quota_internal(&db, user_id, overrides)
+		.await
+		.map_err(make_server_err)
+}
+
+/// the cookies and logins the user's downloads authenticate with, without their secrets
+#[server]
+pub async fn get_download_credentials() -> Result<Vec<PubDownloadCredential>, ServerFnError> {
+	let (user_id, _) = require_permission(Permission::Download).await?;
+	let db = extract_db()?;
+
+	get_download_credentials_internal(&db, user_id)
+		.await
+		.map_err(make_server_err)
+}
+
+/// store a Netscape cookies file used for downloads from the site
+#[server]
+pub async fn set_download_cookies(
+	site: String,
+	cookies: String,
+) -> Result<uuid::Uuid, ServerFnError> {
+	let (user_id, _) = require_permission(Permission::Download).await?;
+	let db = extract_db()?;
+
+	set_download_cookies_internal(&db, user_id, &site, &cookies)
+		.await
+		.map_err(make_server_err)
+}
+
+/// store the login yt-dlp uses for downloads from the site
+#[server]
+pub async fn set_download_login(
+	site: String,
+	username: String,
+	password: String,
+) -> Result<uuid::Uuid, ServerFnError> {
+	let (user_id, _) = require_permission(Permission::Download).await?;
+	let db = extract_db()?;
+
+	set_download_login_internal(&db, user_id, &site, username, &password)
+		.await
+		.map_err(make_server_err)
+}
+
+#[server]
+pub async fn delete_download_credential(
+	credential: uuid::Uuid,
+) -> Result<(), ServerFnError> {
+	let (user_id, _) = require_permission(Permission::Download).await?;
+	let db = extract_db()?;
+
+	delete_download_credential_internal(&db, user_id, credential)
+		.await
+		.map_err(make_server_err)
+}
@@ -0,0 +1,341 @@
+use std::{io::Write, path::Path};
+
+use chacha20poly1305::{aead::{Aead, AeadCore, KeyInit, OsRng, Payload}, ChaCha20Poly1305, Key, Nonce};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+
+use super::prelude::*;
+use crate::app::media_request::YtdlAuth;
+
+mod consts {
+	/// any passphrase works, it is hashed into the key
+	pub const SECRET_KEY_ENV: &str = "THRW_SECRET_KEY";
+
+	pub const MAX_COOKIES_LEN: usize = 1024 * 1024;
+	pub const MAX_LOGIN_LEN: usize = 256;
+	pub const MAX_SITE_LEN: usize = 253;
+	/// yt-dlp expects this header on top of a cookies file
+	pub const COOKIES_HEADER: &str = "# Netscape HTTP Cookie File";
+	/// cookies that are hidden from scripts are exported with this prefix instead of being commented out
+	pub const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+	pub const COOKIE_FIELDS: usize = 7;
+	pub const NONCE_LEN: usize = 12;
+
+	pub const COOKIES_FILENAME: &str = "site-cookies.txt";
+	pub const CONFIG_FILENAME: &str = "site-login.conf";
+}
+
+/// a credential as stored, the secret is still encrypted
+struct StoredCredential {
+	kind: String,
+	site: String,
+	username: Option<String>,
+	nonce: Vec<u8>,
+	secret: Vec<u8>,
+}
+
+fn get_cipher() -> Result<ChaCha20Poly1305, DownloaderError> {
+	let secret_key = std::env::var(consts::SECRET_KEY_ENV)
+		.ok()
+		.filter(|key| !key.trim().is_empty())
+		.ok_or(DownloaderError::SecretsUnavailable)?
+	;
+	let key = Sha256::digest(secret_key.as_bytes());
+	Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+/// ties a secret to its owner and site, so a row copied over to someone else does not decrypt
+fn associated_data(user_id: i32, kind: PubCredentialKind, site: &str) -> Vec<u8> {
+	format!("{user_id}:{}:{site}", kind.to_string()).into_bytes()
+}
+
+/// the nonce along with the encrypted secret
+fn encrypt_secret(
+	user_id: i32,
+	kind: PubCredentialKind,
+	site: &str,
+	secret: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), DownloaderError> {
+	let cipher = get_cipher()?;
+	let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+	let aad = associated_data(user_id, kind, site);
+	let encrypted = cipher.encrypt(&nonce, Payload { msg: secret, aad: &aad })
+		.map_err(|_| DownloaderError::SecretsCorrupt)?
+	;
+	Ok((nonce.to_vec(), encrypted))
+}
+
+fn decrypt_secret(
+	user_id: i32,
+	credential: &StoredCredential,
+) -> Result<Vec<u8>, DownloaderError> {
+	let cipher = get_cipher()?;
+	if credential.nonce.len() != consts::NONCE_LEN {
+		return Err(DownloaderError::SecretsCorrupt);
+	}
+	let kind = PubCredentialKind::from(credential.kind.clone());
+	let aad = associated_data(user_id, kind, &credential.site);
+	cipher.decrypt(Nonce::from_slice(&credential.nonce), Payload { msg: &credential.secret, aad: &aad })
+		.map_err(|_| DownloaderError::SecretsCorrupt)
+}
+
+/// the host of a url without port and credentials, lowercase
+fn url_host(url: &str) -> Option<String> {
+	let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+	let authority = rest.split(['/', '?', '#']).next()?;
+	let host = authority.rsplit_once('@').map(|(_, host)| host).unwrap_or(authority);
+	let host = host.split(':').next()?.trim_end_matches('.');
+	(!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+/// accepts bare domains as well as urls, `www.` is dropped so the credential covers the whole site
+fn normalize_site(site: &str) -> Result<String, DownloaderError> {
+	let site = url_host(site.trim())
+		.ok_or(DownloaderError::InvalidCredential("the site is empty".to_string()))?
+	;
+	let site = site.strip_prefix("www.").unwrap_or(&site).trim_start_matches('.').to_string();
+	if site.len() > consts::MAX_SITE_LEN
+		|| !site.contains('.')
+		|| !site.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+	{
+		return Err(DownloaderError::InvalidCredential(format!("'{site}' is no domain")));
+	}
+	Ok(site)
+}
+
+fn site_matches(site: &str, host: &str) -> bool {
+	host == site || host.ends_with(&format!(".{site}"))
+}
+
+/// check the cookies are in the netscape format yt-dlp reads, returning them with the header it expects
+fn validate_cookies(cookies: &str) -> Result<String, DownloaderError> {
+	if cookies.len() > consts::MAX_COOKIES_LEN {
+		return Err(DownloaderError::InvalidCredential("the cookies file is too large".to_string()));
+	}
+
+	let mut count = 0;
+	for (index, line) in cookies.lines().enumerate() {
+		let line = line.trim_end_matches('\r');
+		let cookie = line.strip_prefix(consts::HTTP_ONLY_PREFIX).unwrap_or(line);
+		if cookie.trim().is_empty() || cookie.starts_with('#') {
+			continue;
+		}
+		if cookie.split('\t').count() != consts::COOKIE_FIELDS {
+			return Err(DownloaderError::InvalidCredential(format!("line {} is no netscape cookie", index + 1)));
+		}
+		count += 1;
+	}
+	if count == 0 {
+		return Err(DownloaderError::InvalidCredential("the cookies file has no cookies".to_string()));
+	}
+
+	match cookies.starts_with(consts::COOKIES_HEADER) {
+		true => Ok(cookies.to_string()),
+		false => Ok(format!("{}\n{cookies}", consts::COOKIES_HEADER)),
+	}
+}
+
+fn validate_login_part(value: &str, what: &str) -> Result<(), DownloaderError> {
+	if value.is_empty() || value.len() > consts::MAX_LOGIN_LEN {
+		return Err(DownloaderError::InvalidCredential(format!("the {what} has to be 1 to {} characters long", consts::MAX_LOGIN_LEN)));
+	}
+	// each option takes up a line of the config file
+	if value.contains(['\n', '\r', '\0']) {
+		return Err(DownloaderError::InvalidCredential(format!("the {what} can't span lines")));
+	}
+	Ok(())
+}
+
+/// quote a value for yt-dlp's config file, which is split like a shell command line
+fn quote_config_value(value: &str) -> String {
+	format!("'{}'", value.replace('\'', r#"'"'"'"#))
+}
+
+async fn store_credential(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	kind: PubCredentialKind,
+	site: String,
+	username: Option<String>,
+	secret: &[u8],
+) -> Result<uuid::Uuid, DownloaderError> {
+	let (nonce, secret) = encrypt_secret(user_id, kind, &site, secret)?;
+
+	sqlx::query!("
+		INSERT INTO download_credentials
+			(user_id, kind, site, username, nonce, secret)
+		VALUES
+			($1, $2, $3, $4, $5, $6)
+		ON CONFLICT (user_id, kind, site) DO UPDATE
+		SET username	= EXCLUDED.username,
+			nonce		= EXCLUDED.nonce,
+			secret		= EXCLUDED.secret,
+			created_at	= now()
+		RETURNING id
+		;",
+		user_id,
+		kind.to_string(),
+		site,
+		username,
+		nonce,
+		secret
+	)
+		.fetch_one(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+		.map(|rec| rec.id)
+}
+
+/// replaces the cookies stored for the site
+pub async fn set_download_cookies_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	site: &str,
+	cookies: &str,
+) -> Result<uuid::Uuid, DownloaderError> {
+	let site = normalize_site(site)?;
+	let cookies = validate_cookies(cookies)?;
+	store_credential(db_pool, user_id, PubCredentialKind::Cookies, site, None, cookies.as_bytes()).await
+}
+
+/// replaces the login stored for the site
+pub async fn set_download_login_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	site: &str,
+	username: String,
+	password: &str,
+) -> Result<uuid::Uuid, DownloaderError> {
+	let site = normalize_site(site)?;
+	validate_login_part(&username, "username")?;
+	validate_login_part(password, "password")?;
+	store_credential(db_pool, user_id, PubCredentialKind::Login, site, Some(username), password.as_bytes()).await
+}
+
+pub async fn get_download_credentials_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+) -> Result<Vec<PubDownloadCredential>, DownloaderError> {
+	let recs = sqlx::query!("
+		SELECT id, kind, site, username, created_at
+		FROM download_credentials
+		WHERE user_id = $1
+		ORDER BY site, kind
+		;",
+		user_id
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	Ok(recs
+		.into_iter()
+		.map(|rec| PubDownloadCredential {
+			id: rec.id,
+			site: rec.site,
+			kind: rec.kind.into(),
+			username: rec.username,
+			created_at: rec.created_at,
+		})
+		.collect()
+	)
+}
+
+pub async fn delete_download_credential_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	credential: uuid::Uuid,
+) -> Result<(), DownloaderError> {
+	let res = sqlx::query!("
+		DELETE FROM download_credentials
+		WHERE user_id = $1
+		  AND id = $2
+		;",
+		user_id,
+		credential
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	match res.rows_affected() {
+		0 => Err(DownloaderError::NoCredential),
+		_ => Ok(()),
+	}
+}
+
+/// create a file only the server's user can read
+fn write_private_file(path: &Path, contents: &[u8]) -> Result<(), DownloaderError> {
+	let mut options = std::fs::OpenOptions::new();
+	options.write(true).create_new(true);
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+	options.open(path)
+		.and_then(|mut file| file.write_all(contents))
+		.map_err(DownloaderError::Io)
+}
+
+/// the most specific credential of each kind the user stored for the host of the url
+fn pick_credentials(credentials: Vec<StoredCredential>, host: &str) -> Vec<StoredCredential> {
+	let mut picked: Vec<StoredCredential> = vec![];
+	for credential in credentials.into_iter().filter(|credential| site_matches(&credential.site, host)) {
+		match picked.iter_mut().find(|other| other.kind == credential.kind) {
+			Some(other) if other.site.len() < credential.site.len() => *other = credential,
+			Some(_) => (),
+			None => picked.push(credential),
+		}
+	}
+	picked
+}
+
+/// write the user's credentials for the url into the job directory, nothing is written for other sites
+pub async fn write_ytdl_auth(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	url: &str,
+	job_dir: &Path,
+) -> Result<YtdlAuth, DownloaderError> {
+	let Some(host) = url_host(url) else {
+		return Ok(YtdlAuth::default());
+	};
+	let credentials = sqlx::query_as!(
+		StoredCredential,
+		"SELECT kind, site, username, nonce, secret
+		FROM download_credentials
+		WHERE user_id = $1
+		;",
+		user_id
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	let mut auth = YtdlAuth::default();
+	for credential in pick_credentials(credentials, &host) {
+		let secret = decrypt_secret(user_id, &credential)?;
+		match PubCredentialKind::from(credential.kind.clone()) {
+			PubCredentialKind::Cookies => {
+				let path = job_dir.join(consts::COOKIES_FILENAME);
+				write_private_file(&path, &secret)?;
+				auth.cookies = Some(path);
+			},
+			PubCredentialKind::Login => {
+				let password = String::from_utf8(secret).map_err(|_| DownloaderError::SecretsCorrupt)?;
+				let config = format!(
+					"--username {}\n--password {}\n",
+					quote_config_value(&credential.username.unwrap_or_default()),
+					quote_config_value(&password),
+				);
+				let path = job_dir.join(consts::CONFIG_FILENAME);
+				write_private_file(&path, config.as_bytes())?;
+				auth.config = Some(path);
+			},
+		}
+		println!("using the {} stored for {} in '{job_dir:?}'", credential.kind, credential.site);
+	}
+	Ok(auth)
+}
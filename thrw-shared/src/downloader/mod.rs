@@ -1,17 +1,494 @@
 pub mod api;
 #[cfg(feature = "server")]
 pub mod util;
+#[cfg(feature = "server")]
+pub mod subscription;
+#[cfg(feature = "server")]
+pub mod provenance;
+#[cfg(feature = "server")]
+pub mod quota;
+#[cfg(feature = "server")]
+pub mod postprocess;
+#[cfg(feature = "server")]
+pub mod credentials;
 
 pub mod shared {
+	use serde::{Deserialize, Serialize};
+
+	#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+	pub enum PubDownloadState {
+		Queued,
+		Running,
+		Completed,
+		Failed,
+		Cancelled,
+		/// a playlist or channel that was split into one child job per entry
+		Expanded,
+	}
+	impl PubDownloadState {
+		pub fn can_cancel(&self) -> bool {
+			matches!(self, Self::Queued | Self::Running | Self::Expanded)
+		}
+
+		pub fn can_retry(&self) -> bool {
+			matches!(self, Self::Failed | Self::Cancelled)
+		}
+	}
+	impl ToString for PubDownloadState {
+		fn to_string(&self) -> String {
+			match self {
+				PubDownloadState::Queued => "queued",
+				PubDownloadState::Running => "running",
+				PubDownloadState::Completed => "completed",
+				PubDownloadState::Failed => "failed",
+				PubDownloadState::Cancelled => "cancelled",
+				PubDownloadState::Expanded => "expanded",
+			}.to_string()
+		}
+	}
+	impl From<String> for PubDownloadState {
+		fn from(value: String) -> Self {
+			match value.as_str() {
+				"running" => Self::Running,
+				"completed" => Self::Completed,
+				"failed" => Self::Failed,
+				"cancelled" => Self::Cancelled,
+				"expanded" => Self::Expanded,
+				_ => Self::Queued,
+			}
+		}
+	}
+
+	/// state counts of the child jobs of an expanded playlist
+	#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+	pub struct PubPlaylistProgress {
+		pub total: i64,
+		pub completed: i64,
+		pub failed: i64,
+		/// queued or running
+		pub pending: i64,
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubDownloadJob {
+		pub id: uuid::Uuid,
+		pub url: String,
+		/// the entry title for playlist children, the playlist title for expanded jobs
+		pub title: Option<String>,
+		pub parent_job: Option<uuid::Uuid>,
+		/// 1-based position in the parent playlist
+		pub playlist_index: Option<i32>,
+		/// only set for expanded jobs
+		pub playlist: Option<PubPlaylistProgress>,
+		/// the subscription that queued this job
+		pub subscription: Option<uuid::Uuid>,
+		pub audio_only: bool,
+		pub state: PubDownloadState,
+		/// the error of the most recent failed attempt
+		pub error: Option<String>,
+		pub retry_count: i32,
+		/// when a scheduled retry becomes eligible to run
+		pub next_attempt_at: Option<chrono::DateTime<chrono::Utc>>,
+		/// the downloaded media, once the job completed
+		pub media_file: Option<uuid::Uuid>,
+		pub created_at: chrono::DateTime<chrono::Utc>,
+		pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+		pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+	pub enum PubDownloadPhase {
+		Queued,
+		Downloading,
+		/// a yt-dlp post processor is running, e.g. `Merger` or `ExtractAudio`
+		PostProcessing(String),
+		/// the files are being moved into the vfs
+		Importing,
+		Completed,
+		Failed(String),
+		Cancelled,
+		/// the attempt failed for a transient reason and is queued again after a delay in seconds
+		RetryScheduled(i64),
+		/// the job was split into child jobs, reported again for the parent whenever a child finishes
+		Playlist(PubPlaylistProgress),
+	}
+	impl PubDownloadPhase {
+		/// whether the current attempt is over
+		pub fn is_finished(&self) -> bool {
+			matches!(self, Self::Completed | Self::Failed(_) | Self::Cancelled | Self::RetryScheduled(_) | Self::Playlist(_))
+		}
+	}
+
+	/// live progress of a running job, pushed to the requesting user
+	#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+	pub struct PubDownloadProgress {
+		pub job_id: uuid::Uuid,
+		pub phase: PubDownloadPhase,
+		/// progress of the current file in 0..=100
+		pub percent: Option<f64>,
+		/// bytes per second
+		pub speed: Option<f64>,
+		/// seconds left for the current file
+		pub eta: Option<i64>,
+	}
+	impl PubDownloadProgress {
+		pub fn phase(job_id: uuid::Uuid, phase: PubDownloadPhase) -> Self {
+			Self {
+				job_id,
+				phase,
+				percent: None,
+				speed: None,
+				eta: None,
+			}
+		}
+	}
+
+	#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+	pub enum PubContainer {
+		Mp4,
+		Webm,
+		Mkv,
+	}
+	impl PubContainer {
+		pub const ALL: [Self; 3] = [Self::Mp4, Self::Webm, Self::Mkv];
+	}
+	impl ToString for PubContainer {
+		fn to_string(&self) -> String {
+			match self {
+				PubContainer::Mp4 => "mp4",
+				PubContainer::Webm => "webm",
+				PubContainer::Mkv => "mkv",
+			}.to_string()
+		}
+	}
+
+	/// codecs yt-dlp can extract audio to
+	#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+	pub enum PubAudioCodec {
+		/// keep whatever the source provides
+		Best,
+		Aac,
+		Flac,
+		M4a,
+		Mp3,
+		Opus,
+		Vorbis,
+		Wav,
+	}
+	impl PubAudioCodec {
+		pub const ALL: [Self; 8] = [Self::Best, Self::Aac, Self::Flac, Self::M4a, Self::Mp3, Self::Opus, Self::Vorbis, Self::Wav];
+	}
+	impl ToString for PubAudioCodec {
+		fn to_string(&self) -> String {
+			match self {
+				PubAudioCodec::Best => "best",
+				PubAudioCodec::Aac => "aac",
+				PubAudioCodec::Flac => "flac",
+				PubAudioCodec::M4a => "m4a",
+				PubAudioCodec::Mp3 => "mp3",
+				PubAudioCodec::Opus => "opus",
+				PubAudioCodec::Vorbis => "vorbis",
+				PubAudioCodec::Wav => "wav",
+			}.to_string()
+		}
+	}
+
+	/// how a download is picked and converted, unset options leave the choice to yt-dlp
+	#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+	pub struct PubDownloadFormat {
+		/// a yt-dlp format selector, e.g. from [`PubAvailableFormat::selector`], takes precedence over the max height
+		pub format_id: Option<String>,
+		pub max_height: Option<i32>,
+		pub container: Option<PubContainer>,
+		/// only used for audio extraction
+		pub audio_codec: Option<PubAudioCodec>,
+		/// in kbit/s, only used for audio extraction
+		pub audio_bitrate: Option<i32>,
+		pub embed_thumbnail: bool,
+		pub embed_metadata: bool,
+	}
+
+	/// a format yt-dlp offers for a url
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubAvailableFormat {
+		pub format_id: String,
+		pub ext: Option<String>,
+		pub width: Option<i32>,
+		pub height: Option<i32>,
+		pub fps: Option<f64>,
+		/// `None` for formats without video
+		pub vcodec: Option<String>,
+		/// `None` for formats without audio
+		pub acodec: Option<String>,
+		/// in bytes, possibly approximated
+		pub filesize: Option<i64>,
+		/// total bitrate in kbit/s
+		pub tbr: Option<f64>,
+		pub note: Option<String>,
+	}
+	impl PubAvailableFormat {
+		/// the format selector downloading this format, video only formats get the best audio merged in
+		pub fn selector(&self) -> String {
+			match (&self.vcodec, &self.acodec) {
+				(Some(_), None) => format!("{0}+bestaudio/{0}", self.format_id),
+				_ => self.format_id.clone(),
+			}
+		}
+	}
+
+	/// a step run on a download once it is in the vfs
+	#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+	pub enum PubPostStep {
+		/// re-encode into an audio only file, the bitrate is in kbit/s
+		ConvertAudio {
+			codec: PubAudioCodec,
+			bitrate: Option<i32>,
+		},
+		/// cut silence off the start and the end, only audio files are trimmed
+		TrimSilence,
+		/// cut out the segments SponsorBlock knows of, only youtube videos have them
+		RemoveSponsors,
+		/// move the file into a folder named after its uploader, next to where it was downloaded to
+		MoveByUploader,
+	}
+	impl PubPostStep {
+		/// whether the step produces a new file through ffmpeg rather than acting on the vfs
+		pub fn is_transform(&self) -> bool {
+			!matches!(self, Self::MoveByUploader)
+		}
+	}
+	impl ToString for PubPostStep {
+		fn to_string(&self) -> String {
+			match self {
+				PubPostStep::ConvertAudio { codec, bitrate: Some(bitrate) } => format!("{} {bitrate}k", codec.to_string()),
+				PubPostStep::ConvertAudio { codec, bitrate: None } => codec.to_string(),
+				PubPostStep::TrimSilence => "silence trimmed".to_string(),
+				PubPostStep::RemoveSponsors => "sponsors removed".to_string(),
+				PubPostStep::MoveByUploader => "moved by uploader".to_string(),
+			}
+		}
+	}
+
+	/// where the file the transforms produced ends up
+	#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+	pub enum PubPostOutput {
+		/// a new node next to the download, named after the steps
+		#[default]
+		Sibling,
+		/// the node of the download shows the processed file from then on
+		Version,
+	}
+
+	/// steps run in order after a download was imported
+	#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+	pub struct PubPostProcessing {
+		pub steps: Vec<PubPostStep>,
+		pub output: PubPostOutput,
+	}
+
+	/// where a downloaded file came from
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubSourceInfo {
+		/// the yt-dlp extractor, e.g. `Youtube`
+		pub extractor: String,
+		/// the id of the media on its site
+		pub source_id: String,
+		pub webpage_url: Option<String>,
+		pub uploader: Option<String>,
+		pub upload_date: Option<chrono::NaiveDate>,
+		pub description: Option<String>,
+		pub downloaded_at: chrono::DateTime<chrono::Utc>,
+	}
+
+	/// an earlier download of the same source
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubExistingDownload {
+		pub file: uuid::Uuid,
+		/// one of the nodes showing the file
+		pub node: uuid::Uuid,
+		/// vfs path of that node
+		pub path: String,
+		pub audio_only: bool,
+		pub downloaded_at: chrono::DateTime<chrono::Utc>,
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub enum PubDownloadRequest {
+		Queued(uuid::Uuid),
+		/// the source was downloaded before, nothing was queued
+		AlreadyDownloaded(Vec<PubExistingDownload>),
+	}
+
+	/// which entries of a subscription get downloaded
+	#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+	pub struct PubSubscriptionFilter {
+		/// in seconds, entries without a known duration always pass
+		pub max_duration: Option<i32>,
+		/// only entries with a matching title are downloaded
+		pub title_regex: Option<String>,
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubSubscriptionArgs {
+		pub url: String,
+		pub audio_only: bool,
+		pub filter: PubSubscriptionFilter,
+		/// in seconds
+		pub check_interval: i32,
+		/// run on every entry the subscription downloads
+		pub post_processing: PubPostProcessing,
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubSubscription {
+		pub id: uuid::Uuid,
+		pub url: String,
+		/// the channel or playlist title, known after the first check
+		pub title: Option<String>,
+		/// vfs path of the folder new entries are downloaded into
+		pub target: Option<String>,
+		pub audio_only: bool,
+		pub filter: PubSubscriptionFilter,
+		/// in seconds
+		pub check_interval: i32,
+		pub post_processing: PubPostProcessing,
+		pub enabled: bool,
+		pub last_checked_at: Option<chrono::DateTime<chrono::Utc>>,
+		pub next_check_at: chrono::DateTime<chrono::Utc>,
+		/// the error of the most recent check, if it failed
+		pub last_error: Option<String>,
+		pub created_at: chrono::DateTime<chrono::Utc>,
+	}
+
+	/// download limits, `None` is unlimited for a level and falls back to the level for a user
+	#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+	pub struct PubDownloadLimits {
+		/// jobs downloading at the same time, further jobs wait in the queue
+		pub max_concurrent: Option<i32>,
+		/// downloads requested in the last 24 hours, the entries of a playlist don't count on their own
+		pub max_daily_jobs: Option<i32>,
+		/// bytes of downloaded media in the vfs
+		pub max_total_bytes: Option<i64>,
+		pub max_file_bytes: Option<i64>,
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+	pub struct PubDownloadUsage {
+		pub running: i64,
+		/// downloads requested in the last 24 hours
+		pub daily_jobs: i64,
+		pub total_bytes: i64,
+	}
+
+	/// the limits that apply to a user along with what they used up
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubDownloadQuota {
+		pub limits: PubDownloadLimits,
+		pub usage: PubDownloadUsage,
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubLevelQuota {
+		pub level_id: i16,
+		pub level_name: String,
+		pub limits: PubDownloadLimits,
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubUserQuota {
+		pub user_id: i32,
+		pub email: String,
+		pub level_name: String,
+		/// the limits set for the user alone
+		pub overrides: PubDownloadLimits,
+		pub quota: PubDownloadQuota,
+	}
+
+	#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+	pub enum PubCredentialKind {
+		/// a netscape cookies file exported from a logged in browser
+		Cookies,
+		/// username and password, for the sites yt-dlp can log in to itself
+		Login,
+	}
+	impl ToString for PubCredentialKind {
+		fn to_string(&self) -> String {
+			match self {
+				PubCredentialKind::Cookies => "cookies",
+				PubCredentialKind::Login => "login",
+			}.to_string()
+		}
+	}
+	impl From<String> for PubCredentialKind {
+		fn from(value: String) -> Self {
+			match value.as_str() {
+				"login" => Self::Login,
+				_ => Self::Cookies,
+			}
+		}
+	}
+
+	/// a stored site login, the secret part never leaves the server
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubDownloadCredential {
+		pub id: uuid::Uuid,
+		/// the domain the credential is used for, subdomains included
+		pub site: String,
+		pub kind: PubCredentialKind,
+		pub username: Option<String>,
+		pub created_at: chrono::DateTime<chrono::Utc>,
+	}
+
 	#[derive(Debug)]
 	pub enum DownloaderError {
 		YtdlInitError,
 		YtdlNotSingle,
 		NoTempFile,
+		/// the job does not exist or is in a state that doesn't allow the action
+		InvalidJobState,
+		Cancelled,
+		EmptyPlaylist,
+		/// subscriptions have to point at a playlist or channel
+		NotPlaylist,
+		/// the subscription does not exist or belongs to someone else
+		NoSubscription,
+		/// the check interval is too short or the title regex does not compile
+		InvalidSubscription(String),
+		/// the format options can't be passed to yt-dlp
+		InvalidFormat(String),
+		/// the user reached one of their download limits
+		QuotaExceeded(String),
+		/// a download limit is negative or zero where that makes no sense
+		InvalidQuota(String),
+		/// a post-processing step can't be run as configured
+		InvalidPostProcessing(String),
+		/// ffmpeg exited unsuccessfully with the given code and the end of its stderr
+		FfmpegProcess(Option<i32>, String),
+		/// the site, cookies file or login is malformed
+		InvalidCredential(String),
+		/// the credential does not exist or belongs to someone else
+		NoCredential,
+		/// no secret key is configured, so credentials can't be stored or read
+		SecretsUnavailable,
+		/// a stored credential could not be encrypted or decrypted, e.g. after the secret key changed
+		SecretsCorrupt,
 		#[cfg(feature = "server")]
 		Ytdl(youtube_dl::Error),
+		/// yt-dlp exited unsuccessfully with the given code and the end of its stderr
+		YtdlProcess(Option<i32>, String),
+		/// a direct download was answered with this status
+		HttpStatus(u16),
+		/// a direct download failed with the given message, and whether retrying it could help
+		Http(String, bool),
+		/// the file is larger than the limit of this many bytes
+		TooLarge(u64),
+		/// the downloaded file is neither media, an image nor a document
+		UnsupportedType(String),
 		Io(std::io::Error),
-		Media(crate::media::shared::MediaError)
+		Media(crate::media::shared::MediaError),
+		Vfs(crate::vfs::shared::VFSError),
+		#[cfg(feature = "server")]
+		Sql(sqlx::Error),
 	}
 }
 
@@ -21,4 +498,14 @@ pub mod prelude {
 	pub use super::shared::*;
 	#[cfg(feature = "server")]
 	pub use super::util::*;
-}
\ No newline at end of file
+	#[cfg(feature = "server")]
+	pub use super::subscription::*;
+	#[cfg(feature = "server")]
+	pub use super::provenance::*;
+	#[cfg(feature = "server")]
+	pub use super::quota::*;
+	#[cfg(feature = "server")]
+	pub use super::postprocess::*;
+	#[cfg(feature = "server")]
+	pub use super::credentials::*;
+}
@@ -0,0 +1,186 @@
+use std::path::PathBuf;
+
+use sqlx::{Pool, Postgres};
+
+use super::prelude::*;
+use super::util::values::AUDIO_BITRATE_RANGE;
+use crate::vfs::prelude::*;
+use crate::vfs::shared::VfsTarget;
+
+mod consts {
+	/// every transform runs ffmpeg over the whole file once
+	pub const MAX_POST_STEPS: usize = 8;
+}
+
+/// a committed file along with the node showing it
+#[derive(Debug, Clone, Copy)]
+pub struct PostProcessed {
+	pub file: uuid::Uuid,
+	pub node: uuid::Uuid,
+}
+
+pub fn validate_post_processing(post_processing: &PubPostProcessing) -> Result<(), DownloaderError> {
+	if post_processing.steps.len() > consts::MAX_POST_STEPS {
+		return Err(DownloaderError::InvalidPostProcessing(format!("at most {} steps are allowed", consts::MAX_POST_STEPS)));
+	}
+	for step in &post_processing.steps {
+		let PubPostStep::ConvertAudio { codec, bitrate } = step else {
+			continue;
+		};
+		if *codec == PubAudioCodec::Best {
+			return Err(DownloaderError::InvalidPostProcessing("pick a codec to convert to".to_string()));
+		}
+		if let Some(bitrate) = bitrate
+		&& !AUDIO_BITRATE_RANGE.contains(bitrate) {
+			return Err(DownloaderError::InvalidPostProcessing(format!("audio bitrate has to be within {:?} kbit/s", AUDIO_BITRATE_RANGE)));
+		}
+	}
+	Ok(())
+}
+
+/// the first node that was created for the file
+pub async fn get_file_node(
+	db_pool: &Pool<Postgres>,
+	file_id: uuid::Uuid,
+) -> Result<uuid::Uuid, DownloaderError> {
+	sqlx::query!("
+		SELECT id
+		FROM vfs_nodes
+		WHERE vfs_file = $1
+		ORDER BY created_at
+		LIMIT 1
+		;",
+		file_id
+	)
+		.fetch_optional(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+		.map(|rec| rec.id)
+		.ok_or(DownloaderError::Vfs(VFSError::NotFound))
+}
+
+/// the processed file shares the thumbnail of the one it was made of
+async fn copy_thumbnail(
+	db_pool: &Pool<Postgres>,
+	from_file: uuid::Uuid,
+	to_file: uuid::Uuid,
+) -> Result<(), DownloaderError> {
+	sqlx::query!("
+		INSERT INTO vfs_thumbs
+			(id, thumbnail)
+		SELECT $2, thumbnail
+		FROM vfs_thumbs
+		WHERE id = $1
+		ON CONFLICT (id) DO NOTHING
+		;",
+		from_file,
+		to_file
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+		.map(|_| ())
+}
+
+/// commit the output of the transforms next to the file they were run on, or as a new version of its node
+///
+/// tracks and chapters are left behind, their timings don't have to match the processed file
+pub async fn commit_post_processed(
+	db_pool: &Pool<Postgres>,
+	source: PostProcessed,
+	path: PathBuf,
+	label: &str,
+	output: PubPostOutput,
+) -> Result<PostProcessed, DownloaderError> {
+	let node = get_vfs_node_data(db_pool, source.node)
+		.await
+		.map_err(DownloaderError::Vfs)?
+	;
+	let file_size = std::fs::metadata(&path)
+		.map_err(DownloaderError::Io)?
+		.len()
+	;
+	let file = FileRef {
+		path,
+		file_size: file_size as i64,
+	};
+
+	// a node of the same name under the same parent is pointed at the new file
+	let name = match output {
+		PubPostOutput::Version => node.node_name,
+		PubPostOutput::Sibling => format!("{} ({label})", node.node_name),
+	};
+	let file_data = VfsFileData {
+		name,
+		file: file.clone(),
+		file_type: get_file_type(file, infer::MatcherType::Audio)
+			.await
+			.map_err(DownloaderError::Media)?,
+		hide: node.hide,
+	};
+	let (file_id, node_id) = commit_file_to_vfs(
+		file_data,
+		db_pool,
+		node.parent_id.map(VfsTarget::Node)
+	)
+		.await
+		.map_err(DownloaderError::Vfs)?
+	;
+
+	copy_thumbnail(db_pool, source.file, file_id).await?;
+	if let Err(err) = copy_provenance(db_pool, source.file, file_id).await {
+		println!("unable to copy the source of '{}' to its processed file: {err:?}", source.file);
+	}
+	spawn_media_analysis(db_pool, file_id).await;
+
+	Ok(PostProcessed {
+		file: file_id,
+		node: node_id,
+	})
+}
+
+/// files without a known uploader stay where they are
+pub async fn move_to_uploader_folder(
+	db_pool: &Pool<Postgres>,
+	target: PostProcessed,
+) -> Result<(), DownloaderError> {
+	let Some(uploader) = get_file_source_info(db_pool, target.file)
+		.await?
+		.and_then(|source| source.uploader)
+	else {
+		println!("no uploader known for '{}', not moving it", target.file);
+		return Ok(());
+	};
+	// a slash in the name would nest folders
+	let folder_name = uploader.replace(['/', '\\'], "-");
+
+	let node = get_vfs_node_data(db_pool, target.node)
+		.await
+		.map_err(DownloaderError::Vfs)?
+	;
+	let Some(parent_id) = node.parent_id else {
+		return Ok(());
+	};
+	let parent = get_vfs_node_data(db_pool, parent_id)
+		.await
+		.map_err(DownloaderError::Vfs)?
+	;
+	if parent.node_name == folder_name {
+		return Ok(());
+	}
+
+	let folder = create_vfs_node_internal(
+		db_pool,
+		VfsNodeCreateArgs {
+			name: folder_name,
+			hide: false,
+		},
+		Some(parent_id)
+	)
+		.await
+		.map_err(DownloaderError::Vfs)?
+	;
+	move_vfs_file(db_pool, target.node, VfsTarget::Node(folder))
+		.await
+		.map_err(DownloaderError::Vfs)
+}
@@ -0,0 +1,283 @@
+use sqlx::{Pool, Postgres};
+use youtube_dl::{SingleVideo, YoutubeDlOutput};
+
+use super::prelude::*;
+use crate::app::media_request::YtdlAuth;
+use crate::vfs::prelude::*;
+use crate::vfs::shared::VfsTarget;
+
+mod consts {
+	/// yt-dlp reports upload dates as `YYYYMMDD`
+	pub const UPLOAD_DATE_FORMAT: &str = "%Y%m%d";
+	/// stored as the extractor of files downloaded without yt-dlp
+	pub const DIRECT_EXTRACTOR: &str = "direct";
+}
+
+struct ExistingDownload {
+	file: uuid::Uuid,
+	node: uuid::Uuid,
+	file_type: String,
+	created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// the extractor and id identifying the media on its site
+fn get_source_key(output: &SingleVideo) -> Option<(String, String)> {
+	let extractor = output.extractor_key.clone().or(output.extractor.clone())?;
+	Some((extractor, output.id.clone()))
+}
+
+/// remember where a downloaded file came from
+pub async fn record_provenance(
+	db_pool: &Pool<Postgres>,
+	file_id: uuid::Uuid,
+	output: &SingleVideo,
+) -> Result<(), DownloaderError> {
+	let Some((extractor, source_id)) = get_source_key(output) else {
+		println!("no extractor reported for '{}', skipping provenance", output.id);
+		return Ok(());
+	};
+	let upload_date = output.upload_date
+		.as_deref()
+		.and_then(|date| chrono::NaiveDate::parse_from_str(date, consts::UPLOAD_DATE_FORMAT).ok())
+	;
+
+	sqlx::query!("
+		INSERT INTO media_provenance
+			(id, extractor, source_id, webpage_url, uploader, upload_date, description)
+		VALUES
+			($1, $2, $3, $4, $5, $6, $7)
+		ON CONFLICT (id) DO NOTHING
+		;",
+		file_id,
+		extractor,
+		source_id,
+		output.webpage_url,
+		output.uploader.clone().or(output.channel.clone()),
+		upload_date,
+		output.description
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+		.map(|_| ())
+}
+
+/// direct downloads have no extractor, the url itself identifies them
+pub async fn record_direct_provenance(
+	db_pool: &Pool<Postgres>,
+	file_id: uuid::Uuid,
+	url: &str,
+) -> Result<(), DownloaderError> {
+	sqlx::query!("
+		INSERT INTO media_provenance
+			(id, extractor, source_id, webpage_url)
+		VALUES
+			($1, $2, $3, $3)
+		ON CONFLICT (id) DO NOTHING
+		;",
+		file_id,
+		consts::DIRECT_EXTRACTOR,
+		url
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+		.map(|_| ())
+}
+
+/// a processed file comes from the same source as the download it was made of
+pub async fn copy_provenance(
+	db_pool: &Pool<Postgres>,
+	from_file: uuid::Uuid,
+	to_file: uuid::Uuid,
+) -> Result<(), DownloaderError> {
+	sqlx::query!("
+		INSERT INTO media_provenance
+			(id, extractor, source_id, webpage_url, uploader, upload_date, description)
+		SELECT $2, extractor, source_id, webpage_url, uploader, upload_date, description
+		FROM media_provenance
+		WHERE id = $1
+		ON CONFLICT (id) DO NOTHING
+		;",
+		from_file,
+		to_file
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+		.map(|_| ())
+}
+
+pub async fn get_file_source_info(
+	db_pool: &Pool<Postgres>,
+	file_id: uuid::Uuid,
+) -> Result<Option<PubSourceInfo>, DownloaderError> {
+	sqlx::query_as!(
+		PubSourceInfo,
+		"SELECT
+			extractor,
+			source_id,
+			webpage_url,
+			uploader,
+			upload_date,
+			description,
+			created_at		AS downloaded_at
+		FROM media_provenance
+		WHERE id = $1
+		;",
+		file_id
+	)
+		.fetch_optional(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+}
+
+pub async fn get_source_info_internal(
+	db_pool: &Pool<Postgres>,
+	node_id: uuid::Uuid,
+) -> Result<Option<PubSourceInfo>, DownloaderError> {
+	sqlx::query_as!(
+		PubSourceInfo,
+		"SELECT
+			prov.extractor,
+			prov.source_id,
+			prov.webpage_url,
+			prov.uploader,
+			prov.upload_date,
+			prov.description,
+			prov.created_at		AS downloaded_at
+		FROM vfs_nodes				AS node
+		JOIN media_provenance		AS prov		ON prov.id = node.vfs_file
+		WHERE node.id = $1
+		;",
+		node_id
+	)
+		.fetch_optional(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+}
+
+async fn into_pub_existing(
+	db_pool: &Pool<Postgres>,
+	recs: Vec<ExistingDownload>,
+) -> Result<Vec<PubExistingDownload>, DownloaderError> {
+	let mut existing = Vec::with_capacity(recs.len());
+	for rec in recs {
+		existing.push(PubExistingDownload {
+			file: rec.file,
+			node: rec.node,
+			path: get_vfs_path_to(db_pool, rec.node)
+				.await
+				.map_err(DownloaderError::Vfs)?
+				.to_string_lossy()
+				.into_owned(),
+			audio_only: rec.file_type == "audio",
+			downloaded_at: rec.created_at,
+		});
+	}
+	Ok(existing)
+}
+
+async fn get_existing_by_url(
+	db_pool: &Pool<Postgres>,
+	url: &str,
+) -> Result<Vec<PubExistingDownload>, DownloaderError> {
+	let recs = sqlx::query_as!(
+		ExistingDownload,
+		"SELECT DISTINCT ON (prov.id)
+			prov.id				AS file,
+			node.id				AS node,
+			file.file_type,
+			prov.created_at
+		FROM media_provenance		AS prov
+		JOIN vfs_files				AS file		ON file.id = prov.id
+		JOIN vfs_nodes				AS node		ON node.vfs_file = prov.id
+		WHERE prov.webpage_url = $1
+		ORDER BY prov.id, node.created_at
+		;",
+		url
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	into_pub_existing(db_pool, recs).await
+}
+
+async fn get_existing_by_source(
+	db_pool: &Pool<Postgres>,
+	extractor: &str,
+	source_id: &str,
+) -> Result<Vec<PubExistingDownload>, DownloaderError> {
+	let recs = sqlx::query_as!(
+		ExistingDownload,
+		"SELECT DISTINCT ON (prov.id)
+			prov.id				AS file,
+			node.id				AS node,
+			file.file_type,
+			prov.created_at
+		FROM media_provenance		AS prov
+		JOIN vfs_files				AS file		ON file.id = prov.id
+		JOIN vfs_nodes				AS node		ON node.vfs_file = prov.id
+		WHERE prov.extractor = $1
+		  AND prov.source_id = $2
+		ORDER BY prov.id, node.created_at
+		;",
+		extractor,
+		source_id
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	into_pub_existing(db_pool, recs).await
+}
+
+/// earlier downloads of the media at the url, matched by url first and by what yt-dlp identifies it as otherwise
+pub async fn find_existing_downloads(
+	db_pool: &Pool<Postgres>,
+	url: &str,
+) -> Result<Vec<PubExistingDownload>, DownloaderError> {
+	let by_url = get_existing_by_url(db_pool, url).await?;
+	if !by_url.is_empty() {
+		return Ok(by_url);
+	}
+
+	// playlists are not deduplicated as a whole
+	let YoutubeDlOutput::SingleVideo(output) = probe_ytdl_url(url, &YtdlAuth::default()).await? else {
+		return Ok(vec![]);
+	};
+	let Some((extractor, source_id)) = get_source_key(&output) else {
+		return Ok(vec![]);
+	};
+	get_existing_by_source(db_pool, &extractor, &source_id).await
+}
+
+/// show an earlier download in another folder instead of downloading it again
+pub async fn link_existing_download_internal(
+	db_pool: &Pool<Postgres>,
+	file_id: uuid::Uuid,
+	vfs_target: Option<VfsTarget>,
+) -> Result<uuid::Uuid, DownloaderError> {
+	let rec = sqlx::query!("
+		SELECT node.node_name
+		FROM media_provenance		AS prov
+		JOIN vfs_nodes				AS node		ON node.vfs_file = prov.id
+		WHERE prov.id = $1
+		ORDER BY node.created_at
+		LIMIT 1
+		;",
+		file_id
+	)
+		.fetch_optional(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+		.ok_or(DownloaderError::Vfs(VFSError::NotFound))?
+	;
+
+	link_vfs_file(db_pool, file_id, rec.node_name, vfs_target)
+		.await
+		.map_err(DownloaderError::Vfs)
+}
@@ -0,0 +1,306 @@
+use sqlx::{PgExecutor, Pool, Postgres, Transaction};
+
+use super::prelude::*;
+use super::util::lock_user_downloads;
+
+fn validate_limits(limits: &PubDownloadLimits) -> Result<(), DownloaderError> {
+	if limits.max_concurrent.is_some_and(|max| max <= 0) {
+		return Err(DownloaderError::InvalidQuota("concurrent downloads have to be positive".to_string()));
+	}
+	if limits.max_daily_jobs.is_some_and(|max| max < 0) {
+		return Err(DownloaderError::InvalidQuota("daily downloads can't be negative".to_string()));
+	}
+	if limits.max_total_bytes.is_some_and(|max| max < 0) {
+		return Err(DownloaderError::InvalidQuota("storage can't be negative".to_string()));
+	}
+	if limits.max_file_bytes.is_some_and(|max| max <= 0) {
+		return Err(DownloaderError::InvalidQuota("file size has to be positive".to_string()));
+	}
+	Ok(())
+}
+
+/// the limits of the user's level with their own overrides applied
+pub async fn get_download_limits(
+	db: impl PgExecutor<'_>,
+	user_id: i32,
+) -> Result<PubDownloadLimits, DownloaderError> {
+	let rec = sqlx::query!("
+		SELECT max_concurrent, max_daily_jobs, max_total_bytes, max_file_bytes
+		FROM effective_download_quotas
+		WHERE user_id = $1
+		;",
+		user_id
+	)
+		.fetch_optional(db)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	Ok(rec
+		.map(|rec| PubDownloadLimits {
+			max_concurrent: rec.max_concurrent,
+			max_daily_jobs: rec.max_daily_jobs,
+			max_total_bytes: rec.max_total_bytes,
+			max_file_bytes: rec.max_file_bytes,
+		})
+		.unwrap_or_default()
+	)
+}
+
+/// every job that downloads something counts towards the daily limit, playlists by their entries once expanded
+pub async fn get_download_usage(
+	db: impl PgExecutor<'_>,
+	user_id: i32,
+) -> Result<PubDownloadUsage, DownloaderError> {
+	let rec = sqlx::query!(r#"
+		SELECT
+			(
+				SELECT count(*)
+				FROM download_jobs
+				WHERE user_id = $1
+				  AND state = 'running'
+			)																AS "running!",
+			(
+				SELECT count(*)
+				FROM download_jobs
+				WHERE user_id = $1
+				  AND state <> 'expanded'
+				  AND created_at > now() - INTERVAL '1 day'
+			)																AS "daily_jobs!",
+			(
+				SELECT COALESCE(sum(file.file_size), 0)::BIGINT
+				FROM vfs_files				AS file
+				WHERE file.id IN (
+					SELECT media_file
+					FROM download_jobs
+					WHERE user_id = $1
+				)
+			)																AS "total_bytes!"
+		;"#,
+		user_id
+	)
+		.fetch_one(db)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	Ok(PubDownloadUsage {
+		running: rec.running,
+		daily_jobs: rec.daily_jobs,
+		total_bytes: rec.total_bytes,
+	})
+}
+
+pub async fn get_download_quota_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+) -> Result<PubDownloadQuota, DownloaderError> {
+	Ok(PubDownloadQuota {
+		limits: get_download_limits(db_pool, user_id).await?,
+		usage: get_download_usage(db_pool, user_id).await?,
+	})
+}
+
+/// the quota as of taking the user's download lock, nothing else is queued for them until the transaction ends
+pub async fn lock_download_quota(
+	tx: &mut Transaction<'_, Postgres>,
+	user_id: i32,
+) -> Result<PubDownloadQuota, DownloaderError> {
+	lock_user_downloads(tx, user_id).await?;
+	Ok(PubDownloadQuota {
+		limits: get_download_limits(&mut **tx, user_id).await?,
+		usage: get_download_usage(&mut **tx, user_id).await?,
+	})
+}
+
+/// how many more downloads the user may request today, `None` if there is no limit
+pub fn remaining_daily_jobs(quota: &PubDownloadQuota) -> Option<i64> {
+	quota.limits.max_daily_jobs
+		.map(|max| (max as i64 - quota.usage.daily_jobs).max(0))
+}
+
+/// whether the user may queue another download, the job has to be inserted in the same transaction
+pub async fn check_enqueue_quota(
+	tx: &mut Transaction<'_, Postgres>,
+	user_id: i32,
+) -> Result<(), DownloaderError> {
+	let quota = lock_download_quota(tx, user_id).await?;
+	if remaining_daily_jobs(&quota) == Some(0) {
+		return Err(DownloaderError::QuotaExceeded("daily download limit reached".to_string()));
+	}
+	if quota.limits.max_total_bytes.is_some_and(|max| quota.usage.total_bytes >= max) {
+		return Err(DownloaderError::QuotaExceeded("storage limit reached".to_string()));
+	}
+	Ok(())
+}
+
+/// the largest file the user may download right now, bounded by the file size limit and the storage left
+pub async fn get_max_download_size(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+) -> Result<Option<u64>, DownloaderError> {
+	let quota = get_download_quota_internal(db_pool, user_id).await?;
+	let storage_left = quota.limits.max_total_bytes
+		.map(|max| max - quota.usage.total_bytes)
+	;
+	if storage_left.is_some_and(|left| left <= 0) {
+		return Err(DownloaderError::QuotaExceeded("storage limit reached".to_string()));
+	}
+
+	let max_size = match (quota.limits.max_file_bytes, storage_left) {
+		(Some(max_file), Some(left)) => Some(max_file.min(left)),
+		(max_file, left) => max_file.or(left),
+	};
+	Ok(max_size.map(|max| max as u64))
+}
+
+pub async fn get_level_quotas_internal(
+	db_pool: &Pool<Postgres>,
+) -> Result<Vec<PubLevelQuota>, DownloaderError> {
+	let recs = sqlx::query!(r#"
+		SELECT
+			level.level_id,
+			level.level_name,
+			quota.max_concurrent		AS "max_concurrent?",
+			quota.max_daily_jobs		AS "max_daily_jobs?",
+			quota.max_total_bytes		AS "max_total_bytes?",
+			quota.max_file_bytes		AS "max_file_bytes?"
+		FROM user_levels				AS level
+		LEFT JOIN download_quotas		AS quota	ON quota.level_id = level.level_id
+		ORDER BY level.level_id
+		;"#
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	Ok(recs
+		.into_iter()
+		.map(|rec| PubLevelQuota {
+			level_id: rec.level_id,
+			level_name: rec.level_name,
+			limits: PubDownloadLimits {
+				max_concurrent: rec.max_concurrent,
+				max_daily_jobs: rec.max_daily_jobs,
+				max_total_bytes: rec.max_total_bytes,
+				max_file_bytes: rec.max_file_bytes,
+			},
+		})
+		.collect()
+	)
+}
+
+pub async fn set_level_quota_internal(
+	db_pool: &Pool<Postgres>,
+	level_id: i16,
+	limits: PubDownloadLimits,
+) -> Result<(), DownloaderError> {
+	validate_limits(&limits)?;
+
+	sqlx::query!("
+		INSERT INTO download_quotas
+			(level_id, max_concurrent, max_daily_jobs, max_total_bytes, max_file_bytes)
+		VALUES
+			($1, $2, $3, $4, $5)
+		ON CONFLICT (level_id) DO UPDATE
+		SET max_concurrent	= EXCLUDED.max_concurrent,
+			max_daily_jobs	= EXCLUDED.max_daily_jobs,
+			max_total_bytes	= EXCLUDED.max_total_bytes,
+			max_file_bytes	= EXCLUDED.max_file_bytes
+		;",
+		level_id,
+		limits.max_concurrent,
+		limits.max_daily_jobs,
+		limits.max_total_bytes,
+		limits.max_file_bytes
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+		.map(|_| ())
+}
+
+pub async fn get_user_quotas_internal(
+	db_pool: &Pool<Postgres>,
+) -> Result<Vec<PubUserQuota>, DownloaderError> {
+	let recs = sqlx::query!(r#"
+		SELECT
+			users.id,
+			users.email,
+			level.level_name,
+			quota.max_concurrent		AS "max_concurrent?",
+			quota.max_daily_jobs		AS "max_daily_jobs?",
+			quota.max_total_bytes		AS "max_total_bytes?",
+			quota.max_file_bytes		AS "max_file_bytes?"
+		FROM users
+		JOIN user_levels				AS level	ON level.level_id = users.user_level
+		LEFT JOIN user_download_quotas	AS quota	ON quota.user_id = users.id
+		ORDER BY users.id
+		;"#
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	let mut users = Vec::with_capacity(recs.len());
+	for rec in recs {
+		users.push(PubUserQuota {
+			user_id: rec.id,
+			email: rec.email,
+			level_name: rec.level_name,
+			overrides: PubDownloadLimits {
+				max_concurrent: rec.max_concurrent,
+				max_daily_jobs: rec.max_daily_jobs,
+				max_total_bytes: rec.max_total_bytes,
+				max_file_bytes: rec.max_file_bytes,
+			},
+			quota: get_download_quota_internal(db_pool, rec.id).await?,
+		});
+	}
+	Ok(users)
+}
+
+/// override limits of a single user, unset limits and `None` fall back to the user's level
+pub async fn set_user_quota_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	overrides: Option<PubDownloadLimits>,
+) -> Result<(), DownloaderError> {
+	let Some(overrides) = overrides.filter(|overrides| *overrides != PubDownloadLimits::default()) else {
+		return sqlx::query!("
+			DELETE FROM user_download_quotas
+			WHERE user_id = $1
+			;",
+			user_id
+		)
+			.execute(db_pool)
+			.await
+			.map_err(DownloaderError::Sql)
+			.map(|_| ());
+	};
+	validate_limits(&overrides)?;
+
+	sqlx::query!("
+		INSERT INTO user_download_quotas
+			(user_id, max_concurrent, max_daily_jobs, max_total_bytes, max_file_bytes)
+		VALUES
+			($1, $2, $3, $4, $5)
+		ON CONFLICT (user_id) DO UPDATE
+		SET max_concurrent	= EXCLUDED.max_concurrent,
+			max_daily_jobs	= EXCLUDED.max_daily_jobs,
+			max_total_bytes	= EXCLUDED.max_total_bytes,
+			max_file_bytes	= EXCLUDED.max_file_bytes
+		;",
+		user_id,
+		overrides.max_concurrent,
+		overrides.max_daily_jobs,
+		overrides.max_total_bytes,
+		overrides.max_file_bytes
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+		.map(|_| ())
+}
@@ -0,0 +1,407 @@
+use std::path::PathBuf;
+
+use regex::Regex;
+use sqlx::{Pool, Postgres};
+
+use super::prelude::*;
+use crate::vfs::prelude::*;
+use crate::vfs::shared::VfsTarget;
+
+mod consts {
+	/// a few dozen channels checked every few minutes would hammer the sites
+	pub const MIN_CHECK_INTERVAL: i32 = 15 * 60;
+	pub const MAX_TITLE_REGEX_LEN: usize = 512;
+}
+
+/// the compiled form of [`PubSubscriptionFilter`]
+#[derive(Debug, Clone)]
+pub struct SubscriptionFilter {
+	pub max_duration: Option<i32>,
+	pub title_regex: Option<Regex>,
+}
+impl SubscriptionFilter {
+	pub fn new(filter: &PubSubscriptionFilter) -> Result<Self, DownloaderError> {
+		if filter.max_duration.is_some_and(|max| max <= 0) {
+			return Err(DownloaderError::InvalidSubscription("max duration has to be positive".to_string()));
+		}
+		let title_regex = match filter.title_regex.as_deref().map(str::trim) {
+			Some("") | None => None,
+			Some(regex) if regex.len() > consts::MAX_TITLE_REGEX_LEN => {
+				return Err(DownloaderError::InvalidSubscription("title regex is too long".to_string()));
+			},
+			Some(regex) => Some(Regex::new(regex)
+				.map_err(|err| DownloaderError::InvalidSubscription(err.to_string()))?
+			),
+		};
+
+		Ok(Self {
+			max_duration: filter.max_duration,
+			title_regex,
+		})
+	}
+
+	pub fn matches(&self, entry: &SubscriptionEntry) -> bool {
+		if let Some(max) = self.max_duration
+		&& let Some(duration) = entry.duration
+		&& duration > max as f64 {
+			return false;
+		}
+		match &self.title_regex {
+			Some(regex) => entry.title.as_deref().is_some_and(|title| regex.is_match(title)),
+			None => true,
+		}
+	}
+}
+
+/// an entry yt-dlp listed for a subscription
+#[derive(Debug, Clone)]
+pub struct SubscriptionEntry {
+	/// the id of the entry on its site, used for the download archive
+	pub id: String,
+	pub url: String,
+	pub title: Option<String>,
+	/// in seconds
+	pub duration: Option<f64>,
+}
+
+/// a subscription that is due for a check, as the scheduler sees it
+#[derive(Debug, Clone)]
+pub struct DueSubscription {
+	pub id: uuid::Uuid,
+	pub user_id: i32,
+	pub url: String,
+	pub audio_only: bool,
+	pub target_node: Option<uuid::Uuid>,
+	pub filter: SubscriptionFilter,
+	pub post_processing: PubPostProcessing,
+	/// nothing is downloaded on the first check, it only fills the archive
+	pub first_check: bool,
+}
+
+pub async fn create_subscription_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	args: PubSubscriptionArgs,
+	target: Option<VfsTarget>,
+) -> Result<uuid::Uuid, DownloaderError> {
+	if args.check_interval < consts::MIN_CHECK_INTERVAL {
+		return Err(DownloaderError::InvalidSubscription(format!("check interval has to be at least {}s", consts::MIN_CHECK_INTERVAL)));
+	}
+	// only validated here, the scheduler compiles it again
+	SubscriptionFilter::new(&args.filter)?;
+	validate_post_processing(&args.post_processing)?;
+
+	let target_node = match target {
+		Some(VfsTarget::Node(node)) => node,
+		Some(VfsTarget::Path(path)) => ensure_vfs_path(db_pool, path)
+			.await
+			.map_err(DownloaderError::Vfs)?,
+		None => ensure_vfs_path(db_pool, PathBuf::new())
+			.await
+			.map_err(DownloaderError::Vfs)?,
+	};
+
+	sqlx::query!("
+		INSERT INTO download_subscriptions
+			(user_id, url, target_node, audio_only, max_duration, title_regex, check_interval, post_processing)
+		VALUES
+			($1, $2, $3, $4, $5, $6, $7, $8)
+		RETURNING id
+		;",
+		user_id,
+		args.url,
+		target_node,
+		args.audio_only,
+		args.filter.max_duration,
+		args.filter.title_regex.filter(|regex| !regex.trim().is_empty()),
+		args.check_interval,
+		options_to_db(&args.post_processing)
+	)
+		.fetch_one(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+		.map(|rec| rec.id)
+}
+
+pub async fn delete_subscription_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	subscription: uuid::Uuid,
+) -> Result<(), DownloaderError> {
+	let res = sqlx::query!("
+		DELETE FROM download_subscriptions
+		WHERE user_id = $1
+		  AND id = $2
+		;",
+		user_id,
+		subscription
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	match res.rows_affected() {
+		0 => Err(DownloaderError::NoSubscription),
+		_ => Ok(()),
+	}
+}
+
+/// pausing keeps the archive, so resuming won't download what was uploaded in between
+pub async fn set_subscription_enabled_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	subscription: uuid::Uuid,
+	enabled: bool,
+) -> Result<(), DownloaderError> {
+	let res = sqlx::query!("
+		UPDATE download_subscriptions
+		SET enabled = $3
+		WHERE user_id = $1
+		  AND id = $2
+		;",
+		user_id,
+		subscription,
+		enabled
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	match res.rows_affected() {
+		0 => Err(DownloaderError::NoSubscription),
+		_ => Ok(()),
+	}
+}
+
+/// make the subscription due right away
+pub async fn check_subscription_now_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	subscription: uuid::Uuid,
+) -> Result<(), DownloaderError> {
+	let res = sqlx::query!("
+		UPDATE download_subscriptions
+		SET next_check_at = now()
+		WHERE user_id = $1
+		  AND id = $2
+		  AND enabled
+		;",
+		user_id,
+		subscription
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	match res.rows_affected() {
+		0 => Err(DownloaderError::NoSubscription),
+		_ => Ok(()),
+	}
+}
+
+pub async fn get_subscriptions_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+) -> Result<Vec<PubSubscription>, DownloaderError> {
+	let recs = sqlx::query!("
+		SELECT id, url, title, target_node, audio_only, max_duration, title_regex, check_interval, post_processing, enabled, last_checked_at, next_check_at, last_error, created_at
+		FROM download_subscriptions
+		WHERE user_id = $1
+		ORDER BY created_at
+		;",
+		user_id
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	let mut subscriptions = Vec::with_capacity(recs.len());
+	for rec in recs {
+		let target = match rec.target_node {
+			Some(node) => Some(get_vfs_path_to(db_pool, node)
+				.await
+				.map_err(DownloaderError::Vfs)?
+				.to_string_lossy()
+				.into_owned()
+			),
+			None => None,
+		};
+		subscriptions.push(PubSubscription {
+			id: rec.id,
+			url: rec.url,
+			title: rec.title,
+			target,
+			audio_only: rec.audio_only,
+			filter: PubSubscriptionFilter {
+				max_duration: rec.max_duration,
+				title_regex: rec.title_regex,
+			},
+			check_interval: rec.check_interval,
+			post_processing: options_from_db(rec.post_processing),
+			enabled: rec.enabled,
+			last_checked_at: rec.last_checked_at,
+			next_check_at: rec.next_check_at,
+			last_error: rec.last_error,
+			created_at: rec.created_at,
+		});
+	}
+	Ok(subscriptions)
+}
+
+/// hand out the subscription that has been due the longest, scheduling its next check right away
+pub async fn claim_due_subscription(
+	db_pool: &Pool<Postgres>,
+) -> Result<Option<DueSubscription>, DownloaderError> {
+	let rec = sqlx::query!("
+		UPDATE download_subscriptions
+		SET next_check_at = now() + make_interval(secs => check_interval)
+		WHERE id = (
+			SELECT id
+			FROM download_subscriptions
+			WHERE enabled
+			  AND next_check_at <= now()
+			ORDER BY next_check_at
+			LIMIT 1
+			FOR UPDATE SKIP LOCKED
+		)
+		RETURNING id, user_id, url, audio_only, target_node, max_duration, title_regex, post_processing, last_checked_at
+		;"
+	)
+		.fetch_optional(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	let Some(rec) = rec else {
+		return Ok(None);
+	};
+	let filter = SubscriptionFilter::new(&PubSubscriptionFilter {
+		max_duration: rec.max_duration,
+		title_regex: rec.title_regex,
+	})?;
+
+	Ok(Some(DueSubscription {
+		id: rec.id,
+		user_id: rec.user_id,
+		url: rec.url,
+		audio_only: rec.audio_only,
+		target_node: rec.target_node,
+		filter,
+		post_processing: options_from_db(rec.post_processing),
+		first_check: rec.last_checked_at.is_none(),
+	}))
+}
+
+/// queue the entries that are neither archived nor filtered while the daily limit allows, returning the number of new jobs
+pub async fn queue_subscription_entries(
+	db_pool: &Pool<Postgres>,
+	subscription: &DueSubscription,
+	title: Option<String>,
+	entries: Vec<SubscriptionEntry>,
+) -> Result<usize, DownloaderError> {
+	let mut tx = db_pool.begin()
+		.await
+		.map_err(DownloaderError::Sql)?;
+
+	let quota = lock_download_quota(&mut tx, subscription.user_id).await?;
+	let mut remaining = remaining_daily_jobs(&quota);
+
+	let post_processing = options_to_db(&subscription.post_processing);
+	let mut queued = 0;
+	for entry in entries {
+		// filtered entries stay out of the archive so a changed filter still sees them
+		if !subscription.first_check && !subscription.filter.matches(&entry) {
+			continue;
+		}
+		// the same goes for entries over the daily limit, a later check queues them
+		if !subscription.first_check && remaining == Some(0) {
+			println!("subscription {} reached the daily download limit of its user", subscription.id);
+			break;
+		}
+
+		let archived = sqlx::query!("
+			INSERT INTO download_archive
+				(subscription, entry_id)
+			VALUES
+				($1, $2)
+			ON CONFLICT DO NOTHING
+			;",
+			subscription.id,
+			entry.id
+		)
+			.execute(&mut *tx)
+			.await
+			.map_err(DownloaderError::Sql)?
+			.rows_affected()
+		;
+		if archived == 0 || subscription.first_check {
+			continue;
+		}
+
+		sqlx::query!("
+			INSERT INTO download_jobs
+				(user_id, url, audio_only, post_processing, target_node, title, subscription)
+			VALUES
+				($1, $2, $3, $4, $5, $6, $7)
+			;",
+			subscription.user_id,
+			entry.url,
+			subscription.audio_only,
+			post_processing,
+			subscription.target_node,
+			entry.title,
+			subscription.id
+		)
+			.execute(&mut *tx)
+			.await
+			.map_err(DownloaderError::Sql)?
+		;
+		queued += 1;
+		remaining = remaining.map(|remaining| remaining - 1);
+	}
+
+	sqlx::query!("
+		UPDATE download_subscriptions
+		SET title			= COALESCE($2, title),
+			last_checked_at	= now(),
+			last_error		= NULL
+		WHERE id = $1
+		;",
+		subscription.id,
+		title
+	)
+		.execute(&mut *tx)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	tx.commit()
+		.await
+		.map_err(DownloaderError::Sql)?;
+
+	Ok(queued)
+}
+
+/// the check is attempted again at the next interval, a failed first check stays the first one
+pub async fn fail_subscription_check(
+	db_pool: &Pool<Postgres>,
+	subscription: uuid::Uuid,
+	error: String,
+) -> Result<(), DownloaderError> {
+	sqlx::query!("
+		UPDATE download_subscriptions
+		SET last_error = $2
+		WHERE id = $1
+		;",
+		subscription,
+		error
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+		.map(|_| ())
+}
@@ -0,0 +1,1122 @@
+use std::{collections::HashSet, path::PathBuf, time::Duration};
+
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::{Pool, Postgres};
+use youtube_dl::YoutubeDlOutput;
+
+use super::prelude::*;
+use crate::app::media_request::{DirectFile, YtdlAuth, YtdlRequest, YtdlResult};
+use crate::media::prelude::*;
+use crate::vfs::prelude::*;
+use crate::vfs::shared::VfsTarget;
+
+pub mod values {
+	/// in kbit/s, for audio extracted by yt-dlp as well as converted in post-processing
+	pub const AUDIO_BITRATE_RANGE: std::ops::RangeInclusive<i32> = 8..=512;
+}
+
+mod consts {
+	use std::time::Duration;
+
+	/// the first key of the advisory locks a user's downloads are serialized with
+	pub const USER_DOWNLOADS_LOCK: i32 = 0x646c;
+
+	/// automatic retries of transient failures, manual retries count towards this as well
+	pub const MAX_AUTO_RETRIES: i32 = 5;
+	pub const RETRY_BASE_DELAY: Duration = Duration::from_secs(30);
+	pub const RETRY_MAX_DELAY: Duration = Duration::from_secs(60 * 60);
+
+	/// yt-dlp gives up on single connections after 15 seconds, this bounds the whole probe
+	pub const PROBE_TIMEOUT: Duration = Duration::from_secs(120);
+
+	pub const MAX_FORMAT_ID_LEN: usize = 64;
+	/// everything a format selector like `137+bestaudio/best[height<=720]` is made of
+	pub const FORMAT_ID_CHARS: &str = "+/[]<>=!*?:.,-_";
+
+	/// yt-dlp output hinting at network trouble rather than a broken url
+	pub const TRANSIENT_PATTERNS: &[&str] = &[
+		"timed out",
+		"timeout",
+		"connection reset",
+		"connection refused",
+		"connection aborted",
+		"network is unreachable",
+		"temporary failure in name resolution",
+		"name or service not known",
+		"incompleteread",
+		"remote end closed connection",
+		"http error 429",
+		"http error 500",
+		"http error 502",
+		"http error 503",
+		"http error 504",
+		"unable to download webpage",
+	];
+}
+
+/// a queued download as the worker sees it
+#[derive(Debug, Clone)]
+pub struct DownloadJob {
+	pub id: uuid::Uuid,
+	pub user_id: i32,
+	pub request: YtdlRequest,
+	pub target: Option<VfsTarget>,
+	pub post_processing: PubPostProcessing,
+	pub retry_count: i32,
+	pub parent_job: Option<uuid::Uuid>,
+	pub playlist_index: Option<i32>,
+}
+
+/// an entry of a playlist that gets its own child job
+#[derive(Debug, Clone)]
+pub struct PlaylistEntry {
+	pub url: String,
+	pub title: Option<String>,
+}
+
+fn split_target(target: Option<VfsTarget>) -> (Option<uuid::Uuid>, Option<String>) {
+	match target {
+		Some(VfsTarget::Node(node)) => (Some(node), None),
+		Some(VfsTarget::Path(path)) => (None, Some(path.to_string_lossy().into_owned())),
+		None => (None, None),
+	}
+}
+
+fn join_target(node: Option<uuid::Uuid>, path: Option<String>) -> Option<VfsTarget> {
+	node.map(VfsTarget::Node)
+		.or(path.map(|path| VfsTarget::Path(path.into())))
+}
+
+/// binaries are never fetched implicitly, an admin has to install or update them
+pub fn get_ytdl_path() -> Result<PathBuf, DownloaderError> {
+	let ytdl_path = binary_path(&PubBinaryKind::Ytdl);
+	if binary_config().is_managed(&PubBinaryKind::Ytdl) && !ytdl_path.exists() {
+		println!("ytdl is not available at '{ytdl_path:?}'");
+		return Err(DownloaderError::YtdlInitError);
+	}
+	Ok(ytdl_path)
+}
+
+/// metadata of the url, playlists only list their entries instead of resolving each one
+pub async fn probe_ytdl_url(
+	url: &str,
+	auth: &YtdlAuth,
+) -> Result<YoutubeDlOutput, DownloaderError> {
+	use std::process::Stdio;
+	use tokio::process::Command;
+
+	let ytdl_path = get_ytdl_path()?;
+	let mut command = Command::new(&ytdl_path);
+	command
+		.arg("--socket-timeout")
+		.arg("15")
+		.arg("--flat-playlist")
+		.args(auth.args())
+		.arg("-J")
+		.arg("--")
+		.arg(url)
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		// a request that is given up on, or a timeout, doesn't leave yt-dlp running
+		.kill_on_drop(true)
+	;
+
+	let child = command
+		.spawn()
+		.map_err(|err| DownloaderError::Ytdl(youtube_dl::Error::Io(err)))?
+	;
+	let output = tokio::time::timeout(consts::PROBE_TIMEOUT, child.wait_with_output())
+		.await
+		.map_err(|_| DownloaderError::Ytdl(youtube_dl::Error::ProcessTimeout))?
+		.map_err(|err| DownloaderError::Ytdl(youtube_dl::Error::Io(err)))?
+	;
+	if !output.status.success() {
+		return Err(DownloaderError::Ytdl(youtube_dl::Error::ExitCode {
+			code: output.status.code().unwrap_or(1),
+			stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+		}));
+	}
+
+	let json_err = |err| DownloaderError::Ytdl(youtube_dl::Error::Json(err));
+	let value: serde_json::Value = serde_json::from_slice(&output.stdout).map_err(json_err)?;
+	match value["_type"].as_str() {
+		Some("playlist") => serde_json::from_value(value)
+			.map(|playlist| YoutubeDlOutput::Playlist(Box::new(playlist)))
+			.map_err(json_err),
+		_ => serde_json::from_value(value)
+			.map(|video| YoutubeDlOutput::SingleVideo(Box::new(video)))
+			.map_err(json_err),
+	}
+}
+
+/// the formats yt-dlp offers for a single video
+pub async fn get_available_formats_internal(
+	url: &str,
+) -> Result<Vec<PubAvailableFormat>, DownloaderError> {
+	let YoutubeDlOutput::SingleVideo(output) = probe_ytdl_url(url, &YtdlAuth::default()).await? else {
+		return Err(DownloaderError::YtdlNotSingle);
+	};
+	// yt-dlp reports missing streams as "none"
+	let codec = |codec: Option<String>| codec.filter(|codec| codec != "none");
+
+	let formats = output.formats
+		.unwrap_or_default()
+		.into_iter()
+		// storyboards and the like
+		.filter(|format| !(format.vcodec.as_deref() == Some("none") && format.acodec.as_deref() == Some("none")))
+		.filter_map(|format| Some(PubAvailableFormat {
+			format_id: format.format_id?,
+			ext: format.ext,
+			width: format.width.map(|width| width as i32),
+			height: format.height.map(|height| height as i32),
+			fps: format.fps,
+			vcodec: codec(format.vcodec),
+			acodec: codec(format.acodec),
+			filesize: format.filesize.or(format.filesize_approx).map(|size| size as i64),
+			tbr: format.tbr,
+			note: format.format_note,
+		}))
+		.collect()
+	;
+	Ok(formats)
+}
+
+pub fn validate_download_format(format: &PubDownloadFormat) -> Result<(), DownloaderError> {
+	if let Some(format_id) = &format.format_id
+	&& (format_id.is_empty()
+		|| format_id.len() > consts::MAX_FORMAT_ID_LEN
+		|| !format_id.chars().all(|c| c.is_ascii_alphanumeric() || consts::FORMAT_ID_CHARS.contains(c))
+	) {
+		return Err(DownloaderError::InvalidFormat(format!("invalid format selector '{format_id}'")));
+	}
+	if format.max_height.is_some_and(|height| height <= 0) {
+		return Err(DownloaderError::InvalidFormat("max height has to be positive".to_string()));
+	}
+	if let Some(bitrate) = format.audio_bitrate
+	&& !values::AUDIO_BITRATE_RANGE.contains(&bitrate) {
+		return Err(DownloaderError::InvalidFormat(format!("audio bitrate has to be within {:?} kbit/s", values::AUDIO_BITRATE_RANGE)));
+	}
+	Ok(())
+}
+
+/// format and post-processing options are stored as json, defaults are left out entirely
+pub(crate) fn options_to_db<T: Serialize + Default + PartialEq>(options: &T) -> Option<String> {
+	if *options == T::default() {
+		return None;
+	}
+	serde_json::to_string(options).ok()
+}
+
+pub(crate) fn options_from_db<T: DeserializeOwned + Default>(options: Option<String>) -> T {
+	let Some(options) = options else {
+		return T::default();
+	};
+	serde_json::from_str(&options).unwrap_or_else(|err| {
+		println!("invalid stored options '{options}', using defaults: {err:?}");
+		T::default()
+	})
+}
+
+pub async fn enqueue_download_job(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	request: YtdlRequest,
+	post_processing: PubPostProcessing,
+	target: Option<VfsTarget>,
+) -> Result<uuid::Uuid, DownloaderError> {
+	validate_download_format(&request.format)?;
+	validate_post_processing(&post_processing)?;
+	let (target_node, target_path) = split_target(target);
+
+	let mut tx = db_pool.begin()
+		.await
+		.map_err(DownloaderError::Sql)?;
+	check_enqueue_quota(&mut tx, user_id).await?;
+
+	let rec = sqlx::query!("
+		INSERT INTO download_jobs
+			(user_id, url, audio_only, format_options, post_processing, target_node, target_path)
+		VALUES
+			($1, $2, $3, $4, $5, $6, $7)
+		RETURNING id
+		;",
+		user_id,
+		request.url,
+		request.audio_only,
+		options_to_db(&request.format),
+		options_to_db(&post_processing),
+		target_node,
+		target_path
+	)
+		.fetch_one(&mut *tx)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	tx.commit()
+		.await
+		.map_err(DownloaderError::Sql)?;
+	Ok(rec.id)
+}
+
+/// held until the transaction ends, so counting a user's jobs and changing them can't interleave with another worker or request
+pub(crate) async fn lock_user_downloads(
+	tx: &mut sqlx::Transaction<'_, Postgres>,
+	user_id: i32,
+) -> Result<(), DownloaderError> {
+	sqlx::query!("
+		SELECT pg_advisory_xact_lock($1, $2)
+		;",
+		consts::USER_DOWNLOADS_LOCK,
+		user_id
+	)
+		.execute(&mut **tx)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+	Ok(())
+}
+
+/// mark the oldest queued job of a user below their concurrency limit as running and hand it out
+pub async fn claim_next_download_job(
+	db_pool: &Pool<Postgres>,
+) -> Result<Option<DownloadJob>, DownloaderError> {
+	let mut tx = db_pool.begin()
+		.await
+		.map_err(DownloaderError::Sql)?;
+
+	let candidate = sqlx::query!("
+		SELECT job.id, job.user_id
+		FROM download_jobs						AS job
+		LEFT JOIN effective_download_quotas		AS quota	ON quota.user_id = job.user_id
+		WHERE job.state = 'queued'
+		  AND (job.next_attempt_at IS NULL OR job.next_attempt_at <= now())
+		  -- users at their limit of concurrent downloads wait for one of them to finish
+		  AND (quota.max_concurrent IS NULL OR quota.max_concurrent > (
+			SELECT count(*)
+			FROM download_jobs		AS running
+			WHERE running.user_id = job.user_id
+			  AND running.state = 'running'
+		  ))
+		ORDER BY job.created_at, job.playlist_index NULLS FIRST
+		LIMIT 1
+		FOR UPDATE OF job SKIP LOCKED
+		;"
+	)
+		.fetch_optional(&mut *tx)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+	let Some(candidate) = candidate else {
+		return Ok(None);
+	};
+
+	// another worker may have started a job of the same user since, the limit is checked again under the lock
+	lock_user_downloads(&mut tx, candidate.user_id).await?;
+	let rec = sqlx::query!("
+		UPDATE download_jobs
+		SET state		= 'running',
+			started_at	= now()
+		WHERE id = $1
+		  AND NOT EXISTS(
+			SELECT 1
+			FROM effective_download_quotas	AS quota
+			WHERE quota.user_id = download_jobs.user_id
+			  AND quota.max_concurrent <= (
+				SELECT count(*)
+				FROM download_jobs		AS running
+				WHERE running.user_id = download_jobs.user_id
+				  AND running.state = 'running'
+			  )
+		  )
+		RETURNING id, user_id, url, audio_only, format_options, post_processing, target_node, target_path, retry_count, parent_job, playlist_index
+		;",
+		candidate.id
+	)
+		.fetch_optional(&mut *tx)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+	tx.commit()
+		.await
+		.map_err(DownloaderError::Sql)?;
+
+	Ok(rec.map(|rec| DownloadJob {
+		id: rec.id,
+		user_id: rec.user_id,
+		request: YtdlRequest {
+			url: rec.url,
+			audio_only: rec.audio_only,
+			format: options_from_db(rec.format_options),
+		},
+		target: join_target(rec.target_node, rec.target_path),
+		post_processing: options_from_db(rec.post_processing),
+		retry_count: rec.retry_count,
+		parent_job: rec.parent_job,
+		playlist_index: rec.playlist_index,
+	}))
+}
+
+/// the folder a playlist's entries are downloaded into, named after the playlist
+pub async fn ensure_playlist_folder(
+	db_pool: &Pool<Postgres>,
+	target: Option<VfsTarget>,
+	title: &str,
+) -> Result<uuid::Uuid, DownloaderError> {
+	let parent_path = match target {
+		Some(VfsTarget::Node(node)) => get_vfs_path_to(db_pool, node)
+			.await
+			.map_err(DownloaderError::Vfs)?,
+		Some(VfsTarget::Path(path)) => path,
+		None => PathBuf::new(),
+	};
+	// a slash in the title would nest folders
+	let folder_name = title.replace(['/', '\\'], "-");
+
+	ensure_vfs_path(db_pool, parent_path.join(folder_name))
+		.await
+		.map_err(DownloaderError::Vfs)
+}
+
+/// replace a running job with one child job per playlist entry, returning the number of entries
+pub async fn expand_download_job(
+	db_pool: &Pool<Postgres>,
+	job: &DownloadJob,
+	title: Option<String>,
+	folder: uuid::Uuid,
+	entries: Vec<PlaylistEntry>,
+) -> Result<usize, DownloaderError> {
+	let mut tx = db_pool.begin()
+		.await
+		.map_err(DownloaderError::Sql)?;
+
+	// a retried expansion keeps the entries that already finished and queues the rest again
+	sqlx::query!("
+		DELETE FROM download_jobs
+		WHERE parent_job = $1
+		  AND state NOT IN ('completed', 'expanded')
+		;",
+		job.id
+	)
+		.execute(&mut *tx)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+	let kept: HashSet<String> = sqlx::query!("
+		SELECT url
+		FROM download_jobs
+		WHERE parent_job = $1
+		;",
+		job.id
+	)
+		.fetch_all(&mut *tx)
+		.await
+		.map_err(DownloaderError::Sql)?
+		.into_iter()
+		.map(|rec| rec.url)
+		.collect()
+	;
+	let mut pending: Vec<(usize, PlaylistEntry)> = entries
+		.into_iter()
+		.enumerate()
+		.filter(|(_, entry)| !kept.contains(&entry.url))
+		.collect()
+	;
+
+	// the entries count towards the daily limit instead of the playlist itself
+	let quota = lock_download_quota(&mut tx, job.user_id).await?;
+	let allowed = quota.limits.max_daily_jobs
+		.map(|max| (max as i64 - quota.usage.daily_jobs + 1).max(0) as usize)
+	;
+	match allowed {
+		Some(0) if !pending.is_empty() => return Err(DownloaderError::QuotaExceeded("daily download limit reached".to_string())),
+		Some(allowed) if allowed < pending.len() => {
+			println!("download job {} only queues {allowed} of {} entries, the daily limit is reached", job.id, pending.len());
+			pending.truncate(allowed);
+		},
+		_ => (),
+	}
+
+	// every entry is downloaded the way the playlist was requested
+	let format_options = options_to_db(&job.request.format);
+	let post_processing = options_to_db(&job.post_processing);
+	for (index, entry) in &pending {
+		sqlx::query!("
+			INSERT INTO download_jobs
+				(user_id, url, audio_only, format_options, post_processing, target_node, parent_job, playlist_index, title)
+			VALUES
+				($1, $2, $3, $4, $5, $6, $7, $8, $9)
+			;",
+			job.user_id,
+			entry.url,
+			job.request.audio_only,
+			format_options,
+			post_processing,
+			folder,
+			job.id,
+			*index as i32 + 1,
+			entry.title
+		)
+			.execute(&mut *tx)
+			.await
+			.map_err(DownloaderError::Sql)?
+		;
+	}
+
+	sqlx::query!("
+		UPDATE download_jobs
+		SET state		= 'expanded',
+			title		= $2,
+			finished_at	= now()
+		WHERE id = $1
+		;",
+		job.id,
+		title
+	)
+		.execute(&mut *tx)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	tx.commit()
+		.await
+		.map_err(DownloaderError::Sql)?;
+
+	Ok(kept.len() + pending.len())
+}
+
+/// the top level job a playlist entry belongs to, channels nest playlists in playlists
+pub async fn get_root_job(
+	db_pool: &Pool<Postgres>,
+	job_id: uuid::Uuid,
+) -> Result<uuid::Uuid, DownloaderError> {
+	sqlx::query!(r#"
+		WITH RECURSIVE ancestors AS (
+			SELECT id, parent_job
+			FROM download_jobs
+			WHERE id = $1
+			UNION ALL
+			SELECT job.id, job.parent_job
+			FROM download_jobs		AS job
+			JOIN ancestors					ON ancestors.parent_job = job.id
+		)
+		SELECT id AS "id!"
+		FROM ancestors
+		WHERE parent_job IS NULL
+		;"#,
+		job_id
+	)
+		.fetch_one(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+		.map(|rec| rec.id)
+}
+
+/// state counts of every download below an expanded job, nested playlists excluded
+pub async fn get_playlist_progress(
+	db_pool: &Pool<Postgres>,
+	job_id: uuid::Uuid,
+) -> Result<PubPlaylistProgress, DownloaderError> {
+	let rec = sqlx::query!(r#"
+		WITH RECURSIVE descendants AS (
+			SELECT id, state
+			FROM download_jobs
+			WHERE parent_job = $1
+			UNION ALL
+			SELECT job.id, job.state
+			FROM download_jobs		AS job
+			JOIN descendants				ON job.parent_job = descendants.id
+		)
+		SELECT
+			count(*)													AS "total!",
+			count(*) FILTER (WHERE state = 'completed')					AS "completed!",
+			count(*) FILTER (WHERE state IN ('failed', 'cancelled'))	AS "failed!",
+			count(*) FILTER (WHERE state IN ('queued', 'running'))		AS "pending!"
+		FROM descendants
+		WHERE state <> 'expanded'
+		;"#,
+		job_id
+	)
+		.fetch_one(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	Ok(PubPlaylistProgress {
+		total: rec.total,
+		completed: rec.completed,
+		failed: rec.failed,
+		pending: rec.pending,
+	})
+}
+
+/// a failed post-processing step doesn't fail the job, the download itself is kept and the error noted;
+/// a job cancelled in the meantime stays cancelled
+pub async fn complete_download_job(
+	db_pool: &Pool<Postgres>,
+	job_id: uuid::Uuid,
+	media_file: uuid::Uuid,
+	error: Option<String>,
+) -> Result<(), DownloaderError> {
+	let res = sqlx::query!("
+		UPDATE download_jobs
+		SET state		= 'completed',
+			media_file	= $2,
+			error		= $3,
+			finished_at	= now()
+		WHERE id = $1
+		  AND state = 'running'
+		;",
+		job_id,
+		media_file,
+		error
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	match res.rows_affected() {
+		0 => Err(DownloaderError::InvalidJobState),
+		_ => Ok(()),
+	}
+}
+
+pub async fn fail_download_job(
+	db_pool: &Pool<Postgres>,
+	job_id: uuid::Uuid,
+	error: String,
+) -> Result<(), DownloaderError> {
+	sqlx::query!("
+		UPDATE download_jobs
+		SET state		= 'failed',
+			error		= $2,
+			finished_at	= now()
+		WHERE id = $1
+		  AND state = 'running'
+		;",
+		job_id,
+		error
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+		.map(|_| ())
+}
+
+/// put a failed job back into the queue once the delay has passed
+pub async fn schedule_download_retry(
+	db_pool: &Pool<Postgres>,
+	job_id: uuid::Uuid,
+	error: String,
+	delay: Duration,
+) -> Result<(), DownloaderError> {
+	sqlx::query!("
+		UPDATE download_jobs
+		SET state			= 'queued',
+			error			= $2,
+			retry_count		= retry_count + 1,
+			started_at		= NULL,
+			next_attempt_at	= now() + make_interval(secs => $3)
+		WHERE id = $1
+		  AND state = 'running'
+		;",
+		job_id,
+		error,
+		delay.as_secs_f64()
+	)
+		.execute(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)
+		.map(|_| ())
+}
+
+/// cancel a queued, running or expanded job along with its playlist entries,
+/// returning the ids of the jobs that were running
+pub async fn cancel_download_job_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	job_id: uuid::Uuid,
+) -> Result<Vec<uuid::Uuid>, DownloaderError> {
+	let recs = sqlx::query!(r#"
+		WITH RECURSIVE tree AS (
+			SELECT id
+			FROM download_jobs
+			WHERE id = $1
+			  AND user_id = $2
+			UNION ALL
+			SELECT job.id
+			FROM download_jobs		AS job
+			JOIN tree						ON job.parent_job = tree.id
+		), prev AS (
+			SELECT job.id, job.state
+			FROM download_jobs		AS job
+			JOIN tree						ON tree.id = job.id
+			WHERE job.state IN ('queued', 'running', 'expanded')
+			FOR UPDATE OF job
+		)
+		UPDATE download_jobs
+		SET state		= 'cancelled',
+			finished_at	= now()
+		FROM prev
+		WHERE download_jobs.id = prev.id
+		RETURNING prev.id AS "id!", prev.state AS "state!"
+		;"#,
+		job_id,
+		user_id
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	if recs.is_empty() {
+		return Err(DownloaderError::InvalidJobState);
+	}
+	Ok(recs
+		.into_iter()
+		.filter(|rec| PubDownloadState::from(rec.state.clone()) == PubDownloadState::Running)
+		.map(|rec| rec.id)
+		.collect()
+	)
+}
+
+/// queue a failed or cancelled job again right away, if the quota still allows another download
+pub async fn retry_download_job_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	job_id: uuid::Uuid,
+) -> Result<(), DownloaderError> {
+	let mut tx = db_pool.begin()
+		.await
+		.map_err(DownloaderError::Sql)?;
+	check_enqueue_quota(&mut tx, user_id).await?;
+
+	let res = sqlx::query!("
+		UPDATE download_jobs
+		SET state			= 'queued',
+			retry_count		= retry_count + 1,
+			started_at		= NULL,
+			finished_at		= NULL,
+			next_attempt_at	= NULL
+		WHERE id = $1
+		  AND user_id = $2
+		  AND state IN ('failed', 'cancelled')
+		;",
+		job_id,
+		user_id
+	)
+		.execute(&mut *tx)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	if res.rows_affected() == 0 {
+		return Err(DownloaderError::InvalidJobState);
+	}
+	tx.commit()
+		.await
+		.map_err(DownloaderError::Sql)
+}
+
+fn is_transient_message(message: &str) -> bool {
+	let message = message.to_ascii_lowercase();
+	consts::TRANSIENT_PATTERNS
+		.iter()
+		.any(|pattern| message.contains(pattern))
+}
+
+fn is_transient_io(err: &std::io::Error) -> bool {
+	use std::io::ErrorKind;
+	matches!(
+		err.kind(),
+		  ErrorKind::TimedOut
+		| ErrorKind::ConnectionReset
+		| ErrorKind::ConnectionRefused
+		| ErrorKind::ConnectionAborted
+		| ErrorKind::NetworkUnreachable
+		| ErrorKind::HostUnreachable
+	)
+}
+
+/// whether retrying the download later has a chance of succeeding
+pub fn is_transient_error(err: &DownloaderError) -> bool {
+	match err {
+		DownloaderError::Ytdl(youtube_dl::Error::Io(err)) => is_transient_io(err),
+		DownloaderError::Ytdl(youtube_dl::Error::ExitCode { stderr, .. }) => is_transient_message(stderr),
+		DownloaderError::Ytdl(youtube_dl::Error::ProcessTimeout) => true,
+		DownloaderError::YtdlProcess(_, stderr) => is_transient_message(stderr),
+		DownloaderError::HttpStatus(status) => *status == 408 || *status == 429 || *status >= 500,
+		DownloaderError::Http(_, transient) => *transient,
+		_ => false,
+	}
+}
+
+/// exponential backoff for the given number of previous retries
+pub fn get_retry_delay(retry_count: i32) -> Option<Duration> {
+	if retry_count >= consts::MAX_AUTO_RETRIES {
+		return None;
+	}
+	let delay = consts::RETRY_BASE_DELAY.saturating_mul(2u32.saturating_pow(retry_count.max(0) as u32));
+	Some(delay.min(consts::RETRY_MAX_DELAY))
+}
+
+/// jobs left running by a previous process never finished, queue them again
+pub async fn requeue_interrupted_jobs(
+	db_pool: &Pool<Postgres>,
+) -> Result<Vec<uuid::Uuid>, DownloaderError> {
+	let recs = sqlx::query!("
+		UPDATE download_jobs
+		SET state		= 'queued',
+			started_at	= NULL
+		WHERE state = 'running'
+		RETURNING id
+		;"
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	Ok(recs
+		.into_iter()
+		.map(|rec| rec.id)
+		.collect()
+	)
+}
+
+pub async fn get_download_job_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	job_id: uuid::Uuid,
+) -> Result<Option<PubDownloadJob>, DownloaderError> {
+	let rec = sqlx::query!("
+		SELECT id, url, title, parent_job, playlist_index, subscription, audio_only, state, error, retry_count, next_attempt_at, media_file, created_at, started_at, finished_at
+		FROM download_jobs
+		WHERE user_id = $1
+		  AND id = $2
+		;",
+		user_id,
+		job_id
+	)
+		.fetch_optional(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	let Some(rec) = rec else {
+		return Ok(None);
+	};
+	let state = PubDownloadState::from(rec.state);
+	let playlist = match state {
+		PubDownloadState::Expanded => Some(get_playlist_progress(db_pool, rec.id).await?),
+		_ => None,
+	};
+
+	Ok(Some(PubDownloadJob {
+		id: rec.id,
+		url: rec.url,
+		title: rec.title,
+		parent_job: rec.parent_job,
+		playlist_index: rec.playlist_index,
+		playlist,
+		subscription: rec.subscription,
+		audio_only: rec.audio_only,
+		state,
+		error: rec.error,
+		retry_count: rec.retry_count,
+		next_attempt_at: rec.next_attempt_at,
+		media_file: rec.media_file,
+		created_at: rec.created_at,
+		started_at: rec.started_at,
+		finished_at: rec.finished_at,
+	}))
+}
+
+pub async fn get_download_jobs_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	limit: i64,
+) -> Result<Vec<PubDownloadJob>, DownloaderError> {
+	let recs = sqlx::query!("
+		SELECT id, url, title, parent_job, playlist_index, subscription, audio_only, state, error, retry_count, next_attempt_at, media_file, created_at, started_at, finished_at
+		FROM download_jobs
+		WHERE user_id = $1
+		  AND parent_job IS NULL
+		ORDER BY created_at DESC
+		LIMIT $2
+		;",
+		user_id,
+		limit
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(DownloaderError::Sql)?
+	;
+
+	let mut jobs = Vec::with_capacity(recs.len());
+	for rec in recs {
+		let state = PubDownloadState::from(rec.state);
+		let playlist = match state {
+			PubDownloadState::Expanded => Some(get_playlist_progress(db_pool, rec.id).await?),
+			_ => None,
+		};
+		jobs.push(PubDownloadJob {
+			id: rec.id,
+			url: rec.url,
+			title: rec.title,
+			parent_job: rec.parent_job,
+			playlist_index: rec.playlist_index,
+			playlist,
+			subscription: rec.subscription,
+			audio_only: rec.audio_only,
+			state,
+			error: rec.error,
+			retry_count: rec.retry_count,
+			next_attempt_at: rec.next_attempt_at,
+			media_file: rec.media_file,
+			created_at: rec.created_at,
+			started_at: rec.started_at,
+			finished_at: rec.finished_at,
+		});
+	}
+	Ok(jobs)
+}
+
+pub(crate) async fn get_file_type(
+	file: FileRef,
+	infer_type: infer::MatcherType,
+) -> Result<VFSFileType, MediaError> {
+	match infer_type {
+		  infer::MatcherType::Video
+		| infer::MatcherType::Audio => Ok(VFSFileType::Multimedia(
+			get_media_file_metadata(file.path.clone()).await?
+		)),
+		infer::MatcherType::Image => Ok(VFSFileType::Image(
+			get_media_file_metadata(file.path.clone()).await?
+		)),
+		_ => {
+			println!("invalid file type received from ytdl, removing");
+			let _ = file.delete_file();
+			Err(MediaError::InvalidType)
+		},
+	}
+}
+
+/// waveform and loudness can take a while, don't hold up the download for them
+pub(crate) async fn spawn_media_analysis(
+	db_pool: &Pool<Postgres>,
+	media_file_id: uuid::Uuid,
+) {
+	if let Ok(info) = get_vfs_file_info(db_pool, media_file_id).await {
+		let db_pool = db_pool.clone();
+		tokio::spawn(async move {
+			if let Err(err) = analyze_and_store(&db_pool, media_file_id, get_abs_file_path(&info.file_path)).await {
+				println!("error analyzing '{}': {err:?}", info.file_path);
+			}
+		});
+	}
+}
+
+/// move a finished download into the vfs along with its tracks, chapters and thumbnail
+pub async fn import_ytdl_result(
+	db_pool: &Pool<Postgres>,
+	ytdl_res: YtdlResult,
+	vfs_target: Option<VfsTarget>,
+	playlist_index: Option<i32>,
+) -> Result<uuid::Uuid, DownloaderError> {
+	let title = ytdl_res.output.title.clone().unwrap_or("UNKNOWN TITLE??".to_string());
+	// the prefix keeps playlist entries in order when sorted by name
+	let name = match playlist_index {
+		Some(index) => format!("{index:03} - {title}"),
+		None => title,
+	};
+
+	let file_data = VfsFileData {
+		name: name.clone(),
+		file: ytdl_res.media.clone(),
+		file_type: get_file_type(ytdl_res.media.clone(), infer::MatcherType::Audio)
+			.await
+			.map_err(DownloaderError::Media)?,
+		hide: false,
+	};
+	let probe = match &file_data.file_type {
+		VFSFileType::Multimedia(probe) => Some(probe.clone()),
+		_ => None,
+	};
+
+	// prefer subtitles from the source, fall back to the ones embedded in the file
+	let manual_langs = ytdl_res.output.subtitles.clone().unwrap_or_default();
+	let mut tracks: Vec<VfsTrackData> = ytdl_res.subtitles
+		.iter()
+		.map(|sub| VfsTrackData {
+			kind: match manual_langs.contains_key(&sub.lang) {
+				true => PubTrackKind::Subtitles,
+				false => PubTrackKind::Captions,
+			},
+			lang: Some(sub.lang.clone()),
+			file: sub.file.clone(),
+		})
+		.collect()
+	;
+	if tracks.is_empty()
+	&& let Some(probe) = &probe
+	&& let Some(out_dir) = ytdl_res.media.path.parent() {
+		tracks = extract_embedded_subtitles(ytdl_res.media.path.clone(), probe, out_dir.to_path_buf())
+			.await
+			.map_err(DownloaderError::Media)?
+			.into_iter()
+			.map(|sub| VfsTrackData {
+				kind: PubTrackKind::Subtitles,
+				lang: sub.lang,
+				file: sub.file,
+			})
+			.collect()
+		;
+	}
+
+	let chapters: Vec<PubVfsChapter> = match ytdl_res.output.chapters.as_ref().filter(|chapters| !chapters.is_empty()) {
+		Some(chapters) => chapters
+			.iter()
+			.filter_map(|chapter| Some(PubVfsChapter {
+				start: chapter.start_time?,
+				end: chapter.end_time,
+				title: chapter.title.clone(),
+			}))
+			.collect(),
+		None => probe
+			.map(|probe| probe.chapters
+				.iter()
+				.map(|chapter| PubVfsChapter {
+					start: chapter.start_time,
+					end: chapter.end_time,
+					title: chapter.tags.as_ref().and_then(|tags| tags.title.clone()),
+				})
+				.collect()
+			)
+			.unwrap_or_default(),
+	};
+
+	let (media_file_id, media_node_id) = commit_file_to_vfs(
+		file_data,
+		db_pool,
+		vfs_target.clone()
+	)
+		.await
+		.map_err(DownloaderError::Vfs)?
+	;
+
+	for track in tracks {
+		commit_track_to_vfs(db_pool, media_file_id, media_node_id, &name, track)
+			.await
+			.map_err(DownloaderError::Vfs)?
+		;
+	}
+	set_media_chapters(db_pool, media_file_id, &chapters)
+		.await
+		.map_err(DownloaderError::Vfs)?
+	;
+	if let Err(err) = record_provenance(db_pool, media_file_id, &ytdl_res.output).await {
+		println!("unable to record the source of '{name}': {err:?}");
+	}
+
+	spawn_media_analysis(db_pool, media_file_id).await;
+
+	if let Some(thumb_file) = ytdl_res.thumbnail.clone() {
+		let file_data = VfsFileData {
+			name: name.clone() + " Thumbnail",
+			file: thumb_file.clone(),
+			file_type: get_file_type(thumb_file, infer::MatcherType::Image)
+				.await
+				.map_err(DownloaderError::Media)?,
+			hide: true,
+		};
+
+		let (file_id, _) = commit_file_to_vfs(
+			file_data,
+			db_pool,
+			vfs_target.clone()
+		)
+			.await
+			.map_err(DownloaderError::Vfs)?
+		;
+
+		set_thumbnail(db_pool, media_file_id, file_id)
+			.await
+			.map_err(DownloaderError::Vfs)?
+		;
+	};
+
+	#[cfg(debug_assertions)]
+	{
+		let _ = crate::util::copy_dir_all(
+			std::path::PathBuf::from("./site"),
+			std::path::PathBuf::from("./target/site")
+		);
+	}
+
+	Ok(media_file_id)
+}
+
+/// move a direct download into the vfs, what it is stored as is decided by its content
+pub async fn import_direct_file(
+	db_pool: &Pool<Postgres>,
+	direct: DirectFile,
+	vfs_target: Option<VfsTarget>,
+	playlist_index: Option<i32>,
+) -> Result<uuid::Uuid, DownloaderError> {
+	let name = match playlist_index {
+		Some(index) => format!("{index:03} - {}", direct.name),
+		None => direct.name.clone(),
+	};
+	let inferred = infer::get_from_path(&direct.file.path)
+		.map_err(DownloaderError::Io)?
+	;
+	let reported = direct.mime_type.clone().unwrap_or_default();
+
+	let file_type = match inferred.map(|itype| (itype.matcher_type(), itype.mime_type())) {
+		Some((
+			  matcher @ infer::MatcherType::Video
+			| matcher @ infer::MatcherType::Audio
+			| matcher @ infer::MatcherType::Image,
+			_
+		)) => get_file_type(direct.file.clone(), matcher)
+			.await
+			.map_err(DownloaderError::Media)?,
+		// infer files pdfs under archives
+		Some((_, "application/pdf")) => VFSFileType::Text,
+		// plain text has no magic bytes, the server has to be trusted there
+		None if reported.starts_with("text/") => VFSFileType::Text,
+		other => {
+			let _ = direct.file.delete_file();
+			let mime_type = other.map(|(_, mime_type)| mime_type.to_string()).unwrap_or(reported);
+			return Err(DownloaderError::UnsupportedType(mime_type));
+		},
+	};
+	let is_media = matches!(file_type, VFSFileType::Multimedia(_));
+
+	let file_data = VfsFileData {
+		name: name.clone(),
+		file: direct.file,
+		file_type,
+		hide: false,
+	};
+	let (file_id, _) = commit_file_to_vfs(
+		file_data,
+		db_pool,
+		vfs_target
+	)
+		.await
+		.map_err(DownloaderError::Vfs)?
+	;
+
+	if let Err(err) = record_direct_provenance(db_pool, file_id, &direct.url).await {
+		println!("unable to record the source of '{name}': {err:?}");
+	}
+	if is_media {
+		spawn_media_analysis(db_pool, file_id).await;
+	}
+
+	Ok(file_id)
+}
@@ -20,4 +20,5 @@ pub mod macros;
 pub mod ws;
 pub mod vfs;
 pub mod media;
-pub mod downloader;
\ No newline at end of file
+pub mod downloader;
+pub mod playback;
\ No newline at end of file
@@ -0,0 +1,277 @@
+use std::{path::{Path, PathBuf}, process::Stdio, sync::LazyLock};
+
+use sqlx::{Pool, Postgres};
+use tokio::{io::AsyncReadExt, process::Command, sync::Semaphore};
+
+use super::prelude::*;
+use crate::vfs::util::get_abs_file_path;
+
+mod consts {
+	use std::time::Duration;
+
+	/// number of peaks stored per file, independent of its duration
+	pub const PEAK_COUNT: usize = 1000;
+	/// mono sample rate the audio is decoded at for peak detection
+	pub const PEAK_SAMPLE_RATE: u32 = 4000;
+	/// samples folded into one intermediate peak while streaming
+	pub const PEAK_WINDOW: usize = 400;
+	/// loudness all files are normalized to, in LUFS
+	pub const REPLAY_GAIN_REFERENCE: f64 = -18.0;
+
+	pub const MAX_CONCURRENT_ANALYSES: usize = 1;
+	pub const ANALYSIS_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+}
+
+static ANALYSIS_PERMITS: LazyLock<Semaphore> = LazyLock::new(|| Semaphore::new(consts::MAX_CONCURRENT_ANALYSES));
+
+#[derive(Debug, Clone)]
+pub struct MediaAnalysis {
+	pub peaks: Vec<u8>,
+	pub integrated_lufs: Option<f64>,
+	pub loudness_range: Option<f64>,
+	pub true_peak: Option<f64>,
+	pub replay_gain: Option<f64>,
+}
+
+fn ffmpeg_command(path: &Path) -> Command {
+	let mut command = Command::new(binary_path(&PubBinaryKind::Ffmpeg));
+	command
+		.args(["-hide_banner", "-nostats", "-i"])
+		.arg(path)
+		.stdin(Stdio::null())
+		.kill_on_drop(true)
+	;
+	command
+}
+
+/// downsample the intermediate peaks to a fixed count, scaled to 0..=255
+fn fold_peaks(window_peaks: &[u16]) -> Vec<u8> {
+	if window_peaks.is_empty() {
+		return vec![];
+	}
+
+	let bucket_size = window_peaks.len().div_ceil(consts::PEAK_COUNT);
+	window_peaks
+		.chunks(bucket_size)
+		.map(|bucket| {
+			let peak = bucket.iter().copied().max().unwrap_or(0);
+			(peak as u32 * 255 / i16::MAX as u32).min(255) as u8
+		})
+		.collect()
+}
+
+async fn get_waveform_peaks(
+	path: &Path,
+) -> Result<Vec<u8>, MediaError> {
+	let mut child = ffmpeg_command(path)
+		.args(["-vn", "-ac", "1", "-ar"])
+		.arg(consts::PEAK_SAMPLE_RATE.to_string())
+		.args(["-f", "s16le", "-"])
+		.stdout(Stdio::piped())
+		.stderr(Stdio::null())
+		.spawn()
+		.map_err(MediaError::Io)?
+	;
+	let mut stdout = child.stdout.take().ok_or(MediaError::BinaryMissing)?;
+
+	let mut window_peaks: Vec<u16> = vec![];
+	let mut current_peak: u16 = 0;
+	let mut samples_in_window = 0;
+	let mut buf = vec![0u8; 64 * 1024];
+	let mut carry: Option<u8> = None;
+	loop {
+		let read = stdout.read(&mut buf)
+			.await
+			.map_err(MediaError::Io)?
+		;
+		if read == 0 {
+			break;
+		}
+
+		let mut bytes = buf[..read].iter().copied();
+		loop {
+			let Some(lo) = carry.take().or_else(|| bytes.next()) else {
+				break;
+			};
+			let Some(hi) = bytes.next() else {
+				carry = Some(lo);
+				break;
+			};
+
+			let sample = i16::from_le_bytes([lo, hi]).unsigned_abs();
+			current_peak = current_peak.max(sample);
+			samples_in_window += 1;
+			if samples_in_window == consts::PEAK_WINDOW {
+				window_peaks.push(current_peak);
+				current_peak = 0;
+				samples_in_window = 0;
+			}
+		}
+	}
+	if samples_in_window > 0 {
+		window_peaks.push(current_peak);
+	}
+
+	let status = child.wait()
+		.await
+		.map_err(MediaError::Io)?
+	;
+	if !status.success() {
+		return Err(MediaError::ProbeFailed(status.code(), "waveform extraction failed".to_string()));
+	}
+
+	Ok(fold_peaks(&window_peaks))
+}
+
+/// read a value like `I:         -16.8 LUFS` from the ebur128 summary
+fn parse_summary_value(summary: &str, key: &str) -> Option<f64> {
+	summary
+		.lines()
+		.map(str::trim)
+		.find_map(|line| line.strip_prefix(key))
+		.and_then(|rest| rest.split_whitespace().next())
+		.and_then(|value| value.parse().ok())
+}
+
+async fn get_loudness(
+	path: &Path,
+) -> Result<(Option<f64>, Option<f64>, Option<f64>), MediaError> {
+	let output = ffmpeg_command(path)
+		.args(["-vn", "-af", "ebur128=peak=true", "-f", "null", "-"])
+		.stdout(Stdio::null())
+		.stderr(Stdio::piped())
+		.output()
+		.await
+		.map_err(MediaError::Io)?
+	;
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	if !output.status.success() {
+		return Err(MediaError::ProbeFailed(output.status.code(), stderr.trim().to_string()));
+	}
+
+	let Some((_, summary)) = stderr.rsplit_once("Summary:") else {
+		return Ok((None, None, None));
+	};
+	Ok((
+		parse_summary_value(summary, "I:"),
+		parse_summary_value(summary, "LRA:"),
+		parse_summary_value(summary, "Peak:"),
+	))
+}
+
+pub async fn analyze_media(
+	path: PathBuf,
+) -> Result<MediaAnalysis, MediaError> {
+	let _permit = ANALYSIS_PERMITS
+		.acquire()
+		.await
+		.map_err(|err| MediaError::Ffmpeg(err.into()))?
+	;
+
+	let analysis = async {
+		let peaks = get_waveform_peaks(&path).await?;
+		let (integrated_lufs, loudness_range, true_peak) = get_loudness(&path).await?;
+		Ok(MediaAnalysis {
+			peaks,
+			integrated_lufs,
+			loudness_range,
+			true_peak,
+			replay_gain: integrated_lufs.map(|lufs| consts::REPLAY_GAIN_REFERENCE - lufs),
+		})
+	};
+
+	tokio::time::timeout(consts::ANALYSIS_TIMEOUT, analysis)
+		.await
+		.map_err(|_| MediaError::ProbeTimeout)?
+}
+
+pub async fn store_media_analysis(
+	db_pool: &Pool<Postgres>,
+	file_id: uuid::Uuid,
+	analysis: &MediaAnalysis,
+) -> Result<(), MediaError> {
+	sqlx::query!("
+		INSERT INTO media_analysis
+			(id, peaks, integrated_lufs, loudness_range, true_peak, replay_gain)
+		VALUES
+			($1, $2, $3, $4, $5, $6)
+		ON CONFLICT (id) DO UPDATE
+		SET peaks			= EXCLUDED.peaks,
+			integrated_lufs	= EXCLUDED.integrated_lufs,
+			loudness_range	= EXCLUDED.loudness_range,
+			true_peak		= EXCLUDED.true_peak,
+			replay_gain		= EXCLUDED.replay_gain,
+			analyzed_at		= now()
+		;",
+		file_id,
+		analysis.peaks,
+		analysis.integrated_lufs,
+		analysis.loudness_range,
+		analysis.true_peak,
+		analysis.replay_gain
+	)
+		.execute(db_pool)
+		.await
+		.map_err(MediaError::Sql)
+		.map(|_| ())
+}
+
+pub async fn analyze_and_store(
+	db_pool: &Pool<Postgres>,
+	file_id: uuid::Uuid,
+	path: PathBuf,
+) -> Result<(), MediaError> {
+	let analysis = analyze_media(path).await?;
+	store_media_analysis(db_pool, file_id, &analysis).await
+}
+
+pub async fn get_media_analysis_internal(
+	db_pool: &Pool<Postgres>,
+	node_id: uuid::Uuid,
+) -> Result<Option<PubMediaAnalysis>, MediaError> {
+	sqlx::query_as!(
+		PubMediaAnalysis,
+		"SELECT
+			analysis.peaks,
+			analysis.integrated_lufs,
+			analysis.replay_gain
+		FROM vfs_nodes				AS node
+		JOIN media_analysis			AS analysis	ON analysis.id = node.vfs_file
+		WHERE node.id = $1
+		;",
+		node_id
+	)
+		.fetch_optional(db_pool)
+		.await
+		.map_err(MediaError::Sql)
+}
+
+/// analyze every audio and video file that has not been analyzed yet, one at a time
+pub async fn backfill_media_analysis(
+	db_pool: Pool<Postgres>,
+) -> Result<(), MediaError> {
+	let pending = sqlx::query!("
+		SELECT file.id, file.file_path
+		FROM vfs_files				AS file
+		LEFT JOIN media_analysis	AS analysis	ON analysis.id = file.id
+		WHERE file.file_type IN ('audio', 'video')
+		  AND analysis.id IS NULL
+		ORDER BY file.created_at DESC
+		;"
+	)
+		.fetch_all(&db_pool)
+		.await
+		.map_err(MediaError::Sql)?
+	;
+
+	if !pending.is_empty() {
+		println!("analyzing {} media files...", pending.len());
+	}
+	for file in pending {
+		if let Err(err) = analyze_and_store(&db_pool, file.id, get_abs_file_path(&file.file_path)).await {
+			println!("unable to analyze '{}': {err:?}", file.file_path);
+		}
+	}
+
+	Ok(())
+}
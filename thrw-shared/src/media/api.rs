@@ -0,0 +1,39 @@
+use super::prelude::*;
+
+use crate::prelude::*;
+use crate::user::prelude::*;
+
+#[server]
+pub async fn get_media_binaries(
+	recheck: bool,
+) -> Result<Vec<PubBinaryStatus>, ServerFnError> {
+	require_permission(Permission::ManageServer).await?;
+
+	if recheck {
+		return Ok(verify_binaries().await);
+	}
+	Ok(get_binary_status_internal())
+}
+
+#[server]
+pub async fn update_media_binary(
+	kind: PubBinaryKind,
+) -> Result<PubBinaryStatus, ServerFnError> {
+	require_permission(Permission::ManageServer).await?;
+
+	update_binary(kind)
+		.await
+		.map_err(make_server_err)
+}
+
+#[server]
+pub async fn get_media_analysis(
+	node: uuid::Uuid,
+) -> Result<Option<PubMediaAnalysis>, ServerFnError> {
+	let _ = require_scope(ApiScope::Files).await?;
+	let db = extract_db()?;
+
+	get_media_analysis_internal(&db, node)
+		.await
+		.map_err(make_server_err)
+}
@@ -0,0 +1,223 @@
+use std::{path::PathBuf, process::Stdio, sync::{LazyLock, RwLock}};
+
+use tokio::process::Command;
+
+use super::prelude::*;
+
+mod consts {
+	use std::time::Duration;
+
+	pub const FFMPEG_PATH_ENV: &str = "THRW_FFMPEG_PATH";
+	pub const FFPROBE_PATH_ENV: &str = "THRW_FFPROBE_PATH";
+	pub const YTDL_PATH_ENV: &str = "THRW_YTDL_PATH";
+	pub const YTDL_DIR_ENV: &str = "YTDL_LOCATION";
+	/// opt-in to fetching missing managed binaries at startup
+	pub const AUTO_DOWNLOAD_ENV: &str = "THRW_BINARY_AUTO_DOWNLOAD";
+
+	pub const DEFAULT_YTDL_DIR: &str = "./ytdl";
+	#[cfg(target_family = "windows")]
+	pub const YTDL_BIN: &str = "yt-dlp.exe";
+	#[cfg(target_family = "unix")]
+	pub const YTDL_BIN: &str = "yt-dlp_linux";
+
+	pub const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+}
+
+/// where the media binaries are taken from; binaries given through the environment
+/// are system provided and never replaced, all others are managed by us
+#[derive(Debug, Clone)]
+pub struct BinaryConfig {
+	pub ffmpeg: Option<PathBuf>,
+	pub ffprobe: Option<PathBuf>,
+	pub ytdl: Option<PathBuf>,
+	pub ytdl_dir: PathBuf,
+	pub auto_download: bool,
+}
+impl BinaryConfig {
+	fn from_env() -> Self {
+		let path_var = |name| std::env::var(name).ok().map(PathBuf::from);
+		Self {
+			ffmpeg: path_var(consts::FFMPEG_PATH_ENV),
+			ffprobe: path_var(consts::FFPROBE_PATH_ENV),
+			ytdl: path_var(consts::YTDL_PATH_ENV),
+			ytdl_dir: path_var(consts::YTDL_DIR_ENV).unwrap_or(PathBuf::from(consts::DEFAULT_YTDL_DIR)),
+			auto_download: std::env::var(consts::AUTO_DOWNLOAD_ENV)
+				.is_ok_and(|val| matches!(val.to_ascii_lowercase().as_str(), "1" | "true" | "yes")),
+		}
+	}
+
+	pub fn is_managed(&self, kind: &PubBinaryKind) -> bool {
+		match kind {
+			PubBinaryKind::Ffmpeg => self.ffmpeg.is_none(),
+			PubBinaryKind::Ffprobe => self.ffprobe.is_none(),
+			PubBinaryKind::Ytdl => self.ytdl.is_none(),
+		}
+	}
+}
+
+static BINARY_CONFIG: LazyLock<BinaryConfig> = LazyLock::new(BinaryConfig::from_env);
+static BINARY_STATUS: LazyLock<RwLock<Vec<PubBinaryStatus>>> = LazyLock::new(Default::default);
+
+pub fn binary_config() -> &'static BinaryConfig {
+	&BINARY_CONFIG
+}
+
+pub fn binary_path(kind: &PubBinaryKind) -> PathBuf {
+	let config = binary_config();
+	match kind {
+		PubBinaryKind::Ffmpeg => config.ffmpeg
+			.clone()
+			.unwrap_or_else(ffmpeg_sidecar::paths::ffmpeg_path),
+		PubBinaryKind::Ffprobe => config.ffprobe
+			.clone()
+			.unwrap_or_else(ffmpeg_sidecar::ffprobe::ffprobe_path),
+		PubBinaryKind::Ytdl => config.ytdl
+			.clone()
+			.unwrap_or_else(|| config.ytdl_dir.join(consts::YTDL_BIN)),
+	}
+}
+
+async fn get_binary_version(
+	kind: &PubBinaryKind,
+) -> Result<String, MediaError> {
+	let version_arg = match kind {
+		PubBinaryKind::Ffmpeg | PubBinaryKind::Ffprobe => "-version",
+		PubBinaryKind::Ytdl => "--version",
+	};
+
+	let child = Command::new(binary_path(kind))
+		.arg(version_arg)
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::null())
+		.kill_on_drop(true)
+		.spawn()
+		.map_err(|err| match err.kind() {
+			std::io::ErrorKind::NotFound => MediaError::BinaryMissing,
+			_ => MediaError::Io(err),
+		})?
+	;
+	let output = tokio::time::timeout(consts::VERSION_TIMEOUT, child.wait_with_output())
+		.await
+		.map_err(|_| MediaError::ProbeTimeout)?
+		.map_err(MediaError::Io)?
+	;
+	if !output.status.success() {
+		return Err(MediaError::ProbeFailed(output.status.code(), String::new()));
+	}
+
+	// "ffmpeg version 7.1 Copyright ..." / "2025.06.30"
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let first_line = stdout.lines().next().unwrap_or_default();
+	let version = first_line
+		.split_whitespace()
+		.skip_while(|word| *word != "version")
+		.nth(1)
+		.unwrap_or(first_line.trim())
+		.to_string()
+	;
+	Ok(version)
+}
+
+async fn check_binary(
+	kind: PubBinaryKind,
+) -> PubBinaryStatus {
+	let version = get_binary_version(&kind).await;
+	PubBinaryStatus {
+		path: binary_path(&kind).to_string_lossy().into_owned(),
+		managed: binary_config().is_managed(&kind),
+		version: version.as_ref().ok().cloned(),
+		error: version.err().map(|err| format!("{err:?}")),
+		checked_at: chrono::Utc::now(),
+		kind,
+	}
+}
+
+/// check every binary and remember the results for the admin page
+pub async fn verify_binaries() -> Vec<PubBinaryStatus> {
+	let status = futures::future::join_all(
+		PubBinaryKind::ALL
+			.into_iter()
+			.map(check_binary)
+	).await;
+
+	*BINARY_STATUS.write().unwrap() = status.clone();
+	status
+}
+
+pub fn get_binary_status_internal() -> Vec<PubBinaryStatus> {
+	BINARY_STATUS.read().unwrap().clone()
+}
+
+fn download_ffmpeg() -> Result<(), MediaError> {
+	use ffmpeg_sidecar::{download::{download_ffmpeg_package, ffmpeg_download_url, unpack_ffmpeg}, paths::sidecar_dir};
+
+	let to_err = |err: anyhow::Error| MediaError::BinaryDownload(err.to_string());
+	let destination = sidecar_dir().map_err(to_err)?;
+	let url = ffmpeg_download_url().map_err(to_err)?;
+	let archive = download_ffmpeg_package(url, &destination).map_err(to_err)?;
+	unpack_ffmpeg(&archive, &destination).map_err(to_err)
+}
+
+/// download the latest release of a managed binary, replacing the current one
+pub async fn update_binary(
+	kind: PubBinaryKind,
+) -> Result<PubBinaryStatus, MediaError> {
+	if !binary_config().is_managed(&kind) {
+		return Err(MediaError::BinaryNotManaged);
+	}
+
+	println!("updating {kind:?}...");
+	match kind {
+		// ffprobe ships in the same package as ffmpeg
+		PubBinaryKind::Ffmpeg | PubBinaryKind::Ffprobe => {
+			tokio::task::spawn_blocking(download_ffmpeg)
+				.await
+				.map_err(|err| MediaError::BinaryDownload(err.to_string()))??
+		},
+		PubBinaryKind::Ytdl => {
+			youtube_dl::download_yt_dlp(&binary_config().ytdl_dir)
+				.await
+				.map_err(|err| MediaError::BinaryDownload(err.to_string()))?
+			;
+		},
+	};
+
+	let status = verify_binaries().await;
+	status
+		.into_iter()
+		.find(|status| status.kind == kind)
+		.ok_or(MediaError::BinaryMissing)
+}
+
+/// verify all binaries, fetching missing managed ones only when explicitly allowed
+pub async fn init_binaries() -> Vec<PubBinaryStatus> {
+	let mut status = verify_binaries().await;
+
+	if binary_config().auto_download {
+		let mut missing: Vec<PubBinaryKind> = status
+			.iter()
+			.filter(|status| status.managed && status.version.is_none())
+			.map(|status| status.kind.clone())
+			.collect()
+		;
+		// both come from the same package
+		if missing.contains(&PubBinaryKind::Ffmpeg) {
+			missing.retain(|kind| *kind != PubBinaryKind::Ffprobe);
+		}
+		for kind in missing {
+			if let Err(err) = update_binary(kind.clone()).await {
+				println!("unable to download {kind:?}: {err:?}");
+			}
+		}
+		status = verify_binaries().await;
+	}
+
+	for bin in status.iter() {
+		match &bin.version {
+			Some(version) => println!("{:?} {version} at '{}'", bin.kind, bin.path),
+			None => println!("{:?} unavailable at '{}': {}", bin.kind, bin.path, bin.error.clone().unwrap_or_default()),
+		}
+	}
+	status
+}
@@ -1,20 +1,76 @@
+pub mod api;
 #[cfg(feature = "server")]
 pub mod util;
+#[cfg(feature = "server")]
+pub mod binaries;
+#[cfg(feature = "server")]
+pub mod analysis;
 
 pub mod prelude {
+	pub use super::api::*;
 	pub use super::shared::*;
 	#[cfg(feature = "server")]
 	pub use super::util::*;
+	#[cfg(feature = "server")]
+	pub use super::binaries::*;
+	#[cfg(feature = "server")]
+	pub use super::analysis::*;
 }
 
 pub mod shared {
+	use serde::{Deserialize, Serialize};
+
+	#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+	pub enum PubBinaryKind {
+		Ffmpeg,
+		Ffprobe,
+		Ytdl,
+	}
+	impl PubBinaryKind {
+		pub const ALL: [Self; 3] = [Self::Ffmpeg, Self::Ffprobe, Self::Ytdl];
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubBinaryStatus {
+		pub kind: PubBinaryKind,
+		pub path: String,
+		/// managed binaries can be updated from the admin page, system provided ones can't
+		pub managed: bool,
+		pub version: Option<String>,
+		pub error: Option<String>,
+		pub checked_at: chrono::DateTime<chrono::Utc>,
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubMediaAnalysis {
+		/// amplitude of evenly spaced slices of the media, 0..=255
+		pub peaks: Vec<u8>,
+		pub integrated_lufs: Option<f64>,
+		/// gain in dB to reach the reference loudness
+		pub replay_gain: Option<f64>,
+	}
+
 	#[derive(Debug)]
 	pub enum MediaError {
 		Io(std::io::Error),
 		InvalidPath,
 		InvalidType,
 		Json(serde_json::Error),
+		/// ffprobe is not installed or not on the path
+		ProbeMissing,
+		ProbeTimeout,
+		/// ffprobe could not make sense of the file
+		InvalidMedia(String),
+		/// ffprobe exited unsuccessfully with the given code and stderr
+		ProbeFailed(Option<i32>, String),
+		/// a configured binary could not be found
+		BinaryMissing,
+		/// system provided binaries are not updated by us
+		BinaryNotManaged,
+		BinaryDownload(String),
 		#[cfg(feature = "server")]
 		Ffmpeg(anyhow::Error),
+		#[cfg(feature = "server")]
+		Sql(sqlx::Error),
 	}
 }
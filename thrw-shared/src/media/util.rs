@@ -1,9 +1,12 @@
-use std::{path::PathBuf, process::Command};
+use std::{collections::HashMap, io::SeekFrom, path::{Path, PathBuf}, process::Stdio, sync::{LazyLock, Mutex}};
 
 use super::prelude::*;
+use crate::vfs::util::FileRef;
 use ffmpeg_sidecar::command::FfmpegCommand;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
+use sha2::{Digest, Sha256};
+use tokio::{io::{AsyncReadExt, AsyncSeekExt}, process::Command, sync::Semaphore};
 // use ffmpeg_next::format::context::Input;
 use sqlx::{Pool, Postgres};
 
@@ -18,12 +21,18 @@ use sqlx::{Pool, Postgres};
 pub enum MediaCodecType {
 	Audio,
 	Video,
+	Subtitle,
+	Data,
+	Attachment,
 }
 impl ToString for MediaCodecType {
 	fn to_string(&self) -> String {
 		match self {
 			MediaCodecType::Audio => "audio",
 			MediaCodecType::Video => "video",
+			MediaCodecType::Subtitle => "subtitle",
+			MediaCodecType::Data => "data",
+			MediaCodecType::Attachment => "attachment",
 		}.to_string()
 	}
 }
@@ -32,6 +41,8 @@ impl ToString for MediaCodecType {
 pub struct FFProbeMediaOutput {
     pub streams: Vec<Stream>,
     pub format: Format,
+	#[serde(default)]
+    pub chapters: Vec<Chapter>,
 }
 
 #[serde_as]
@@ -150,37 +161,245 @@ pub struct FormatTags {
     pub encoder: Option<String>,
 }
 
-fn get_file_metadata_output(
-	path: PathBuf,
-) -> Result<std::process::Output, MediaError> {
-	let path = path.to_string_lossy();
-	Command::new("ffprobe")
+#[serde_as]
+#[derive(Debug, Clone, Deserialize)]
+pub struct Chapter {
+    pub id: i64,
+	#[serde_as(as = "DisplayFromStr")]
+    pub start_time: f64,
+	#[serde_as(as = "Option<DisplayFromStr>")]
+    pub end_time: Option<f64>,
+    pub tags: Option<ChapterTags>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChapterTags {
+    pub title: Option<String>,
+}
+
+mod consts {
+	use std::time::Duration;
+
+	pub const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+	pub const MAX_CONCURRENT_PROBES: usize = 4;
+	/// random eviction once full, an arbitrary entry makes room for the new one
+	pub const PROBE_CACHE_SIZE: usize = 1024;
+	/// bytes hashed from the start and the end of a file to build its cache key
+	pub const HASH_CHUNK_SIZE: u64 = 1024 * 1024;
+}
+
+static PROBE_PERMITS: LazyLock<Semaphore> = LazyLock::new(|| Semaphore::new(consts::MAX_CONCURRENT_PROBES));
+static PROBE_CACHE: LazyLock<Mutex<HashMap<String, FFProbeMediaOutput>>> = LazyLock::new(Default::default);
+
+/// hash of the file size and its first and last chunk, cheap enough for large media files
+pub async fn get_file_hash(
+	path: &Path,
+) -> Result<String, MediaError> {
+	let mut file = tokio::fs::File::open(path)
+		.await
+		.map_err(MediaError::Io)?
+	;
+	let size = file.metadata()
+		.await
+		.map_err(MediaError::Io)?
+		.len()
+	;
+
+	let mut hasher = Sha256::new();
+	hasher.update(size.to_le_bytes());
+
+	let mut buf = Vec::with_capacity(consts::HASH_CHUNK_SIZE as usize);
+	(&mut file).take(consts::HASH_CHUNK_SIZE)
+		.read_to_end(&mut buf)
+		.await
+		.map_err(MediaError::Io)?
+	;
+	hasher.update(&buf);
+
+	if size > consts::HASH_CHUNK_SIZE * 2 {
+		buf.clear();
+		file.seek(SeekFrom::End(-(consts::HASH_CHUNK_SIZE as i64)))
+			.await
+			.map_err(MediaError::Io)?
+		;
+		file.read_to_end(&mut buf)
+			.await
+			.map_err(MediaError::Io)?
+		;
+		hasher.update(&buf);
+	}
+
+	Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn classify_probe_failure(
+	status: std::process::ExitStatus,
+	stderr: &[u8],
+) -> MediaError {
+	let stderr = String::from_utf8_lossy(stderr).trim().to_string();
+	if stderr.contains("No such file or directory") {
+		MediaError::InvalidPath
+	} else if stderr.contains("Invalid data found when processing input")
+		|| stderr.contains("moov atom not found") {
+		MediaError::InvalidMedia(stderr)
+	} else {
+		MediaError::ProbeFailed(status.code(), stderr)
+	}
+}
+
+async fn get_file_metadata_output(
+	path: &Path,
+) -> Result<Vec<u8>, MediaError> {
+	let _permit = PROBE_PERMITS
+		.acquire()
+		.await
+		.map_err(|err| MediaError::Ffmpeg(err.into()))?
+	;
+
+	let child = Command::new(binary_path(&PubBinaryKind::Ffprobe))
 		.args([
 			"-v", "error",
 			"-print_format", "json",
 			"-show_format",
 			"-show_streams",
-			&path
+			"-show_chapters",
 		])
-		.output()
-		.map_err(MediaError::Io)
+		.arg(path)
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.kill_on_drop(true)
+		.spawn()
+		.map_err(|err| match err.kind() {
+			std::io::ErrorKind::NotFound => MediaError::ProbeMissing,
+			_ => MediaError::Io(err),
+		})?
+	;
+
+	// the child is killed when the timed out future is dropped
+	let output = tokio::time::timeout(consts::PROBE_TIMEOUT, child.wait_with_output())
+		.await
+		.map_err(|_| MediaError::ProbeTimeout)?
+		.map_err(MediaError::Io)?
+	;
+
+	if !output.status.success() {
+		return Err(classify_probe_failure(output.status, &output.stderr));
+	}
+
+	Ok(output.stdout)
 }
 
 pub async fn get_media_file_metadata(
 	path: PathBuf,
 ) -> Result<FFProbeMediaOutput, MediaError> {
-	let output  = get_file_metadata_output(path)?;
-	
-	serde_json::from_slice(&output.stdout).map_err(MediaError::Json)
+	let hash = get_file_hash(&path).await?;
+	if let Some(cached) = PROBE_CACHE.lock().unwrap().get(&hash) {
+		return Ok(cached.clone());
+	}
+
+	let output = get_file_metadata_output(&path).await?;
+	let mut probe: FFProbeMediaOutput = serde_json::from_slice(&output).map_err(MediaError::Json)?;
+	// the probed path is not part of the key, keep the one that was asked for
+	probe.format.filename = path.to_string_lossy().into_owned();
+
+	let mut cache = PROBE_CACHE.lock().unwrap();
+	if cache.len() >= consts::PROBE_CACHE_SIZE
+	&& let Some(evicted) = cache.keys().next().cloned() {
+		cache.remove(&evicted);
+	}
+	cache.insert(hash, probe.clone());
+
+	Ok(probe)
+}
+
+/// subtitle codecs ffmpeg can convert to webvtt; image based subtitles are skipped
+const TEXT_SUBTITLE_CODECS: [&str; 5] = ["subrip", "ass", "ssa", "webvtt", "mov_text"];
+
+#[derive(Debug, Clone)]
+pub struct ExtractedSubtitle {
+	pub lang: Option<String>,
+	pub file: FileRef,
+}
+
+/// extract all text based subtitle streams of a media file as webvtt files into `out_dir`
+pub async fn extract_embedded_subtitles(
+	path: PathBuf,
+	probe: &FFProbeMediaOutput,
+	out_dir: PathBuf,
+) -> Result<Vec<ExtractedSubtitle>, MediaError> {
+	let streams: Vec<(usize, Option<String>)> = probe.streams
+		.iter()
+		.filter(|stream| matches!(stream.codec_type, MediaCodecType::Subtitle))
+		.enumerate()
+		.filter(|(_, stream)| stream.codec_name
+			.as_deref()
+			.is_some_and(|codec| TEXT_SUBTITLE_CODECS.contains(&codec))
+		)
+		.map(|(sub_index, stream)| (sub_index, stream.tags.as_ref().and_then(|tags| tags.language.clone())))
+		.collect()
+	;
+
+	if streams.is_empty() {
+		return Ok(vec![]);
+	}
+
+	tokio::fs::create_dir_all(&out_dir)
+		.await
+		.map_err(MediaError::Io)?
+	;
+	tokio::task::spawn_blocking(move || {
+		let mut subtitles = vec![];
+		for (sub_index, lang) in streams {
+			let out_path = out_dir.join(format!("{}.{sub_index}.vtt", uuid::Uuid::new_v4()));
+			let map_arg = format!("0:s:{sub_index}");
+			let status = FfmpegCommand::new_with_path(binary_path(&PubBinaryKind::Ffmpeg))
+				.hide_banner()
+				.input(path.to_string_lossy())
+				.args(["-map", map_arg.as_str(), "-c:s", "webvtt"])
+				.overwrite()
+				.output(out_path.to_string_lossy())
+				.spawn()
+				.and_then(|mut child| child.wait())
+				.map_err(MediaError::Io)?
+			;
+			if !status.success() {
+				println!("unable to extract subtitle stream {sub_index} of '{path:?}'");
+				continue;
+			}
+
+			let file_size = std::fs::metadata(&out_path)
+				.map(|md| md.len() as i64)
+				.unwrap_or(0)
+			;
+			subtitles.push(ExtractedSubtitle {
+				lang,
+				file: FileRef { path: out_path, file_size },
+			});
+		}
+		Ok(subtitles)
+	})
+		.await
+		.map_err(|err| MediaError::Ffmpeg(err.into()))?
 }
 
 pub async fn init_media(
 	db_pool: &Pool<Postgres>,
 ) -> Result<(), MediaError> {
 	println!("initializing media...");
-	ffmpeg_sidecar::download::auto_download()
-		.map_err(MediaError::Ffmpeg)?
-	;
+	let status = init_binaries().await;
+	if status.iter().any(|bin| bin.version.is_none()) {
+		println!("some media binaries are unavailable, check the admin page");
+	}
+
+	tokio::spawn({
+		let db_pool = db_pool.clone();
+		async move {
+			if let Err(err) = backfill_media_analysis(db_pool).await {
+				println!("error analyzing media: {err:?}");
+			}
+		}
+	});
 
 	#[cfg(debug_assertions)]
 	{
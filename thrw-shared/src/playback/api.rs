@@ -0,0 +1,116 @@
+use super::prelude::*;
+
+use crate::prelude::*;
+use crate::user::prelude::*;
+#[cfg(feature = "server")]
+use crate::vfs::prelude::*;
+
+mod consts {
+	pub const DEFAULT_LIST_LIMIT: i64 = 20;
+	/// every entry is resolved to its node on its own, so lists stay short
+	pub const MAX_LIST_LIMIT: i64 = 100;
+}
+
+/// the requested list length, bounded to at least one and at most [`consts::MAX_LIST_LIMIT`] entries
+#[cfg(feature = "server")]
+fn list_limit(limit: Option<i64>) -> i64 {
+	limit
+		.unwrap_or(consts::DEFAULT_LIST_LIMIT)
+		.clamp(1, consts::MAX_LIST_LIMIT)
+}
+
+#[cfg(feature = "server")]
+async fn into_entries(
+	db_pool: &sqlx::Pool<sqlx::Postgres>,
+	states: Vec<PlaybackState>,
+) -> Result<Vec<PlaybackEntry>, ServerFnError> {
+	let mut entries = Vec::with_capacity(states.len());
+	for state in states {
+		let mut node = get_pub_vfs_node(db_pool, state.node_id)
+			.await
+			.map_err(make_server_err)?
+		;
+		node.playback = Some(state.clone());
+		entries.push(PlaybackEntry { node, state });
+	}
+	Ok(entries)
+}
+
+#[server]
+pub async fn update_playback(
+	node: uuid::Uuid,
+	position: f64,
+	duration: Option<f64>,
+	completed: bool,
+	started: bool,
+) -> Result<(), ServerFnError> {
+	let (user_id, _) = require_scope(ApiScope::Playback).await?;
+	let db = extract_db()?;
+
+	record_playback_internal(
+		&db,
+		user_id,
+		node,
+		PlaybackUpdate {
+			position,
+			duration,
+			completed,
+			started,
+		}
+	)
+		.await
+		.map_err(make_server_err)
+}
+
+#[server]
+pub async fn set_watched(
+	node: uuid::Uuid,
+	watched: bool,
+) -> Result<(), ServerFnError> {
+	let (user_id, _) = require_scope(ApiScope::Playback).await?;
+	let db = extract_db()?;
+
+	set_watched_internal(&db, user_id, node, watched)
+		.await
+		.map_err(make_server_err)
+}
+
+#[server]
+pub async fn get_playback(
+	node: uuid::Uuid,
+) -> Result<Option<PlaybackState>, ServerFnError> {
+	let (user_id, _) = require_scope(ApiScope::Playback).await?;
+	let db = extract_db()?;
+
+	get_playback_state_internal(&db, user_id, node)
+		.await
+		.map_err(make_server_err)
+}
+
+#[server]
+pub async fn get_continue_watching(
+	limit: Option<i64>,
+) -> Result<Vec<PlaybackEntry>, ServerFnError> {
+	let (user_id, _) = require_scope(ApiScope::Playback).await?;
+	let db = extract_db()?;
+
+	let states = get_continue_watching_internal(&db, user_id, list_limit(limit))
+		.await
+		.map_err(make_server_err)?
+	;
+	into_entries(&db, states).await
+}
+
+#[server]
+pub async fn get_recently_played(
+	limit: Option<i64>,
+) -> Result<Vec<PlaybackEntry>, ServerFnError> {
+	let (user_id, _) = require_scope(ApiScope::Playback).await?;
+	let db = extract_db()?;
+
+	let states = get_recently_played_internal(&db, user_id, list_limit(limit))
+		.await
+		.map_err(make_server_err)?
+	;
+	into_entries(&db, states).await
+}
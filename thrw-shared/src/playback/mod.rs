@@ -0,0 +1,39 @@
+pub mod api;
+#[cfg(feature = "server")]
+pub mod util;
+
+pub mod shared {
+	use serde::{Deserialize, Serialize};
+
+	use crate::vfs::shared::PubVfsNode;
+
+	#[derive(Debug)]
+	pub enum PlaybackError {
+		NotFound,
+		#[cfg(feature = "server")]
+		Sql(sqlx::Error),
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+	pub struct PlaybackState {
+		pub node_id: uuid::Uuid,
+		pub position: f64,
+		pub completed: bool,
+		pub play_count: i32,
+		pub last_played: chrono::DateTime<chrono::Utc>,
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PlaybackEntry {
+		pub node: PubVfsNode,
+		pub state: PlaybackState,
+	}
+}
+
+#[allow(unused)]
+pub mod prelude {
+	pub use super::api::*;
+	pub use super::shared::*;
+	#[cfg(feature = "server")]
+	pub use super::util::*;
+}
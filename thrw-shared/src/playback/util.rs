@@ -0,0 +1,167 @@
+use sqlx::{Pool, Postgres};
+
+use super::prelude::*;
+
+mod consts {
+	/// a playback counts as completed once this fraction of the media has been played
+	pub const COMPLETED_RATIO: f64 = 0.92;
+}
+
+pub struct PlaybackUpdate {
+	pub position: f64,
+	pub duration: Option<f64>,
+	pub completed: bool,
+	/// whether this update marks the start of a new play
+	pub started: bool,
+}
+impl PlaybackUpdate {
+	fn is_completed(&self) -> bool {
+		self.completed || self.duration
+			.filter(|duration| *duration > 0.0)
+			.is_some_and(|duration| self.position / duration >= consts::COMPLETED_RATIO)
+	}
+}
+
+pub async fn record_playback_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	node_id: uuid::Uuid,
+	update: PlaybackUpdate,
+) -> Result<(), PlaybackError> {
+	let completed = update.is_completed();
+	// completed media starts over on the next play, which is unfinished again until it reaches the end
+	let position = if completed { 0.0 } else { update.position.max(0.0) };
+
+	sqlx::query!("
+		INSERT INTO playback_states
+			(user_id, node_id, position, completed, play_count, last_played)
+		VALUES
+			($1, $2, $3, $4, $5, now())
+		ON CONFLICT (user_id, node_id) DO UPDATE
+		SET position	= EXCLUDED.position,
+			completed	= EXCLUDED.completed,
+			play_count	= playback_states.play_count + EXCLUDED.play_count,
+			last_played	= now()
+		;",
+		user_id,
+		node_id,
+		position,
+		completed,
+		update.started as i32
+	)
+		.execute(db_pool)
+		.await
+		.map_err(PlaybackError::Sql)
+		.map(|_| ())
+}
+
+pub async fn set_watched_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	node_id: uuid::Uuid,
+	watched: bool,
+) -> Result<(), PlaybackError> {
+	sqlx::query!("
+		INSERT INTO playback_states
+			(user_id, node_id, completed)
+		VALUES
+			($1, $2, $3)
+		ON CONFLICT (user_id, node_id) DO UPDATE
+		SET position	= 0,
+			completed	= EXCLUDED.completed
+		;",
+		user_id,
+		node_id,
+		watched
+	)
+		.execute(db_pool)
+		.await
+		.map_err(PlaybackError::Sql)
+		.map(|_| ())
+}
+
+pub async fn get_playback_state_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	node_id: uuid::Uuid,
+) -> Result<Option<PlaybackState>, PlaybackError> {
+	sqlx::query_as!(
+		PlaybackState,
+		"SELECT node_id, position, completed, play_count, last_played
+		FROM playback_states
+		WHERE user_id = $1
+		  AND node_id = $2
+		;",
+		user_id,
+		node_id
+	)
+		.fetch_optional(db_pool)
+		.await
+		.map_err(PlaybackError::Sql)
+}
+
+pub async fn get_playback_states_for(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	node_ids: &[uuid::Uuid],
+) -> Result<Vec<PlaybackState>, PlaybackError> {
+	sqlx::query_as!(
+		PlaybackState,
+		"SELECT node_id, position, completed, play_count, last_played
+		FROM playback_states
+		WHERE user_id = $1
+		  AND node_id = ANY($2)
+		;",
+		user_id,
+		node_ids
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(PlaybackError::Sql)
+}
+
+/// started but unfinished media, most recent first
+pub async fn get_continue_watching_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	limit: i64,
+) -> Result<Vec<PlaybackState>, PlaybackError> {
+	sqlx::query_as!(
+		PlaybackState,
+		"SELECT node_id, position, completed, play_count, last_played
+		FROM playback_states
+		WHERE user_id = $1
+		  AND completed = false
+		  AND position > 0
+		ORDER BY last_played DESC
+		LIMIT $2
+		;",
+		user_id,
+		limit
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(PlaybackError::Sql)
+}
+
+pub async fn get_recently_played_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	limit: i64,
+) -> Result<Vec<PlaybackState>, PlaybackError> {
+	sqlx::query_as!(
+		PlaybackState,
+		"SELECT node_id, position, completed, play_count, last_played
+		FROM playback_states
+		WHERE user_id = $1
+		  AND play_count > 0
+		ORDER BY last_played DESC
+		LIMIT $2
+		;",
+		user_id,
+		limit
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(PlaybackError::Sql)
+}
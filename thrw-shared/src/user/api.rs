@@ -12,6 +12,257 @@ pub struct UserKeyChain {
 	pub entry_level:	i16,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountInfo {
+	pub id:				i32,
+	pub email:			String,
+	pub display_name:	Option<String>,
+	pub level_name:		String,
+	pub email_verified:	bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserSettings {
+	/// sessions are only valid from the address they were created from
+	pub strict_ip:		bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserSession {
+	/// not the token, which stays in the cookie
+	pub id:				uuid::Uuid,
+	pub user_id:		i32,
+	pub email:			String,
+	pub created_at:		Option<chrono::DateTime<chrono::Utc>>,
+	pub expires_at:		chrono::DateTime<chrono::Utc>,
+	pub ip_address:		Option<String>,
+	pub user_agent:		Option<String>,
+	/// the session the list was requested with
+	pub current:		bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LoginResult {
+	/// id, token, level name and permissions
+	LoggedIn(i32, String, String, Vec<Permission>),
+	/// the password was right, the login is finished by `verify_login` with the challenge
+	SecondFactor(uuid::Uuid),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TotpEnrollment {
+	/// base32, for authenticators that can't scan the code
+	pub secret:			String,
+	/// rendered as a qr code by the client
+	pub otpauth_uri:	String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TotpStatus {
+	pub enabled:				bool,
+	pub recovery_codes_left:	i64,
+}
+
+/// what an api token may be used for, sessions may do everything
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiScope {
+	/// queue, list and cancel downloads and subscriptions
+	Downloads,
+	/// list and create folders, read media info
+	Files,
+	/// read and update playback progress
+	Playback,
+}
+impl ApiScope {
+	pub const ALL: [ApiScope; 3] = [ApiScope::Downloads, ApiScope::Files, ApiScope::Playback];
+}
+impl ToString for ApiScope {
+	fn to_string(&self) -> String {
+		match self {
+			ApiScope::Downloads => "downloads",
+			ApiScope::Files => "files",
+			ApiScope::Playback => "playback",
+		}.to_string()
+	}
+}
+impl std::str::FromStr for ApiScope {
+	type Err = String;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		match value {
+			"downloads" => Ok(Self::Downloads),
+			"files" => Ok(Self::Files),
+			"playback" => Ok(Self::Playback),
+			other => Err(format!("unknown scope '{other}'")),
+		}
+	}
+}
+
+/// what a role allows its users to do, anything else only needs a login
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Permission {
+	/// queue downloads and subscriptions, store site logins for them
+	Download,
+	/// add nodes to the vfs
+	Upload,
+	/// see hidden nodes in the vfs
+	ViewAllFiles,
+	ManageKeys,
+	/// sessions, second factors, roles and quotas of other users
+	ManageUsers,
+	/// create roles and change what they allow
+	ManageRoles,
+	/// media binaries
+	ManageServer,
+}
+impl Permission {
+	pub const ALL: [Permission; 7] = [
+		Permission::Download,
+		Permission::Upload,
+		Permission::ViewAllFiles,
+		Permission::ManageKeys,
+		Permission::ManageUsers,
+		Permission::ManageRoles,
+		Permission::ManageServer,
+	];
+	/// the ones that are managed from the admin pages
+	pub const ADMIN: [Permission; 4] = [
+		Permission::ManageKeys,
+		Permission::ManageUsers,
+		Permission::ManageRoles,
+		Permission::ManageServer,
+	];
+}
+impl ToString for Permission {
+	fn to_string(&self) -> String {
+		match self {
+			Permission::Download => "download",
+			Permission::Upload => "upload",
+			Permission::ViewAllFiles => "view_all_files",
+			Permission::ManageKeys => "manage_keys",
+			Permission::ManageUsers => "manage_users",
+			Permission::ManageRoles => "manage_roles",
+			Permission::ManageServer => "manage_server",
+		}.to_string()
+	}
+}
+impl std::str::FromStr for Permission {
+	type Err = String;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		match value {
+			"download" => Ok(Self::Download),
+			"upload" => Ok(Self::Upload),
+			"view_all_files" => Ok(Self::ViewAllFiles),
+			"manage_keys" => Ok(Self::ManageKeys),
+			"manage_users" => Ok(Self::ManageUsers),
+			"manage_roles" => Ok(Self::ManageRoles),
+			"manage_server" => Ok(Self::ManageServer),
+			other => Err(format!("unknown permission '{other}'")),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserRole {
+	pub level_id:		i16,
+	pub name:			String,
+	pub permissions:	Vec<Permission>,
+	pub members:		i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+	pub id:				uuid::Uuid,
+	pub name:			String,
+	pub scopes:			Vec<ApiScope>,
+	pub expires_at:		Option<chrono::DateTime<chrono::Utc>>,
+	pub last_used_at:	Option<chrono::DateTime<chrono::Utc>>,
+	pub created_at:		chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewApiToken {
+	pub token:			ApiToken,
+	/// sent as `Authorization: Bearer <secret>`, only shown once
+	pub secret:			String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AuthEventKind {
+	LoginSucceeded,
+	LoginFailed,
+	SecondFactorFailed,
+	/// too many failures, attempts are refused for a while
+	LockedOut,
+	/// an attempt was refused by the limits
+	RateLimited,
+	RegisterSucceeded,
+	RegisterFailed,
+	/// a strict-ip session was used from elsewhere
+	IpMismatch,
+	EmailVerified,
+	PasswordResetRequested,
+	PasswordReset,
+	/// a user got another role or a role other permissions
+	RoleChanged,
+	Other(String),
+}
+impl ToString for AuthEventKind {
+	fn to_string(&self) -> String {
+		match self {
+			AuthEventKind::LoginSucceeded => "login_succeeded",
+			AuthEventKind::LoginFailed => "login_failed",
+			AuthEventKind::SecondFactorFailed => "second_factor_failed",
+			AuthEventKind::LockedOut => "locked_out",
+			AuthEventKind::RateLimited => "rate_limited",
+			AuthEventKind::RegisterSucceeded => "register_succeeded",
+			AuthEventKind::RegisterFailed => "register_failed",
+			AuthEventKind::IpMismatch => "ip_mismatch",
+			AuthEventKind::EmailVerified => "email_verified",
+			AuthEventKind::PasswordResetRequested => "password_reset_requested",
+			AuthEventKind::PasswordReset => "password_reset",
+			AuthEventKind::RoleChanged => "role_changed",
+			AuthEventKind::Other(other) => other,
+		}.to_string()
+	}
+}
+impl From<String> for AuthEventKind {
+	fn from(value: String) -> Self {
+		match value.as_str() {
+			"login_succeeded" => Self::LoginSucceeded,
+			"login_failed" => Self::LoginFailed,
+			"second_factor_failed" => Self::SecondFactorFailed,
+			"locked_out" => Self::LockedOut,
+			"rate_limited" => Self::RateLimited,
+			"register_succeeded" => Self::RegisterSucceeded,
+			"register_failed" => Self::RegisterFailed,
+			"ip_mismatch" => Self::IpMismatch,
+			"email_verified" => Self::EmailVerified,
+			"password_reset_requested" => Self::PasswordResetRequested,
+			"password_reset" => Self::PasswordReset,
+			"role_changed" => Self::RoleChanged,
+			_ => Self::Other(value),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthEvent {
+	pub id:				i64,
+	pub event:			AuthEventKind,
+	pub email:			Option<String>,
+	pub user_id:		Option<i32>,
+	pub ip_address:		Option<String>,
+	pub detail:			Option<String>,
+	pub created_at:		chrono::DateTime<chrono::Utc>,
+}
+
+pub mod values {
+	pub const MAX_DISPLAY_NAME_LENGTH: usize = 64;
+	pub const AUTH_EVENT_PAGE: i64 = 100;
+}
+
 #[server]
 pub async fn generate_keychain(
 	name: Option<String>,
@@ -19,7 +270,7 @@ pub async fn generate_keychain(
 	entry_level: Option<i16>,
 ) -> Result<UserKeyChain, ServerFnError> {
 	use crate::app::state::prelude::extract_db;
-	require_admin().await?;
+	let (user_id, _) = require_permission(Permission::ManageKeys).await?;
 
 	let db_pool = extract_db()?;
 
@@ -40,6 +291,8 @@ pub async fn generate_keychain(
 			res.level_id
 		}
 	};
+	// a key is as good as handing out its role
+	check_role_grantable(&db_pool, user_id, entry_level).await?;
 
 	let res = sqlx::query!("
 		INSERT INTO key_chains
@@ -65,7 +318,7 @@ pub async fn generate_keychain(
 #[server]
 pub async fn kill_keychain(keychain: String) -> Result<(), ServerFnError> {
 	use crate::app::state::prelude::extract_db;
-	require_admin().await?;
+	require_permission(Permission::ManageKeys).await?;
 
 	let db_pool = extract_db()?;
 
@@ -86,7 +339,7 @@ pub async fn kill_keychain(keychain: String) -> Result<(), ServerFnError> {
 #[server]
 pub async fn get_active_keychains() -> Result<Vec<UserKeyChain>, ServerFnError> {
 	use crate::app::state::prelude::extract_db;
-	require_admin().await?;
+	require_permission(Permission::ManageKeys).await?;
 
 	let db_pool = extract_db()?;
 	sqlx::query_as!(
@@ -109,15 +362,32 @@ pub async fn get_user_level() -> Result<(i32, String), ServerFnError> {
 
 #[server]
 pub async fn register(email: String, pw: String, keychain: String) -> Result<(), ServerFnError> {
-	use crate::app::state::server::extract_db;
-	let entry_level = use_keychain(keychain).await?;
+	use crate::app::state::server::extract_state;
+	use axum::extract::ConnectInfo;
+
+	let ip: ConnectInfo<SocketAddr> = leptos_axum::extract().await?;
+	let state = extract_state()?;
+	let db_pool = state.db_pool.clone();
+	let email = validate_email(&email)?;
+	let limit_keys = [LimitKey::RegisterIp(ip.0.ip())];
+	let subject = AuthEventSubject { email: Some(&email), ip: Some(ip.0.ip()), ..Default::default() };
+	let attempt = reserve_auth_attempt(&db_pool, &limit_keys).await?;
+
+	let entry_level = match use_keychain(keychain).await {
+		Ok(entry_level) => entry_level,
+		Err(err) => {
+			attempt.fail(&db_pool, AuthEventKind::RegisterFailed, subject, Some(format!("{err:?}"))).await;
+			return Err(err.into());
+		},
+	};
+	// the keychain was right, whatever fails from here on is no guess
+	attempt.rollback(&db_pool).await?;
 	println!("user registering: {email}");
-	let db_pool = extract_db()?;
 
 	let exists = sqlx::query!("
 		SELECT id
 		FROM users
-		where email = $1
+		WHERE lower(email) = $1
 		;",
 		email.clone()
 	)
@@ -130,33 +400,43 @@ pub async fn register(email: String, pw: String, keychain: String) -> Result<(),
 	}
 
 	let pw_hash = hash(pw);
-	let affected = sqlx::query!("
+	let created = sqlx::query!("
 		INSERT INTO users
 			(email, pwhash, user_level)
 		VALUES
 			($1, $2, $3)
+		RETURNING id
 		;",
 		email,
 		pw_hash,
 		entry_level
 	)
-		.execute(&db_pool)
+		.fetch_optional(&db_pool)
 		.await?
 	;
-
-	if affected.rows_affected() == 0 {
+	let Some(created) = created else {
 		return Err(ServerFnError::ServerError("Error creating account".to_string()));
+	};
+	let user_id = created.id;
+
+	clear_auth_failures(&db_pool, &limit_keys).await?;
+	log_auth_event(&db_pool, AuthEventKind::RegisterSucceeded, AuthEventSubject { user_id: Some(user_id), ..subject }, None).await;
+
+	// the account exists either way, the mail can be sent again from the account page
+	if let Err(err) = send_verification_mail(&db_pool, state.mailer, user_id, &email).await {
+		println!("unable to send verification mail to {email}: {err:?}");
 	}
 
 	Ok(())
 }
 
 #[server]
-pub async fn log_in(email: String, pw: String) -> Result<(i32, String, String), ServerFnError> {
-	use crate::app::state::server::extract_db;
+pub async fn log_in(email: String, pw: String) -> Result<LoginResult, ServerFnError> {
+	use crate::app::state::server::extract_state;
 	use axum::extract::ConnectInfo;
 
 	let ip: ConnectInfo<SocketAddr> = leptos_axum::extract().await?;
+	let email = normalize_email(&email);
 
 	if let Ok(token) = super::auth::extract_session_cookie().await
 		&& check_token_validity_and_refresh(token).await.is_ok() {
@@ -165,11 +445,23 @@ pub async fn log_in(email: String, pw: String) -> Result<(i32, String, String),
 
 	println!("user {email} ({}) logging in...", ip.0);
 	// init db and get row
-	let db_pool = extract_db()?;
+	let state = extract_state()?;
+	let db_pool = state.db_pool.clone();
+	let limit_keys = [LimitKey::LoginIp(ip.0.ip()), LimitKey::LoginEmail(email.clone())];
+	let subject = AuthEventSubject { email: Some(&email), ip: Some(ip.0.ip()), ..Default::default() };
+	let attempt = match reserve_auth_attempt(&db_pool, &limit_keys).await {
+		Ok(attempt) => attempt,
+		Err(err) => {
+			log_auth_event(&db_pool, AuthEventKind::RateLimited, subject, None).await;
+			return Err(err.into());
+		},
+	};
+
+	// lower() so the unique index on it is used
 	let acc_row = sqlx::query!("
-		SELECT id, email, pwhash
+		SELECT id, email, pwhash, email_verified_at
 		FROM users
-		WHERE email = $1
+		WHERE lower(email) = $1
 		;",
 		email.clone()
 	)
@@ -178,30 +470,81 @@ pub async fn log_in(email: String, pw: String) -> Result<(i32, String, String),
 	;
 
 	let Some(acc_row) = acc_row else {
+		attempt.fail(&db_pool, AuthEventKind::LoginFailed, subject, Some("unknown email".to_string())).await;
 		return Err(ServerFnError::ServerError("User does not exist".to_string()));
 	};
 
 	let pw_hash = acc_row.pwhash;
 	let user_id = acc_row.id;
+	let subject = AuthEventSubject { user_id: Some(user_id), ..subject };
 	
 	match verify_hash(&pw, &pw_hash) {
-		Ok(false) => return Err(ServerFnError::ServerError("Wrong password".to_string())),
-		Err(e) => return Err(ServerFnError::ServerError(e)),
+		Ok(false) => {
+			attempt.fail(&db_pool, AuthEventKind::LoginFailed, subject, Some("wrong password".to_string())).await;
+			return Err(ServerFnError::ServerError("Wrong password".to_string()));
+		},
+		Err(e) => {
+			attempt.rollback(&db_pool).await?;
+			return Err(ServerFnError::ServerError(e));
+		},
 
-		Ok(true) => {},
+		Ok(true) => attempt.rollback(&db_pool).await?,
 	};
 
-	let token = generate_auth_token(user_id, ip.0).await;
-	let Ok(token) = token else {
-		return Err(ServerFnError::ServerError(format!("Unable to generate auth token: {:?}", token.unwrap_err())));
-	};
+	// the password was right, so a fresh link is mailed along with the refusal
+	if is_verified_email_required() && acc_row.email_verified_at.is_none() {
+		if let Err(err) = send_verification_mail(&db_pool, state.mailer, user_id, &acc_row.email).await {
+			println!("unable to send verification mail to {email}: {err:?}");
+		}
+		return Err(AuthError::Auth(LocalAuthError::EmailNotVerified).into());
+	}
 
-	refresh_token_cookie(token)?;
+	// the session is only issued once the second factor is accepted, the earlier failures are kept until then
+	if is_totp_enabled(&db_pool, user_id).await? {
+		let challenge = create_login_challenge(&db_pool, user_id).await?;
+		println!("user {email} passed the password, waiting for the second factor");
+		return Ok(LoginResult::SecondFactor(challenge));
+	}
 
+	let (id, token, level_name, permissions) = start_session(user_id).await?;
+	clear_auth_failures(&db_pool, &limit_keys).await?;
+	log_auth_event(&db_pool, AuthEventKind::LoginSucceeded, subject, None).await;
 	println!("user {email} logged in!");
 
-	let (_, level_name) = get_user_level_internal(Some(user_id)).await?;
-	Ok((user_id, token.to_string(), level_name))
+	Ok(LoginResult::LoggedIn(id, token, level_name, permissions))
+}
+
+/// finish a login with a totp or recovery code
+#[server]
+pub async fn verify_login(challenge: uuid::Uuid, code: String) -> Result<(i32, String, String, Vec<Permission>), ServerFnError> {
+	use crate::app::state::server::extract_db;
+	use axum::extract::ConnectInfo;
+
+	let ip: ConnectInfo<SocketAddr> = leptos_axum::extract().await?;
+	let db_pool = extract_db()?;
+
+	let user_id = get_challenge_user(&db_pool, challenge).await?;
+	let limit_keys = [LimitKey::LoginIp(ip.0.ip()), LimitKey::SecondFactor(user_id)];
+	let subject = AuthEventSubject { user_id: Some(user_id), ip: Some(ip.0.ip()), ..Default::default() };
+	let attempt = match reserve_auth_attempt(&db_pool, &limit_keys).await {
+		Ok(attempt) => attempt,
+		Err(err) => {
+			log_auth_event(&db_pool, AuthEventKind::RateLimited, subject, Some("second factor".to_string())).await;
+			return Err(err.into());
+		},
+	};
+
+	if let Err(err) = pass_login_challenge(&db_pool, challenge, &code).await {
+		attempt.fail(&db_pool, AuthEventKind::SecondFactorFailed, subject, Some(format!("{err:?}"))).await;
+		return Err(err.into());
+	}
+	attempt.rollback(&db_pool).await?;
+	let session = start_session(user_id).await?;
+	clear_auth_failures(&db_pool, &limit_keys).await?;
+	log_auth_event(&db_pool, AuthEventKind::LoginSucceeded, subject, Some("with second factor".to_string())).await;
+	println!("user {user_id} logged in with the second factor!");
+
+	Ok(session)
 }
 
 #[server]
@@ -214,16 +557,589 @@ pub async fn log_out() -> Result<(), ServerFnError> {
 }
 
 #[server]
-pub async fn is_logged_in() -> Result<Option<(i32, String, String)>, ServerFnError> {
+pub async fn is_logged_in() -> Result<Option<(i32, String, String, Vec<Permission>)>, ServerFnError> {
+	use crate::app::state::server::extract_db;
+
 	match require_auth().await {
 		// logged in
 		Ok((id, token)) => {
 			let (_, level_name) = get_user_level().await?;
-			Ok(Some((id, token.to_string(), level_name)))
+			let permissions = get_permissions_internal(&extract_db()?, id).await?;
+			Ok(Some((id, token.to_string(), level_name, permissions)))
 		},
 		// no error, but logged out
 		Err(AuthError::Auth(LocalAuthError::NotAuthenticated)) => Ok(None),
 		// something went wrong
 		Err(err) => Err(err.into())
 	}
-}
\ No newline at end of file
+}
+#[server]
+pub async fn get_account_info() -> Result<AccountInfo, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	sqlx::query_as!(
+		AccountInfo,
+		"SELECT users.id, users.email, users.display_name, user_levels.level_name, users.email_verified_at IS NOT NULL AS \"email_verified!\"
+		FROM users
+		JOIN user_levels ON user_levels.level_id = users.user_level
+		WHERE users.id = $1
+		;",
+		user_id
+	)
+		.fetch_one(&db_pool)
+		.await
+		.map_err(Into::into)
+}
+
+/// other sessions and the api tokens of the user are ended, the current session stays logged in
+#[server]
+pub async fn change_password(current_pw: String, new_pw: String) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, token) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	if new_pw.is_empty() {
+		return Err(ServerFnError::ServerError("Password must not be empty".to_string()));
+	}
+	verify_user_password(&db_pool, user_id, &current_pw).await?;
+
+	let mut tx = db_pool.begin().await?;
+	sqlx::query!("
+		UPDATE users
+		SET pwhash = $1
+		WHERE id = $2
+		;",
+		hash(new_pw),
+		user_id
+	)
+		.execute(&mut *tx)
+		.await?
+	;
+
+	end_user_access(&mut tx, user_id, Some(token)).await?;
+	tx.commit().await?;
+
+	println!("user {user_id} changed their password");
+	Ok(())
+}
+
+#[server]
+pub async fn change_email(email: String, pw: String) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_state;
+	let (user_id, _) = require_auth().await?;
+	let state = extract_state()?;
+	let db_pool = state.db_pool.clone();
+
+	let email = validate_email(&email)?;
+	verify_user_password(&db_pool, user_id, &pw).await?;
+
+	let exists = sqlx::query!("
+		SELECT id
+		FROM users
+		WHERE lower(email) = $1
+		  AND id <> $2
+		;",
+		email,
+		user_id
+	)
+		.fetch_optional(&db_pool)
+		.await?
+	;
+	if exists.is_some() {
+		return Err(ServerFnError::ServerError("Email is already registered".to_string()));
+	}
+
+	// tokens mailed to the old address stop working with the address check when they are used
+	sqlx::query!("
+		UPDATE users
+		SET email = $1, email_verified_at = NULL
+		WHERE id = $2
+		;",
+		email,
+		user_id
+	)
+		.execute(&db_pool)
+		.await?
+	;
+
+	if let Err(err) = send_verification_mail(&db_pool, state.mailer, user_id, &email).await {
+		println!("unable to send verification mail to {email}: {err:?}");
+	}
+	Ok(())
+}
+
+#[server]
+pub async fn resend_verification_email() -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_state;
+	let (user_id, _) = require_auth().await?;
+	let state = extract_state()?;
+
+	let rec = sqlx::query!("
+		SELECT email, email_verified_at
+		FROM users
+		WHERE id = $1
+		;",
+		user_id
+	)
+		.fetch_one(&state.db_pool)
+		.await?
+	;
+	if rec.email_verified_at.is_some() {
+		return Err(ServerFnError::ServerError("Email is verified already".to_string()));
+	}
+
+	send_verification_mail(&state.db_pool, state.mailer.clone(), user_id, &rec.email)
+		.await
+		.map_err(Into::into)
+}
+
+/// works without being logged in, the token comes from the mailed link
+#[server]
+pub async fn verify_email(token: String) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let db_pool = extract_db()?;
+
+	let (user_id, email) = consume_email_token(&db_pool, EmailTokenPurpose::VerifyEmail, &token).await?;
+	let res = sqlx::query!("
+		UPDATE users
+		SET email_verified_at = now()
+		WHERE id = $1
+		  AND email = $2
+		;",
+		user_id,
+		email
+	)
+		.execute(&db_pool)
+		.await?
+	;
+	// the address was changed after the mail was sent
+	(res.rows_affected() > 0).ok_or(AuthError::Auth(LocalAuthError::InvalidEmailToken))?;
+
+	let subject = AuthEventSubject { email: Some(&email), user_id: Some(user_id), ..Default::default() };
+	log_auth_event(&db_pool, AuthEventKind::EmailVerified, subject, None).await;
+	println!("user {user_id} verified {email}");
+	Ok(())
+}
+
+/// always succeeds, so it can't be used to find out which addresses are registered
+#[server]
+pub async fn request_password_reset(email: String) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_state;
+	use axum::extract::ConnectInfo;
+
+	let ip: ConnectInfo<SocketAddr> = leptos_axum::extract().await?;
+	let state = extract_state()?;
+	let email = normalize_email(&email);
+	let subject = AuthEventSubject { email: Some(&email), ip: Some(ip.0.ip()), ..Default::default() };
+
+	// counted for unknown addresses too, otherwise the limit would give them away
+	if let Err(err) = throttle_mail(&email) {
+		log_auth_event(&state.db_pool, AuthEventKind::RateLimited, subject, Some("password reset".to_string())).await;
+		return Err(err.into());
+	}
+
+	let rec = sqlx::query!("
+		SELECT id, email
+		FROM users
+		WHERE lower(email) = $1
+		;",
+		email
+	)
+		.fetch_optional(&state.db_pool)
+		.await?
+	;
+	let user_id = rec.as_ref().map(|rec| rec.id);
+	let detail = user_id.is_none().then(|| "unknown email".to_string());
+	log_auth_event(&state.db_pool, AuthEventKind::PasswordResetRequested, AuthEventSubject { user_id, ..subject }, detail).await;
+
+	// the token is bound to the stored address, which reset_password compares against
+	if let Some(rec) = rec
+		&& let Err(err) = send_password_reset_mail(&state.db_pool, state.mailer.clone(), rec.id, &rec.email).await {
+			println!("unable to send password reset mail to {}: {err:?}", rec.email);
+		}
+	Ok(())
+}
+
+/// every session and api token of the user is ended, including any on the device the reset is done from
+#[server]
+pub async fn reset_password(token: String, new_pw: String) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_db;
+	use axum::extract::ConnectInfo;
+
+	let ip: ConnectInfo<SocketAddr> = leptos_axum::extract().await?;
+	let db_pool = extract_db()?;
+
+	if new_pw.is_empty() {
+		return Err(ServerFnError::ServerError("Password must not be empty".to_string()));
+	}
+	let (user_id, email) = consume_email_token(&db_pool, EmailTokenPurpose::ResetPassword, &token).await?;
+
+	let mut tx = db_pool.begin().await?;
+	// receiving the mail proves the address as well
+	let res = sqlx::query!("
+		UPDATE users
+		SET pwhash = $1, email_verified_at = COALESCE(email_verified_at, now())
+		WHERE id = $2
+		  AND email = $3
+		;",
+		hash(new_pw),
+		user_id,
+		email
+	)
+		.execute(&mut *tx)
+		.await?
+	;
+	(res.rows_affected() > 0).ok_or(AuthError::Auth(LocalAuthError::InvalidEmailToken))?;
+
+	// whatever was set up with the old password goes with it
+	end_user_access(&mut tx, user_id, None).await?;
+	tx.commit().await?;
+
+	clear_auth_failures(&db_pool, &[LimitKey::LoginEmail(email.clone())]).await?;
+	let subject = AuthEventSubject { email: Some(&email), user_id: Some(user_id), ip: Some(ip.0.ip()) };
+	log_auth_event(&db_pool, AuthEventKind::PasswordReset, subject, None).await;
+	println!("user {user_id} reset their password");
+	Ok(())
+}
+
+/// `None` or an empty name removes the display name
+#[server]
+pub async fn set_display_name(display_name: Option<String>) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	let display_name = display_name
+		.map(|name| name.trim().to_string())
+		.filter(|name| !name.is_empty())
+	;
+	if display_name.as_ref().is_some_and(|name| name.chars().count() > values::MAX_DISPLAY_NAME_LENGTH) {
+		return Err(ServerFnError::ServerError(format!("Display name is longer than {} characters", values::MAX_DISPLAY_NAME_LENGTH)));
+	}
+
+	sqlx::query!("
+		UPDATE users
+		SET display_name = $1
+		WHERE id = $2
+		;",
+		display_name,
+		user_id
+	)
+		.execute(&db_pool)
+		.await?
+	;
+
+	Ok(())
+}
+
+#[server]
+pub async fn get_user_settings() -> Result<UserSettings, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	let settings = sqlx::query!("
+		SELECT strict_ip
+		FROM usersettings
+		WHERE id = $1
+		;",
+		user_id
+	)
+		.fetch_optional(&db_pool)
+		.await?
+		.map(|rec| UserSettings {
+			strict_ip: rec.strict_ip.unwrap_or(false),
+		})
+		.unwrap_or_default()
+	;
+
+	Ok(settings)
+}
+
+#[server]
+pub async fn set_user_settings(settings: UserSettings) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	sqlx::query!("
+		INSERT INTO usersettings
+			(id, strict_ip)
+		VALUES
+			($1, $2)
+		ON CONFLICT (id) DO UPDATE
+			SET strict_ip = EXCLUDED.strict_ip
+		;",
+		user_id,
+		settings.strict_ip
+	)
+		.execute(&db_pool)
+		.await?
+	;
+
+	Ok(())
+}
+
+/// the sessions of the logged in user
+#[server]
+pub async fn get_sessions() -> Result<Vec<UserSession>, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, token) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	get_sessions_internal(&db_pool, Some(user_id), Some(token))
+		.await
+		.map_err(Into::into)
+}
+
+/// revoking the current session logs out
+#[server]
+pub async fn revoke_session(id: uuid::Uuid) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, token) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	let ended = kill_session_internal(&db_pool, id, Some(user_id)).await?;
+	if ended == token {
+		end_session(token).await?;
+	}
+	Ok(())
+}
+
+/// log out everywhere but here, returns how many sessions were ended
+#[server]
+pub async fn revoke_other_sessions() -> Result<u64, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, token) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	let res = sqlx::query!("
+		DELETE FROM sessions
+		WHERE user_id = $1
+		  AND session_id <> $2
+		;",
+		user_id,
+		token
+	)
+		.execute(&db_pool)
+		.await?
+	;
+
+	Ok(res.rows_affected())
+}
+
+/// the sessions of a single user or, without one, of everyone
+#[server]
+pub async fn get_all_sessions(user_id: Option<i32>) -> Result<Vec<UserSession>, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (_, token) = require_permission(Permission::ManageUsers).await?;
+	let db_pool = extract_db()?;
+
+	get_sessions_internal(&db_pool, user_id, Some(token))
+		.await
+		.map_err(Into::into)
+}
+
+#[server]
+pub async fn kill_session(id: uuid::Uuid) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (admin_id, _) = require_permission(Permission::ManageUsers).await?;
+	let db_pool = extract_db()?;
+
+	let user_id = get_session_user_id(&db_pool, id).await?;
+	check_role_grantable(&db_pool, admin_id, get_user_role_id(&db_pool, user_id).await?).await?;
+
+	println!("admin ended session {id} of user {user_id}");
+	kill_session_internal(&db_pool, id, None).await?;
+	Ok(())
+}
+
+#[server]
+pub async fn get_totp_status() -> Result<TotpStatus, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	get_totp_status_internal(&db_pool, user_id)
+		.await
+		.map_err(Into::into)
+}
+
+/// a new secret to add to an authenticator, active once confirmed with a code
+#[server]
+pub async fn begin_totp_enrollment(pw: String) -> Result<TotpEnrollment, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	verify_user_password(&db_pool, user_id, &pw).await?;
+	begin_totp_enrollment_internal(&db_pool, user_id)
+		.await
+		.map_err(Into::into)
+}
+
+/// returns the recovery codes, they are not shown again
+#[server]
+pub async fn confirm_totp_enrollment(code: String) -> Result<Vec<String>, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	confirm_totp_enrollment_internal(&db_pool, user_id, &code)
+		.await
+		.map_err(Into::into)
+}
+
+/// replaces the recovery codes, the old ones stop working
+#[server]
+pub async fn regenerate_recovery_codes(pw: String, code: String) -> Result<Vec<String>, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	verify_user_password(&db_pool, user_id, &pw).await?;
+	verify_second_factor(&db_pool, user_id, &code).await?;
+	generate_recovery_codes(&db_pool, user_id)
+		.await
+		.map_err(Into::into)
+}
+
+#[server]
+pub async fn disable_totp(pw: String, code: String) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	verify_user_password(&db_pool, user_id, &pw).await?;
+	verify_second_factor(&db_pool, user_id, &code).await?;
+	disable_totp_internal(&db_pool, user_id).await?;
+	println!("user {user_id} disabled two-factor authentication");
+
+	Ok(())
+}
+
+/// for users who lost their authenticator and recovery codes
+#[server]
+pub async fn reset_user_totp(user_id: i32) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (admin_id, _) = require_permission(Permission::ManageUsers).await?;
+	let db_pool = extract_db()?;
+
+	check_role_grantable(&db_pool, admin_id, get_user_role_id(&db_pool, user_id).await?).await?;
+	disable_totp_internal(&db_pool, user_id).await?;
+	println!("admin reset two-factor authentication of user {user_id}");
+
+	Ok(())
+}
+
+#[server]
+pub async fn get_api_tokens() -> Result<Vec<ApiToken>, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	get_api_tokens_internal(&db_pool, user_id)
+		.await
+		.map_err(Into::into)
+}
+
+/// `None` never expires
+#[server]
+pub async fn create_api_token(name: String, scopes: Vec<ApiScope>, expires_in_days: Option<i64>) -> Result<NewApiToken, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	create_api_token_internal(&db_pool, user_id, name, scopes, expires_in_days)
+		.await
+		.map_err(Into::into)
+}
+
+#[server]
+pub async fn revoke_api_token(token_id: uuid::Uuid) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	revoke_api_token_internal(&db_pool, user_id, token_id)
+		.await
+		.map_err(Into::into)
+}
+
+/// the latest logins, lockouts and registrations, `before` pages back from an event id
+#[server]
+pub async fn get_auth_events(before: Option<i64>) -> Result<Vec<AuthEvent>, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	require_permission(Permission::ManageUsers).await?;
+	let db_pool = extract_db()?;
+
+	get_auth_events_internal(&db_pool, values::AUTH_EVENT_PAGE, before)
+		.await
+		.map_err(Into::into)
+}
+
+/// for managing roles or assigning them to users
+#[server]
+pub async fn get_roles() -> Result<Vec<UserRole>, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_auth().await?;
+	let db_pool = extract_db()?;
+
+	if check_permission(&db_pool, user_id, Permission::ManageRoles).await.is_err() {
+		check_permission(&db_pool, user_id, Permission::ManageUsers).await?;
+	}
+	get_roles_internal(&db_pool)
+		.await
+		.map_err(Into::into)
+}
+
+#[server]
+pub async fn create_role(name: String) -> Result<i16, ServerFnError> {
+	use crate::app::state::server::extract_db;
+	require_permission(Permission::ManageRoles).await?;
+	let db_pool = extract_db()?;
+
+	create_role_internal(&db_pool, name)
+		.await
+		.map_err(Into::into)
+}
+
+/// only roles that grant nothing beyond the own permissions can be changed, and only to such
+#[server]
+pub async fn set_role_permissions(level_id: i16, permissions: Vec<Permission>) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (user_id, _) = require_permission(Permission::ManageRoles).await?;
+	let db_pool = extract_db()?;
+
+	check_role_grantable(&db_pool, user_id, level_id).await?;
+	check_grantable(&db_pool, user_id, &permissions).await?;
+	set_role_permissions_internal(&db_pool, level_id, &permissions).await?;
+
+	let detail = permissions
+		.iter()
+		.map(ToString::to_string)
+		.collect::<Vec<_>>()
+		.join(", ")
+	;
+	let subject = AuthEventSubject { user_id: Some(user_id), ..Default::default() };
+	log_auth_event(&db_pool, AuthEventKind::RoleChanged, subject, Some(format!("role {level_id} now allows: {detail}"))).await;
+	Ok(())
+}
+
+#[server]
+pub async fn set_user_role(user_id: i32, level_id: i16) -> Result<(), ServerFnError> {
+	use crate::app::state::server::extract_db;
+	let (admin_id, _) = require_permission(Permission::ManageUsers).await?;
+	let db_pool = extract_db()?;
+
+	// neither promoting anyone above nor demoting anyone from above the own permissions
+	check_role_grantable(&db_pool, admin_id, get_user_role_id(&db_pool, user_id).await?).await?;
+	check_role_grantable(&db_pool, admin_id, level_id).await?;
+	set_user_role_internal(&db_pool, user_id, level_id).await?;
+
+	let subject = AuthEventSubject { user_id: Some(user_id), ..Default::default() };
+	log_auth_event(&db_pool, AuthEventKind::RoleChanged, subject, Some(format!("given role {level_id} by user {admin_id}"))).await;
+	Ok(())
+}
@@ -1,19 +1,58 @@
-use std::{net::SocketAddr, str::FromStr};
+use std::{net::{IpAddr, SocketAddr}, str::FromStr, sync::LazyLock};
 
-use axum::http::{header::{InvalidHeaderName, InvalidHeaderValue}, HeaderName, HeaderValue};
+use axum::{extract::ConnectInfo, http::{header::{InvalidHeaderName, InvalidHeaderValue, AUTHORIZATION}, HeaderMap, HeaderName, HeaderValue}};
 use chrono::{DateTime, Utc};
 use cookie::{time::OffsetDateTime, Cookie};
-use leptos::prelude::{use_context, ServerFnError};
+use leptos::{prelude::{use_context, ServerFnError}, server_fn::error::ServerFnErrorErr};
 use leptos_axum::ResponseOptions;
 use sqlx::Row;
 use uuid::Uuid;
 
+use crate::{user::{api::{ApiScope, AuthEventKind, Permission, UserSession}, events::{log_auth_event, AuthEventSubject}, permissions::get_permissions_internal, tokens::check_api_token_with_state}, util::verify_hash};
 use crate::app::{cookie::server::{get_cookie_jar, CookieError}, state::server::{extract_db, extract_state, ExtractError, SharedAppState}};
 
 pub mod values {
 	pub const SESSION_LENGTH: i64 = 30;
+
+	/// how many leading bits of the address a strict-ip session may not change, the whole address by default
+	pub const STRICT_IP_V4_PREFIX_ENV: &str = "THRW_STRICT_IP_V4_PREFIX";
+	pub const STRICT_IP_V6_PREFIX_ENV: &str = "THRW_STRICT_IP_V6_PREFIX";
 }
 
+/// the prefix lengths addresses of strict-ip sessions are compared by
+#[derive(Debug, Clone, Copy)]
+struct StrictIpConfig {
+	v4_prefix: u32,
+	v6_prefix: u32,
+}
+impl StrictIpConfig {
+	fn from_env() -> Self {
+		let prefix_var = |name, max: u32| std::env::var(name)
+			.ok()
+			.and_then(|val| val.trim().parse::<u32>().ok())
+			.map(|prefix| prefix.min(max))
+			.unwrap_or(max)
+		;
+		Self {
+			v4_prefix: prefix_var(values::STRICT_IP_V4_PREFIX_ENV, 32),
+			v6_prefix: prefix_var(values::STRICT_IP_V6_PREFIX_ENV, 128),
+		}
+	}
+
+	fn same_network(&self, a: IpAddr, b: IpAddr) -> bool {
+		// a prefix of 0 would overflow the shift, it allows any address anyway
+		match (a.to_canonical(), b.to_canonical()) {
+			(IpAddr::V4(a), IpAddr::V4(b)) => self.v4_prefix == 0
+				|| (u32::from(a) ^ u32::from(b)) >> (32 - self.v4_prefix) == 0,
+			(IpAddr::V6(a), IpAddr::V6(b)) => self.v6_prefix == 0
+				|| (u128::from(a) ^ u128::from(b)) >> (128 - self.v6_prefix) == 0,
+			_ => false,
+		}
+	}
+}
+
+static STRICT_IP_CONFIG: LazyLock<StrictIpConfig> = LazyLock::new(StrictIpConfig::from_env);
+
 #[derive(Debug)]
 pub enum LocalAuthError {
 	NotAuthenticated,
@@ -23,7 +62,24 @@ pub enum LocalAuthError {
 	AlreadyLoggedIn,
 	NoKeyChain,
 	InvalidUserLevel,
-	AdminLevelNotInDb,
+	WrongPassword,
+	IpMismatch,
+	TotpEnabled,
+	TotpNotEnrolled,
+	WrongCode,
+	TooManyAttempts,
+	Totp(String),
+	/// api tokens only reach what their scopes allow
+	MissingScope,
+	InvalidApiToken(String),
+	/// seconds until the next attempt is allowed
+	RateLimited(i64),
+	InvalidEmail,
+	/// a mailed token that is malformed, used or replaced already
+	InvalidEmailToken,
+	EmailNotVerified,
+	/// no secret key is configured to sign tokens with
+	SecretsUnavailable,
 }
 
 crate::make_error_type!{
@@ -34,6 +90,7 @@ crate::make_error_type!{
 		Sql(sqlx::Error),
 		Uuid(uuid::Error),
 		Server(ServerFnError),
+		ServerExtract(ServerFnErrorErr),
 		HeaderName(InvalidHeaderName),
 		HeaderValue(InvalidHeaderValue),
 	}
@@ -75,6 +132,26 @@ pub async fn use_keychain(keychain: String) -> Result<i16, AuthError> {
 	Ok(keychain.entry_level)
 }
 
+/// re-verify the password of a logged in user before changing anything sensitive
+pub async fn verify_user_password(db_pool: &sqlx::Pool<sqlx::Postgres>, user_id: i32, pw: &String) -> Result<(), AuthError> {
+	let rec = sqlx::query!("
+		SELECT pwhash
+		FROM users
+		WHERE id = $1
+		;",
+		user_id
+	)
+		.fetch_one(db_pool)
+		.await?
+	;
+
+	match verify_hash(pw, &rec.pwhash) {
+		Ok(true) => Ok(()),
+		Ok(false) => Err(AuthError::Auth(LocalAuthError::WrongPassword)),
+		Err(err) => Err(AuthError::Server(ServerFnError::ServerError(err))),
+	}
+}
+
 // only available in requests
 pub async fn get_user_level_internal(id: Option<i32>) -> Result<(i32, String), AuthError> {
 	let id = match id {
@@ -155,15 +232,23 @@ pub async fn end_session(token: Uuid) -> Result<(), AuthError> {
 	end_session_with_state(token, extract_state()?).await
 }
 
-// pub async  fn check_token_validity(token: Uuid) 
-pub async fn check_token_validity_and_refresh_with_state(token: Uuid, state: SharedAppState) -> Result<(i32, Uuid), AuthError> {
-	let db_pool = state.db_pool;
+/// sessions of users with strict ip set are only valid from the network they were created from
+fn session_ip_matches(session_ip: Option<&str>, addr: IpAddr) -> bool {
+	session_ip
+		.and_then(|ip| ip.parse::<SocketAddr>().ok())
+		.is_some_and(|session_addr| STRICT_IP_CONFIG.same_network(session_addr.ip(), addr))
+}
+
+/// the user of a session that exists, isn't expired and, for strict-ip users, is used from its network; ends it otherwise
+pub async fn check_token_validity_with_state(token: Uuid, addr: IpAddr, state: SharedAppState) -> Result<i32, AuthError> {
+	let db_pool = state.db_pool.clone();
 
-	let token_row = sqlx::query!("
-		SELECT expires_at, user_id
+	let token_row = sqlx::query!(r#"
+		SELECT sessions.expires_at, sessions.user_id, sessions.ip_address, usersettings.strict_ip AS "strict_ip?"
 		FROM sessions
-		WHERE session_id = $1
-		;",
+		LEFT JOIN usersettings ON usersettings.id = sessions.user_id
+		WHERE sessions.session_id = $1
+		;"#,
 		token
 	)
 		.fetch_optional(&db_pool)
@@ -176,18 +261,43 @@ pub async fn check_token_validity_and_refresh_with_state(token: Uuid, state: Sha
 
 	let expires_at: DateTime<Utc> = token_row.expires_at;
 	if chrono::offset::Utc::now() > expires_at {
-		end_session(token).await?;
+		end_session_with_state(token, state).await?;
 		return Err(AuthError::Auth(LocalAuthError::Expired));
 	}
 
+	if token_row.strict_ip.unwrap_or(false)
+	&& !session_ip_matches(token_row.ip_address.as_deref(), addr) {
+		println!(
+			"session of user {} used from {addr} but created from {}, ending it",
+			token_row.user_id,
+			token_row.ip_address.as_deref().unwrap_or("an unknown address")
+		);
+		log_auth_event(
+			&db_pool,
+			AuthEventKind::IpMismatch,
+			AuthEventSubject { user_id: Some(token_row.user_id), ip: Some(addr), ..Default::default() },
+			token_row.ip_address.clone().map(|ip| format!("session created from {ip}"))
+		).await;
+		end_session_with_state(token, state).await?;
+		return Err(AuthError::Auth(LocalAuthError::IpMismatch));
+	}
+
+	Ok(token_row.user_id)
+}
+
+pub async fn check_token_validity_and_refresh_with_state(token: Uuid, addr: IpAddr, state: SharedAppState) -> Result<(i32, Uuid), AuthError> {
+	let db_pool = state.db_pool.clone();
+	let user_id = check_token_validity_with_state(token, addr, state).await?;
+
 	refresh_token(token, Some(db_pool)).await?;
 	refresh_token_cookie(token)?;
 
-	Ok((token_row.user_id, token))
+	Ok((user_id, token))
 }
 
 pub async fn check_token_validity_and_refresh(token: Uuid) -> Result<(i32, Uuid), AuthError> {
-	check_token_validity_and_refresh_with_state(token, extract_state()?).await
+	let ConnectInfo(addr): ConnectInfo<SocketAddr> = leptos_axum::extract().await?;
+	check_token_validity_and_refresh_with_state(token, addr.ip(), extract_state()?).await
 }
 
 pub fn refresh_token_cookie(token: Uuid) -> Result<(), AuthError> {
@@ -232,7 +342,7 @@ pub async fn refresh_token(token: Uuid, db_pool: Option<sqlx::Pool<sqlx::Postgre
 	Ok(())
 }
 
-pub async fn generate_auth_token(user_id: i32, addr: SocketAddr) -> Result<Uuid, AuthError> {
+pub async fn generate_auth_token(user_id: i32, addr: SocketAddr, user_agent: Option<String>) -> Result<Uuid, AuthError> {
 	let db_pool = extract_db()?;
 
 	let uuid = uuid::Uuid::new_v4();
@@ -240,14 +350,15 @@ pub async fn generate_auth_token(user_id: i32, addr: SocketAddr) -> Result<Uuid,
 	addr.set_port(0);
 	sqlx::query!("
 		INSERT INTO sessions
-		(session_id, user_id, expires_at, ip_address)
+		(session_id, user_id, expires_at, ip_address, user_agent)
 		VALUES
-		($1, $2, $3, $4)
+		($1, $2, $3, $4, $5)
 		;",
 		uuid,
 		user_id,
 		get_next_expiry_time(),
-		addr.to_string()
+		addr.to_string(),
+		user_agent
 	)
 		.execute(&db_pool)
 		.await?
@@ -256,6 +367,130 @@ pub async fn generate_auth_token(user_id: i32, addr: SocketAddr) -> Result<Uuid,
 	Ok(uuid)
 }
 
+/// the sessions of a single user or, without one, of every user; `current` is marked as such
+pub async fn get_sessions_internal(db_pool: &sqlx::Pool<sqlx::Postgres>, user_id: Option<i32>, current: Option<Uuid>) -> Result<Vec<UserSession>, AuthError> {
+	let recs = sqlx::query!("
+		SELECT sessions.id, sessions.session_id, sessions.user_id, users.email, sessions.created_at, sessions.expires_at, sessions.ip_address, sessions.user_agent
+		FROM sessions
+		JOIN users ON users.id = sessions.user_id
+		WHERE ($1::INTEGER IS NULL OR sessions.user_id = $1)
+		  AND sessions.expires_at > now()
+		ORDER BY sessions.created_at DESC
+		;",
+		user_id
+	)
+		.fetch_all(db_pool)
+		.await?
+	;
+
+	Ok(recs
+		.into_iter()
+		.map(|rec| UserSession {
+			current: current == Some(rec.session_id),
+			id: rec.id,
+			user_id: rec.user_id,
+			email: rec.email,
+			created_at: rec.created_at,
+			expires_at: rec.expires_at,
+			ip_address: rec.ip_address,
+			user_agent: rec.user_agent,
+		})
+		.collect()
+	)
+}
+
+/// the user of the session with the listed id
+pub async fn get_session_user_id(db_pool: &sqlx::Pool<sqlx::Postgres>, id: Uuid) -> Result<i32, AuthError> {
+	let rec = sqlx::query!("
+		SELECT user_id
+		FROM sessions
+		WHERE id = $1
+		;",
+		id
+	)
+		.fetch_optional(db_pool)
+		.await?
+		.ok_or(AuthError::Auth(LocalAuthError::Invalid))?
+	;
+	Ok(rec.user_id)
+}
+
+/// ends the session with the listed id of the user, or of anyone if no user is given, returning its token
+pub async fn kill_session_internal(db_pool: &sqlx::Pool<sqlx::Postgres>, id: Uuid, user_id: Option<i32>) -> Result<Uuid, AuthError> {
+	let rec = sqlx::query!("
+		DELETE FROM sessions
+		WHERE id = $1
+		  AND ($2::INTEGER IS NULL OR user_id = $2)
+		RETURNING session_id
+		;",
+		id,
+		user_id
+	)
+		.fetch_optional(db_pool)
+		.await?
+		.ok_or(AuthError::Auth(LocalAuthError::Invalid))?
+	;
+	Ok(rec.session_id)
+}
+
+/// logs the user out everywhere but `keep`, revokes their api tokens and drops logins waiting for the second factor
+pub async fn end_user_access(
+	tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+	user_id: i32,
+	keep: Option<Uuid>,
+) -> Result<(), AuthError> {
+	sqlx::query!("
+		DELETE FROM sessions
+		WHERE user_id = $1
+		  AND ($2::UUID IS NULL OR session_id <> $2)
+		;",
+		user_id,
+		keep
+	)
+		.execute(&mut **tx)
+		.await?
+	;
+	sqlx::query!("
+		DELETE FROM api_tokens
+		WHERE user_id = $1
+		;",
+		user_id
+	)
+		.execute(&mut **tx)
+		.await?
+	;
+	sqlx::query!("
+		DELETE FROM login_challenges
+		WHERE user_id = $1
+		;",
+		user_id
+	)
+		.execute(&mut **tx)
+		.await?
+	;
+	Ok(())
+}
+
+/// log the user in from the current request, returning the id, token, level name and permissions the client keeps
+pub async fn start_session(user_id: i32) -> Result<(i32, String, String, Vec<Permission>), AuthError> {
+	use axum::http::header::USER_AGENT;
+
+	let ConnectInfo(addr): ConnectInfo<SocketAddr> = leptos_axum::extract().await?;
+	let headers: HeaderMap = leptos_axum::extract().await?;
+	let user_agent = headers
+		.get(USER_AGENT)
+		.and_then(|agent| agent.to_str().ok())
+		.map(ToString::to_string)
+	;
+
+	let token = generate_auth_token(user_id, addr, user_agent).await?;
+	refresh_token_cookie(token)?;
+
+	let (_, level_name) = get_user_level_internal(Some(user_id)).await?;
+	let permissions = get_permissions_internal(&extract_db()?, user_id).await?;
+	Ok((user_id, token.to_string(), level_name, permissions))
+}
+
 pub async fn extract_session_cookie() -> Result<Uuid, AuthError> {
 	let jar = get_cookie_jar()
 		.await?;
@@ -272,43 +507,49 @@ pub async fn extract_session_cookie() -> Result<Uuid, AuthError> {
 	Ok(uuid::Uuid::from_str(session_id)?)
 }
 
-// notify that authentication is required; only available in requests
-pub async fn require_auth() -> Result<(i32, Uuid), AuthError> {
-	let token = extract_session_cookie().await?;
-	let (id, _) = check_token_validity_and_refresh(token).await?;
+/// the token of an `Authorization: Bearer` header
+pub fn bearer_from_headers(headers: &HeaderMap) -> Option<String> {
+	headers
+		.get(AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "))
+		.map(|token| token.trim().to_string())
+}
 
-	Ok((id, token))
+pub async fn extract_bearer_token() -> Result<Option<String>, AuthError> {
+	let headers: HeaderMap = leptos_axum::extract().await?;
+	Ok(bearer_from_headers(&headers))
 }
 
-async fn get_admin_level() -> Result<i32, AuthError> {
-	let state = extract_state()?;
-	let admin_level = {
-		*state.user_data.admin_level.lock().await
-	};
-	match admin_level {
-		Some(id) => Ok(id),
+/// what a request was authenticated with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Caller {
+	Session(Uuid),
+	ApiToken(Uuid),
+}
+
+/// like `require_auth`, but api tokens with the scope are accepted as well
+pub async fn require_scope(scope: ApiScope) -> Result<(i32, Caller), AuthError> {
+	match extract_bearer_token().await? {
+		Some(token) => {
+			let (user_id, token_id) = check_api_token_with_state(&token, scope, extract_state()?).await?;
+			Ok((user_id, Caller::ApiToken(token_id)))
+		},
 		None => {
-			let level = sqlx::query!("
-				SELECT level_id AS id, level_name
-				FROM user_levels
-				WHERE level_name = 'admin'
-				;"
-			)
-				.fetch_one(&state.db_pool)
-				.await?
-			;
-
-			*state.user_data.admin_level.lock().await = Some(level.id as i32);
-			Ok(level.id as i32)
+			let (user_id, session_id) = require_auth().await?;
+			Ok((user_id, Caller::Session(session_id)))
 		},
 	}
 }
 
-pub async fn require_admin() -> Result<(), AuthError> {
-	let (user_level, _) = get_user_level_internal(None).await?;
-	(
-		user_level >= get_admin_level()
-			.await?
-	)
-		.ok_or(AuthError::Auth(LocalAuthError::Forbidden))
-}
\ No newline at end of file
+// notify that authentication is required; only available in requests
+pub async fn require_auth() -> Result<(i32, Uuid), AuthError> {
+	// anything not behind `require_scope` is off limits for api tokens
+	if extract_bearer_token().await?.is_some() {
+		return Err(AuthError::Auth(LocalAuthError::MissingScope));
+	}
+	let token = extract_session_cookie().await?;
+	let (id, _) = check_token_validity_and_refresh(token).await?;
+
+	Ok((id, token))
+}
@@ -0,0 +1,302 @@
+use std::{collections::HashMap, sync::{Arc, LazyLock, Mutex}};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use super::{auth::{AuthError, LocalAuthError}, limiter::insert_bounded};
+use crate::app::mail::{send_in_background, Mail, Mailer};
+
+mod consts {
+	/// the same key the stored site logins are encrypted with
+	pub const SECRET_KEY_ENV: &str = "THRW_SECRET_KEY";
+	/// where the links in mails point to, `http://<THRW_IP>:<THRW_PORT>` by default
+	pub const PUBLIC_URL_ENV: &str = "THRW_PUBLIC_URL";
+	/// refuse logins until the email is verified
+	pub const REQUIRE_VERIFIED_ENV: &str = "THRW_REQUIRE_VERIFIED_EMAIL";
+
+	pub const VERIFY_EXPIRY_HOURS: i64 = 48;
+	pub const RESET_EXPIRY_MINUTES: i64 = 60;
+	pub const MAX_EMAIL_LENGTH: usize = 254;
+	/// mails sent to the same address within the window
+	pub const MAILS_PER_WINDOW: i32 = 5;
+	pub const MAIL_WINDOW_MINUTES: i64 = 60;
+
+	pub const VERIFY_PATH: &str = "/verify-email";
+	pub const RESET_PATH: &str = "/reset-password";
+}
+
+/// what a mailed token may be used for, a token of one purpose is never accepted for the other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailTokenPurpose {
+	VerifyEmail,
+	ResetPassword,
+}
+impl ToString for EmailTokenPurpose {
+	fn to_string(&self) -> String {
+		match self {
+			EmailTokenPurpose::VerifyEmail => "verify_email",
+			EmailTokenPurpose::ResetPassword => "reset_password",
+		}.to_string()
+	}
+}
+impl EmailTokenPurpose {
+	fn lifetime(&self) -> TimeDelta {
+		match self {
+			EmailTokenPurpose::VerifyEmail => TimeDelta::hours(consts::VERIFY_EXPIRY_HOURS),
+			EmailTokenPurpose::ResetPassword => TimeDelta::minutes(consts::RESET_EXPIRY_MINUTES),
+		}
+	}
+}
+
+/// mails sent to an address since the window started
+#[derive(Debug, Clone, Copy)]
+struct MailWindow {
+	started: DateTime<Utc>,
+	sent: i32,
+}
+impl MailWindow {
+	fn ends(&self) -> DateTime<Utc> {
+		self.started + TimeDelta::minutes(consts::MAIL_WINDOW_MINUTES)
+	}
+}
+
+static MAILS_SENT: LazyLock<Mutex<HashMap<String, MailWindow>>> = LazyLock::new(Default::default);
+
+static REQUIRE_VERIFIED: LazyLock<bool> = LazyLock::new(|| std::env::var(consts::REQUIRE_VERIFIED_ENV)
+	.is_ok_and(|val| matches!(val.to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+);
+
+pub fn is_verified_email_required() -> bool {
+	*REQUIRE_VERIFIED
+}
+
+fn invalid_token() -> AuthError {
+	AuthError::Auth(LocalAuthError::InvalidEmailToken)
+}
+
+/// the form addresses are stored and compared in, `Foo@Example.com ` and `foo@example.com` are the same account
+pub fn normalize_email(email: &str) -> String {
+	email.trim().to_lowercase()
+}
+
+/// a plausible address, whether it exists is up to the verification mail
+pub fn validate_email(email: &str) -> Result<String, AuthError> {
+	let email = normalize_email(email);
+	let valid = email.len() <= consts::MAX_EMAIL_LENGTH
+		&& !email.chars().any(|c| c.is_whitespace() || c.is_control())
+		&& email.split_once('@').is_some_and(|(local, domain)| {
+			!local.is_empty()
+				&& !domain.contains('@')
+				&& domain.split('.').count() > 1
+				&& domain.split('.').all(|label| !label.is_empty())
+		})
+	;
+	valid.ok_or(AuthError::Auth(LocalAuthError::InvalidEmail))?;
+	Ok(email)
+}
+
+fn public_url() -> String {
+	std::env::var(consts::PUBLIC_URL_ENV)
+		.map(|url| url.trim_end_matches('/').to_string())
+		.unwrap_or_else(|_| format!(
+			"http://{}:{}",
+			std::env::var("THRW_IP").unwrap_or("localhost".to_string()),
+			std::env::var("THRW_PORT").unwrap_or("3000".to_string()),
+		))
+}
+
+fn get_mac() -> Result<Hmac<Sha256>, AuthError> {
+	let secret_key = std::env::var(consts::SECRET_KEY_ENV)
+		.ok()
+		.filter(|key| !key.trim().is_empty())
+		.ok_or(AuthError::Auth(LocalAuthError::SecretsUnavailable))?
+	;
+	// any key length is accepted by hmac
+	Hmac::<Sha256>::new_from_slice(secret_key.as_bytes())
+		.map_err(|_| AuthError::Auth(LocalAuthError::SecretsUnavailable))
+}
+
+fn signed_message(purpose: EmailTokenPurpose, id: Uuid, expiry: i64) -> String {
+	format!("{}:{id}:{expiry}", purpose.to_string())
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+	if hex.len() % 2 != 0 {
+		return None;
+	}
+	(0..hex.len())
+		.step_by(2)
+		.map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+		.collect()
+}
+
+/// `<id>.<expiry>.<signature>`, the signature keeps ids from being guessed or the expiry from being changed
+fn sign_token(purpose: EmailTokenPurpose, id: Uuid, expires_at: DateTime<Utc>) -> Result<String, AuthError> {
+	let expiry = expires_at.timestamp();
+	let mut mac = get_mac()?;
+	mac.update(signed_message(purpose, id, expiry).as_bytes());
+	let signature: String = mac
+		.finalize()
+		.into_bytes()
+		.iter()
+		.map(|byte| format!("{byte:02x}"))
+		.collect()
+	;
+	Ok(format!("{id}.{expiry}.{signature}"))
+}
+
+/// the id of a token that is signed by this server and not expired yet
+fn verify_token(purpose: EmailTokenPurpose, token: &str) -> Result<Uuid, AuthError> {
+	let mut parts = token.trim().split('.');
+	let (Some(id), Some(expiry), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+		return Err(invalid_token());
+	};
+	let id = Uuid::parse_str(id).map_err(|_| invalid_token())?;
+	let expiry = expiry.parse::<i64>().map_err(|_| invalid_token())?;
+	let signature = decode_hex(signature).ok_or(invalid_token())?;
+
+	let mut mac = get_mac()?;
+	mac.update(signed_message(purpose, id, expiry).as_bytes());
+	mac.verify_slice(&signature).map_err(|_| invalid_token())?;
+
+	if expiry < Utc::now().timestamp() {
+		return Err(AuthError::Auth(LocalAuthError::Expired));
+	}
+	Ok(id)
+}
+
+/// replaces any unused token of the same purpose, only the newest mail works
+pub async fn issue_email_token(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	purpose: EmailTokenPurpose,
+	email: &str,
+) -> Result<String, AuthError> {
+	sqlx::query!("
+		DELETE FROM email_tokens
+		WHERE user_id = $1
+		  AND purpose = $2
+		  AND used_at IS NULL
+		;",
+		user_id,
+		purpose.to_string()
+	)
+		.execute(db_pool)
+		.await?
+	;
+
+	let id = Uuid::new_v4();
+	let expires_at = Utc::now() + purpose.lifetime();
+	let token = sign_token(purpose, id, expires_at)?;
+	sqlx::query!("
+		INSERT INTO email_tokens
+			(id, user_id, purpose, email, expires_at)
+		VALUES
+			($1, $2, $3, $4, $5)
+		;",
+		id,
+		user_id,
+		purpose.to_string(),
+		email,
+		expires_at
+	)
+		.execute(db_pool)
+		.await?
+	;
+
+	Ok(token)
+}
+
+/// marks the token as used, returning the user and the address it was sent to
+pub async fn consume_email_token(
+	db_pool: &Pool<Postgres>,
+	purpose: EmailTokenPurpose,
+	token: &str,
+) -> Result<(i32, String), AuthError> {
+	let id = verify_token(purpose, token)?;
+
+	// the signature may be valid while the token was used or replaced already
+	let rec = sqlx::query!("
+		UPDATE email_tokens
+		SET used_at = now()
+		WHERE id = $1
+		  AND purpose = $2
+		  AND used_at IS NULL
+		  AND expires_at > now()
+		RETURNING user_id, email
+		;",
+		id,
+		purpose.to_string()
+	)
+		.fetch_optional(db_pool)
+		.await?
+		.ok_or(invalid_token())?
+	;
+
+	Ok((rec.user_id, rec.email))
+}
+
+/// keeps the server from being used to flood an inbox, every mail counts against the address
+pub fn throttle_mail(email: &str) -> Result<(), AuthError> {
+	let email = normalize_email(email);
+	let now = Utc::now();
+	let mut windows = MAILS_SENT.lock().unwrap();
+	let window = windows
+		.get(&email)
+		.copied()
+		.filter(|window| window.ends() > now)
+		.unwrap_or(MailWindow { started: now, sent: 0 })
+	;
+	if window.sent >= consts::MAILS_PER_WINDOW {
+		let wait = (window.ends() - now).num_seconds().max(1);
+		return Err(AuthError::Auth(LocalAuthError::RateLimited(wait)));
+	}
+
+	let window = MailWindow { sent: window.sent + 1, ..window };
+	insert_bounded(&mut windows, email, window, |window| window.ends() <= now, MailWindow::ends);
+	Ok(())
+}
+
+pub async fn send_verification_mail(
+	db_pool: &Pool<Postgres>,
+	mailer: Arc<dyn Mailer>,
+	user_id: i32,
+	email: &str,
+) -> Result<(), AuthError> {
+	throttle_mail(email)?;
+	let token = issue_email_token(db_pool, user_id, EmailTokenPurpose::VerifyEmail, email).await?;
+	let link = format!("{}{}?token={token}", public_url(), consts::VERIFY_PATH);
+
+	send_in_background(mailer, Mail {
+		to: email.to_string(),
+		subject: "Verify your email address".to_string(),
+		body: format!(
+			"Open the link below to verify this address for your account:\n\n{link}\n\nThe link expires in {} hours. If you didn't sign up, you can ignore this mail.",
+			consts::VERIFY_EXPIRY_HOURS
+		),
+	});
+	Ok(())
+}
+
+pub async fn send_password_reset_mail(
+	db_pool: &Pool<Postgres>,
+	mailer: Arc<dyn Mailer>,
+	user_id: i32,
+	email: &str,
+) -> Result<(), AuthError> {
+	let token = issue_email_token(db_pool, user_id, EmailTokenPurpose::ResetPassword, email).await?;
+	let link = format!("{}{}?token={token}", public_url(), consts::RESET_PATH);
+
+	send_in_background(mailer, Mail {
+		to: email.to_string(),
+		subject: "Reset your password".to_string(),
+		body: format!(
+			"Open the link below to choose a new password:\n\n{link}\n\nThe link expires in {} minutes and all sessions are logged out once it is used. If you didn't ask for this, you can ignore this mail.",
+			consts::RESET_EXPIRY_MINUTES
+		),
+	});
+	Ok(())
+}
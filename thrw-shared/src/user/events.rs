@@ -0,0 +1,76 @@
+use std::net::IpAddr;
+
+use sqlx::{Pool, Postgres};
+
+use super::auth::AuthError;
+use crate::user::api::{AuthEvent, AuthEventKind};
+
+/// who an event is about, as far as it is known
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AuthEventSubject<'a> {
+	pub email: Option<&'a str>,
+	pub user_id: Option<i32>,
+	pub ip: Option<IpAddr>,
+}
+
+/// failing to log never fails the attempt that is logged
+pub async fn log_auth_event(
+	db_pool: &Pool<Postgres>,
+	event: AuthEventKind,
+	subject: AuthEventSubject<'_>,
+	detail: Option<String>,
+) {
+	let res = sqlx::query!("
+		INSERT INTO auth_events
+			(event, email, user_id, ip_address, detail)
+		VALUES
+			($1, $2, $3, $4, $5)
+		;",
+		event.to_string(),
+		subject.email,
+		subject.user_id,
+		subject.ip.map(|ip| ip.to_string()),
+		detail
+	)
+		.execute(db_pool)
+		.await
+	;
+	if let Err(err) = res {
+		println!("unable to log auth event '{}': {err:?}", event.to_string());
+	}
+}
+
+/// newest first, `before` pages back from an event id
+pub async fn get_auth_events_internal(
+	db_pool: &Pool<Postgres>,
+	limit: i64,
+	before: Option<i64>,
+) -> Result<Vec<AuthEvent>, AuthError> {
+	let recs = sqlx::query!("
+		SELECT id, event, email, user_id, ip_address, detail, created_at
+		FROM auth_events
+		WHERE ($2::BIGINT IS NULL OR id < $2)
+		ORDER BY id DESC
+		LIMIT $1
+		;",
+		limit,
+		before
+	)
+		.fetch_all(db_pool)
+		.await?
+	;
+
+	Ok(recs
+		.into_iter()
+		.map(|rec| AuthEvent {
+			id: rec.id,
+			event: rec.event.into(),
+			email: rec.email,
+			user_id: rec.user_id,
+			ip_address: rec.ip_address,
+			detail: rec.detail,
+			created_at: rec.created_at,
+		})
+		.collect()
+	)
+}
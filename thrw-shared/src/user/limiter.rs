@@ -0,0 +1,347 @@
+use std::{collections::HashMap, net::IpAddr, sync::{LazyLock, Mutex}};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use sqlx::{Pool, Postgres};
+
+use super::{auth::{AuthError, LocalAuthError}, email::normalize_email, events::{log_auth_event, AuthEventSubject}};
+use crate::user::api::AuthEventKind;
+
+mod consts {
+	/// keep the failures in the database, so restarts don't reset them
+	pub const PERSIST_ENV: &str = "THRW_PERSIST_AUTH_LIMITS";
+
+	/// failures before attempts are slowed down
+	pub const FREE_ATTEMPTS: i32 = 3;
+	/// the delay doubles with every failure up to this
+	pub const MAX_DELAY_SECS: i64 = 60;
+	pub const LOCKOUT_FAILURES: i32 = 10;
+	pub const LOCKOUT_MINUTES: i64 = 15;
+	/// failures are forgotten after this long without another one
+	pub const RESET_HOURS: i64 = 1;
+	/// at most this many records are kept in memory, stale ones are dropped first and then the ones that expire soonest
+	pub const MAX_RECORDS: usize = 10_000;
+}
+
+/// what failed attempts are counted against
+#[derive(Debug, Clone)]
+pub enum LimitKey {
+	LoginIp(IpAddr),
+	LoginEmail(String),
+	/// keychain guessing
+	RegisterIp(IpAddr),
+	/// codes guessed over several login challenges
+	SecondFactor(i32),
+}
+impl LimitKey {
+	fn to_key(&self) -> String {
+		match self {
+			LimitKey::LoginIp(ip) => format!("login-ip:{}", ip.to_canonical()),
+			LimitKey::LoginEmail(email) => format!("login-email:{}", normalize_email(email)),
+			LimitKey::RegisterIp(ip) => format!("register-ip:{}", ip.to_canonical()),
+			LimitKey::SecondFactor(user_id) => format!("second-factor:{user_id}"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FailureRecord {
+	failures: i32,
+	last_failure: DateTime<Utc>,
+	locked_until: Option<DateTime<Utc>>,
+}
+impl FailureRecord {
+	/// when the next attempt is allowed
+	fn retry_at(&self) -> DateTime<Utc> {
+		let delay = match self.failures - consts::FREE_ATTEMPTS {
+			..0 => 0,
+			over => 2i64.saturating_pow(over as u32).min(consts::MAX_DELAY_SECS),
+		};
+		let delayed = self.last_failure + TimeDelta::seconds(delay);
+		self.locked_until
+			.map(|until| until.max(delayed))
+			.unwrap_or(delayed)
+	}
+
+	fn is_stale(&self, now: DateTime<Utc>) -> bool {
+		now - self.last_failure > TimeDelta::hours(consts::RESET_HOURS)
+			&& self.locked_until.is_none_or(|until| until < now)
+	}
+}
+
+static RECORDS: LazyLock<Mutex<HashMap<String, FailureRecord>>> = LazyLock::new(Default::default);
+static PERSIST: LazyLock<bool> = LazyLock::new(|| std::env::var(consts::PERSIST_ENV)
+	.is_ok_and(|val| matches!(val.to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+);
+
+/// keeps a map of records from growing without end, once full the stale records go and then the one `expires` first
+pub(crate) fn insert_bounded<V>(
+	records: &mut HashMap<String, V>,
+	key: String,
+	record: V,
+	is_stale: impl Fn(&V) -> bool,
+	expires: impl Fn(&V) -> DateTime<Utc>,
+) {
+	if !records.contains_key(&key) && records.len() >= consts::MAX_RECORDS {
+		records.retain(|_, record| !is_stale(record));
+		let first = records
+			.iter()
+			.min_by_key(|(_, record)| expires(record))
+			.map(|(key, _)| key.clone())
+		;
+		if records.len() >= consts::MAX_RECORDS && let Some(first) = first {
+			records.remove(&first);
+		}
+	}
+	records.insert(key, record);
+}
+
+/// loads persisted records into memory, where the attempts are counted
+async fn load_records(
+	db_pool: &Pool<Postgres>,
+	keys: &[String],
+) -> Result<(), AuthError> {
+	if !*PERSIST {
+		return Ok(());
+	}
+	let missing: Vec<String> = {
+		let records = RECORDS.lock().unwrap();
+		keys
+			.iter()
+			.filter(|key| !records.contains_key(*key))
+			.cloned()
+			.collect()
+	};
+	if missing.is_empty() {
+		return Ok(());
+	}
+
+	let recs = sqlx::query!("
+		SELECT limit_key, failures, last_failure, locked_until
+		FROM auth_limits
+		WHERE limit_key = ANY($1)
+		;",
+		&missing
+	)
+		.fetch_all(db_pool)
+		.await?
+	;
+
+	let now = Utc::now();
+	let mut records = RECORDS.lock().unwrap();
+	for rec in recs {
+		// an attempt in the meantime is newer than the stored record
+		if records.contains_key(&rec.limit_key) {
+			continue;
+		}
+		let record = FailureRecord {
+			failures: rec.failures,
+			last_failure: rec.last_failure,
+			locked_until: rec.locked_until,
+		};
+		insert_bounded(&mut records, rec.limit_key, record, |record| record.is_stale(now), FailureRecord::retry_at);
+	}
+	Ok(())
+}
+
+async fn persist_record(
+	db_pool: &Pool<Postgres>,
+	key: &str,
+	record: Option<FailureRecord>,
+) -> Result<(), AuthError> {
+	if !*PERSIST {
+		return Ok(());
+	}
+
+	match record {
+		Some(record) => sqlx::query!("
+			INSERT INTO auth_limits
+				(limit_key, failures, last_failure, locked_until)
+			VALUES
+				($1, $2, $3, $4)
+			ON CONFLICT (limit_key) DO UPDATE
+				SET failures = EXCLUDED.failures, last_failure = EXCLUDED.last_failure, locked_until = EXCLUDED.locked_until
+			;",
+			key,
+			record.failures,
+			record.last_failure,
+			record.locked_until
+		)
+			.execute(db_pool)
+			.await?,
+		None => sqlx::query!("
+			DELETE FROM auth_limits
+			WHERE limit_key = $1
+			;",
+			key
+		)
+			.execute(db_pool)
+			.await?,
+	};
+	Ok(())
+}
+
+/// what a key looked like before an attempt was counted against it
+struct ReservedKey {
+	key: String,
+	previous: Option<FailureRecord>,
+	/// whether this attempt locked the key
+	locked: bool,
+}
+
+/// an attempt that is counted as failed until it is rolled back, so parallel attempts can't all pass before the first failure is recorded
+#[must_use]
+pub struct AuthAttempt {
+	reserved_at: DateTime<Utc>,
+	keys: Vec<ReservedKey>,
+}
+
+/// rejects the attempt while any of the keys is slowed down or locked, otherwise counts it against all of them
+pub async fn reserve_auth_attempt(
+	db_pool: &Pool<Postgres>,
+	keys: &[LimitKey],
+) -> Result<AuthAttempt, AuthError> {
+	let keys: Vec<String> = keys
+		.iter()
+		.map(LimitKey::to_key)
+		.collect()
+	;
+	load_records(db_pool, &keys).await?;
+
+	let now = Utc::now();
+	let mut reserved_keys = Vec::new();
+	let mut reserved = Vec::new();
+	{
+		let mut records = RECORDS.lock().unwrap();
+		let current: Vec<Option<FailureRecord>> = keys
+			.iter()
+			.map(|key| records
+				.get(key)
+				.copied()
+				.filter(|record| !record.is_stale(now))
+			)
+			.collect()
+		;
+
+		let retry_at = current
+			.iter()
+			.flatten()
+			.map(FailureRecord::retry_at)
+			.max()
+		;
+		if let Some(retry_at) = retry_at && retry_at > now {
+			let wait = (retry_at - now).num_seconds().max(1);
+			return Err(AuthError::Auth(LocalAuthError::RateLimited(wait)));
+		}
+
+		for (key, previous) in keys.iter().zip(current) {
+			let failures = previous.map(|record| record.failures).unwrap_or(0) + 1;
+			let locked = failures >= consts::LOCKOUT_FAILURES;
+			let locked_until = match locked {
+				true => Some(now + TimeDelta::minutes(consts::LOCKOUT_MINUTES)),
+				false => previous.and_then(|record| record.locked_until),
+			};
+			let record = FailureRecord {
+				failures,
+				last_failure: now,
+				locked_until,
+			};
+			insert_bounded(&mut records, key.clone(), record, |record| record.is_stale(now), FailureRecord::retry_at);
+			reserved.push((key.clone(), record));
+			reserved_keys.push(ReservedKey {
+				key: key.clone(),
+				previous,
+				locked,
+			});
+		}
+	}
+
+	for (key, record) in reserved {
+		persist_record(db_pool, &key, Some(record)).await?;
+	}
+	Ok(AuthAttempt {
+		reserved_at: now,
+		keys: reserved_keys,
+	})
+}
+
+impl AuthAttempt {
+	/// the attempt didn't fail after all, e.g. the password was right and the second factor is still missing
+	pub async fn rollback(self, db_pool: &Pool<Postgres>) -> Result<(), AuthError> {
+		let mut restored = Vec::new();
+		{
+			let mut records = RECORDS.lock().unwrap();
+			for reserved in &self.keys {
+				let Some(record) = records.get_mut(&reserved.key) else {
+					continue;
+				};
+				record.failures -= 1;
+				// unless another attempt failed since, the next failure is delayed as if this one never happened
+				if record.last_failure == self.reserved_at
+				&& let Some(previous) = reserved.previous {
+					record.last_failure = previous.last_failure;
+				}
+				if reserved.locked {
+					record.locked_until = reserved.previous.and_then(|previous| previous.locked_until);
+				}
+				let record = match record.failures > 0 {
+					true => Some(*record),
+					false => {
+						records.remove(&reserved.key);
+						None
+					},
+				};
+				restored.push((reserved.key.clone(), record));
+			}
+		}
+
+		for (key, record) in restored {
+			persist_record(db_pool, &key, record).await?;
+		}
+		Ok(())
+	}
+
+	/// the failure is counted already, this logs it along with the lockout it may have caused
+	pub async fn fail(
+		self,
+		db_pool: &Pool<Postgres>,
+		event: AuthEventKind,
+		subject: AuthEventSubject<'_>,
+		detail: Option<String>,
+	) {
+		log_auth_event(db_pool, event, subject, detail).await;
+		if self.keys.iter().any(|reserved| reserved.locked) {
+			log_auth_event(db_pool, AuthEventKind::LockedOut, subject, None).await;
+		}
+	}
+}
+
+/// forget the failures after a successful attempt
+pub async fn clear_auth_failures(
+	db_pool: &Pool<Postgres>,
+	keys: &[LimitKey],
+) -> Result<(), AuthError> {
+	let keys: Vec<String> = keys
+		.iter()
+		.map(LimitKey::to_key)
+		.collect()
+	;
+	{
+		let mut records = RECORDS.lock().unwrap();
+		for key in &keys {
+			records.remove(key);
+		}
+	}
+
+	if *PERSIST {
+		sqlx::query!("
+			DELETE FROM auth_limits
+			WHERE limit_key = ANY($1)
+			;",
+			&keys
+		)
+			.execute(db_pool)
+			.await?
+		;
+	}
+	Ok(())
+}
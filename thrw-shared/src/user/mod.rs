@@ -1,9 +1,34 @@
 #[cfg(feature = "server")]
 pub mod auth;
+#[cfg(feature = "server")]
+pub mod totp;
+#[cfg(feature = "server")]
+pub mod tokens;
+#[cfg(feature = "server")]
+pub mod limiter;
+#[cfg(feature = "server")]
+pub mod events;
+#[cfg(feature = "server")]
+pub mod email;
+#[cfg(feature = "server")]
+pub mod permissions;
 pub mod api;
 
 pub mod prelude {
 	#[cfg(feature = "server")]
 	pub use super::auth::*;
+	#[cfg(feature = "server")]
+	pub use super::totp::*;
+	#[cfg(feature = "server")]
+	pub use super::tokens::*;
+	#[cfg(feature = "server")]
+	pub use super::limiter::*;
+	#[cfg(feature = "server")]
+	pub use super::events::*;
+	#[cfg(feature = "server")]
+	pub use super::email::*;
+	#[cfg(feature = "server")]
+	pub use super::permissions::*;
+	pub use super::api::{ApiScope, Permission};
 	pub use crate::util::*;
 }
\ No newline at end of file
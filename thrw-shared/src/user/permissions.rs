@@ -0,0 +1,337 @@
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use super::auth::{require_auth, require_scope, AuthError, Caller, LocalAuthError};
+use crate::app::state::server::extract_db;
+use crate::user::api::{ApiScope, Permission, UserRole};
+
+mod consts {
+	pub const MAX_ROLE_NAME_LENGTH: usize = 32;
+	/// key of the advisory lock role and membership changes are checked under
+	pub const ROLE_CHANGES_LOCK: i64 = 0x726f6c65;
+}
+
+fn parse_permissions(permissions: Vec<String>) -> Vec<Permission> {
+	permissions
+		.iter()
+		.filter_map(|permission| permission.parse().ok())
+		.collect()
+}
+
+fn permission_names(permissions: &[Permission]) -> Vec<String> {
+	let mut names: Vec<String> = permissions
+		.iter()
+		.map(ToString::to_string)
+		.collect()
+	;
+	names.sort();
+	names.dedup();
+	names
+}
+
+pub async fn get_permissions_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+) -> Result<Vec<Permission>, AuthError> {
+	let recs = sqlx::query!("
+		SELECT level_permissions.permission
+		FROM users
+		JOIN level_permissions ON level_permissions.level_id = users.user_level
+		WHERE users.id = $1
+		;",
+		user_id
+	)
+		.fetch_all(db_pool)
+		.await?
+	;
+
+	Ok(parse_permissions(recs
+		.into_iter()
+		.map(|rec| rec.permission)
+		.collect()
+	))
+}
+
+pub async fn check_permission(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	permission: Permission,
+) -> Result<(), AuthError> {
+	let rec = sqlx::query!(r#"
+		SELECT EXISTS(
+			SELECT 1
+			FROM users
+			JOIN level_permissions ON level_permissions.level_id = users.user_level
+			WHERE users.id = $1
+			  AND level_permissions.permission = $2
+		) AS "allowed!"
+		;"#,
+		user_id,
+		permission.to_string()
+	)
+		.fetch_one(db_pool)
+		.await?
+	;
+
+	rec.allowed
+		.ok_or(AuthError::Auth(LocalAuthError::Forbidden))
+}
+
+/// a logged in user with the permission; only available in requests
+pub async fn require_permission(permission: Permission) -> Result<(i32, Uuid), AuthError> {
+	let (user_id, token) = require_auth().await?;
+	check_permission(&extract_db()?, user_id, permission).await?;
+	Ok((user_id, token))
+}
+
+/// like `require_scope`, the user behind an api token needs the permission as well
+pub async fn require_scoped_permission(scope: ApiScope, permission: Permission) -> Result<(i32, Caller), AuthError> {
+	let (user_id, caller) = require_scope(scope).await?;
+	check_permission(&extract_db()?, user_id, permission).await?;
+	Ok((user_id, caller))
+}
+
+/// nobody hands out more than they may do themselves
+pub async fn check_grantable(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	permissions: &[Permission],
+) -> Result<(), AuthError> {
+	let own = get_permissions_internal(db_pool, user_id).await?;
+	permissions
+		.iter()
+		.all(|permission| own.contains(permission))
+		.ok_or(AuthError::Auth(LocalAuthError::Forbidden))
+}
+
+async fn get_role_permissions(
+	db_pool: &Pool<Postgres>,
+	level_id: i16,
+) -> Result<Vec<Permission>, AuthError> {
+	let recs = sqlx::query!("
+		SELECT permission
+		FROM level_permissions
+		WHERE level_id = $1
+		;",
+		level_id
+	)
+		.fetch_all(db_pool)
+		.await?
+	;
+
+	Ok(parse_permissions(recs
+		.into_iter()
+		.map(|rec| rec.permission)
+		.collect()
+	))
+}
+
+/// the role may only grant what the user has themselves
+pub async fn check_role_grantable(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	level_id: i16,
+) -> Result<(), AuthError> {
+	let exists = sqlx::query!("
+		SELECT level_id
+		FROM user_levels
+		WHERE level_id = $1
+		;",
+		level_id
+	)
+		.fetch_optional(db_pool)
+		.await?
+	;
+	exists.ok_or(AuthError::Auth(LocalAuthError::InvalidUserLevel))?;
+
+	let permissions = get_role_permissions(db_pool, level_id).await?;
+	check_grantable(db_pool, user_id, &permissions).await
+}
+
+/// refuses changes that would leave nobody able to manage roles
+async fn ensure_role_manager_left(
+	tx: &mut sqlx::Transaction<'_, Postgres>,
+) -> Result<(), AuthError> {
+	// held until the transaction ends, a concurrent change is either committed and seen by the check below or waits for this one
+	sqlx::query!("
+		SELECT pg_advisory_xact_lock($1)
+		;",
+		consts::ROLE_CHANGES_LOCK
+	)
+		.execute(&mut **tx)
+		.await?
+	;
+	let rec = sqlx::query!(r#"
+		SELECT EXISTS(
+			SELECT 1
+			FROM users
+			JOIN level_permissions ON level_permissions.level_id = users.user_level
+			WHERE level_permissions.permission = $1
+		) AS "left!"
+		;"#,
+		Permission::ManageRoles.to_string()
+	)
+		.fetch_one(&mut **tx)
+		.await?
+	;
+
+	rec.left
+		.ok_or(AuthError::Auth(LocalAuthError::Forbidden))
+}
+
+pub async fn get_roles_internal(
+	db_pool: &Pool<Postgres>,
+) -> Result<Vec<UserRole>, AuthError> {
+	let recs = sqlx::query!(r#"
+		SELECT
+			user_levels.level_id,
+			user_levels.level_name,
+			COALESCE(
+				array_agg(level_permissions.permission) FILTER (WHERE level_permissions.permission IS NOT NULL),
+				'{}'
+			) AS "permissions!",
+			(
+				SELECT COUNT(*)
+				FROM users
+				WHERE users.user_level = user_levels.level_id
+			) AS "members!"
+		FROM user_levels
+		LEFT JOIN level_permissions ON level_permissions.level_id = user_levels.level_id
+		GROUP BY user_levels.level_id
+		ORDER BY user_levels.level_id
+		;"#
+	)
+		.fetch_all(db_pool)
+		.await?
+	;
+
+	Ok(recs
+		.into_iter()
+		.map(|rec| UserRole {
+			level_id: rec.level_id,
+			name: rec.level_name,
+			permissions: parse_permissions(rec.permissions),
+			members: rec.members,
+		})
+		.collect()
+	)
+}
+
+/// new roles come without any permissions
+pub async fn create_role_internal(
+	db_pool: &Pool<Postgres>,
+	name: String,
+) -> Result<i16, AuthError> {
+	let name = name.trim().to_lowercase();
+	if name.is_empty() || name.chars().count() > consts::MAX_ROLE_NAME_LENGTH {
+		return Err(AuthError::Auth(LocalAuthError::InvalidUserLevel));
+	}
+
+	let mut tx = db_pool.begin().await?;
+
+	// the next id is taken from the table itself, concurrent creations would pick the same one
+	sqlx::query!("
+		LOCK TABLE user_levels IN SHARE ROW EXCLUSIVE MODE
+		;"
+	)
+		.execute(&mut *tx)
+		.await?
+	;
+	let rec = sqlx::query!(r#"
+		INSERT INTO user_levels
+			(level_id, level_name)
+		SELECT
+			COALESCE(MAX(level_id), 0) + 1, $1
+		FROM user_levels
+		ON CONFLICT (level_name) DO NOTHING
+		RETURNING level_id
+		;"#,
+		name
+	)
+		.fetch_optional(&mut *tx)
+		.await?
+		.ok_or(AuthError::Auth(LocalAuthError::InvalidUserLevel))?
+	;
+	tx.commit().await?;
+
+	println!("created role '{name}'");
+	Ok(rec.level_id)
+}
+
+pub async fn set_role_permissions_internal(
+	db_pool: &Pool<Postgres>,
+	level_id: i16,
+	permissions: &[Permission],
+) -> Result<(), AuthError> {
+	let mut tx = db_pool.begin().await?;
+
+	sqlx::query!("
+		DELETE FROM level_permissions
+		WHERE level_id = $1
+		;",
+		level_id
+	)
+		.execute(&mut *tx)
+		.await?
+	;
+	sqlx::query!("
+		INSERT INTO level_permissions
+			(level_id, permission)
+		SELECT
+			$1, unnest($2::TEXT[])
+		;",
+		level_id,
+		&permission_names(permissions)
+	)
+		.execute(&mut *tx)
+		.await?
+	;
+
+	ensure_role_manager_left(&mut tx).await?;
+	tx.commit().await?;
+	Ok(())
+}
+
+pub async fn set_user_role_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	level_id: i16,
+) -> Result<(), AuthError> {
+	let mut tx = db_pool.begin().await?;
+
+	let res = sqlx::query!("
+		UPDATE users
+		SET user_level = $1
+		WHERE id = $2
+		;",
+		level_id,
+		user_id
+	)
+		.execute(&mut *tx)
+		.await?
+	;
+	(res.rows_affected() > 0).ok_or(AuthError::Auth(LocalAuthError::Invalid))?;
+
+	ensure_role_manager_left(&mut tx).await?;
+	tx.commit().await?;
+	Ok(())
+}
+
+/// the role a user currently has
+pub async fn get_user_role_id(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+) -> Result<i16, AuthError> {
+	let rec = sqlx::query!("
+		SELECT user_level
+		FROM users
+		WHERE id = $1
+		;",
+		user_id
+	)
+		.fetch_optional(db_pool)
+		.await?
+		.ok_or(AuthError::Auth(LocalAuthError::Invalid))?
+	;
+	Ok(rec.user_level)
+}
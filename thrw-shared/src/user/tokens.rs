@@ -0,0 +1,202 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use super::auth::{AuthError, LocalAuthError};
+use crate::app::state::server::SharedAppState;
+use crate::user::api::{ApiScope, ApiToken, NewApiToken};
+
+mod consts {
+	/// makes leaked tokens easy to find in logs and repositories
+	pub const TOKEN_PREFIX: &str = "thrw_";
+	pub const TOKEN_BYTES: usize = 32;
+	pub const MAX_NAME_LENGTH: usize = 64;
+	pub const MAX_EXPIRY_DAYS: i64 = 365 * 5;
+}
+
+struct TokenRow {
+	id: Uuid,
+	token_name: String,
+	scopes: Vec<String>,
+	expires_at: Option<chrono::DateTime<chrono::Utc>>,
+	last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+	created_at: chrono::DateTime<chrono::Utc>,
+}
+impl From<TokenRow> for ApiToken {
+	fn from(row: TokenRow) -> Self {
+		Self {
+			id: row.id,
+			name: row.token_name,
+			scopes: row.scopes
+				.iter()
+				.filter_map(|scope| scope.parse().ok())
+				.collect(),
+			expires_at: row.expires_at,
+			last_used_at: row.last_used_at,
+			created_at: row.created_at,
+		}
+	}
+}
+
+/// the tokens are random enough for a plain hash, no salt or slow hash needed
+fn hash_token(token: &str) -> String {
+	format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn generate_token() -> String {
+	let mut bytes = [0u8; consts::TOKEN_BYTES];
+	OsRng.fill_bytes(&mut bytes);
+	let secret: String = bytes
+		.iter()
+		.map(|byte| format!("{byte:02x}"))
+		.collect()
+	;
+	format!("{}{secret}", consts::TOKEN_PREFIX)
+}
+
+fn invalid(reason: &str) -> AuthError {
+	AuthError::Auth(LocalAuthError::InvalidApiToken(reason.to_string()))
+}
+
+/// the token itself is only returned here, it can't be looked up later
+pub async fn create_api_token_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	name: String,
+	scopes: Vec<ApiScope>,
+	expires_in_days: Option<i64>,
+) -> Result<NewApiToken, AuthError> {
+	let name = name.trim().to_string();
+	if name.is_empty() || name.chars().count() > consts::MAX_NAME_LENGTH {
+		return Err(invalid(&format!("the name has to be 1 to {} characters long", consts::MAX_NAME_LENGTH)));
+	}
+	if scopes.is_empty() {
+		return Err(invalid("pick at least one scope"));
+	}
+	let expires_at = match expires_in_days {
+		Some(days) if !(1..=consts::MAX_EXPIRY_DAYS).contains(&days) => {
+			return Err(invalid(&format!("tokens expire after 1 to {} days", consts::MAX_EXPIRY_DAYS)));
+		},
+		Some(days) => Some(chrono::Utc::now() + chrono::TimeDelta::days(days)),
+		None => None,
+	};
+	let mut scope_names: Vec<String> = scopes
+		.iter()
+		.map(ToString::to_string)
+		.collect()
+	;
+	scope_names.sort();
+	scope_names.dedup();
+
+	let secret = generate_token();
+	let row = sqlx::query_as!(
+		TokenRow,
+		"INSERT INTO api_tokens
+			(user_id, token_name, token_hash, scopes, expires_at)
+		VALUES
+			($1, $2, $3, $4, $5)
+		ON CONFLICT (user_id, token_name) DO NOTHING
+		RETURNING id, token_name, scopes, expires_at, last_used_at, created_at
+		;",
+		user_id,
+		name,
+		hash_token(&secret),
+		&scope_names,
+		expires_at
+	)
+		.fetch_optional(db_pool)
+		.await?
+		.ok_or(invalid("a token of that name exists already"))?
+	;
+	println!("user {user_id} created api token '{}'", row.token_name);
+
+	Ok(NewApiToken {
+		token: row.into(),
+		secret,
+	})
+}
+
+pub async fn get_api_tokens_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+) -> Result<Vec<ApiToken>, AuthError> {
+	let rows = sqlx::query_as!(
+		TokenRow,
+		"SELECT id, token_name, scopes, expires_at, last_used_at, created_at
+		FROM api_tokens
+		WHERE user_id = $1
+		ORDER BY created_at DESC
+		;",
+		user_id
+	)
+		.fetch_all(db_pool)
+		.await?
+	;
+
+	Ok(rows
+		.into_iter()
+		.map(Into::into)
+		.collect()
+	)
+}
+
+pub async fn revoke_api_token_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	token_id: Uuid,
+) -> Result<(), AuthError> {
+	let res = sqlx::query!("
+		DELETE FROM api_tokens
+		WHERE user_id = $1
+		  AND id = $2
+		;",
+		user_id,
+		token_id
+	)
+		.execute(db_pool)
+		.await?
+	;
+
+	(res.rows_affected() > 0)
+		.ok_or(AuthError::Auth(LocalAuthError::Invalid))
+}
+
+/// whether a token accepted earlier is still neither revoked nor expired, without marking it as used
+pub async fn check_api_token_id_with_state(token_id: Uuid, state: SharedAppState) -> Result<i32, AuthError> {
+	let rec = sqlx::query!("
+		SELECT user_id
+		FROM api_tokens
+		WHERE id = $1
+		  AND (expires_at IS NULL OR expires_at > now())
+		;",
+		token_id
+	)
+		.fetch_optional(&state.db_pool)
+		.await?
+		.ok_or(AuthError::Auth(LocalAuthError::NotAuthenticated))?
+	;
+	Ok(rec.user_id)
+}
+
+/// the user and id of a valid token that has the scope, marking it as used
+pub async fn check_api_token_with_state(token: &str, scope: ApiScope, state: SharedAppState) -> Result<(i32, Uuid), AuthError> {
+	let rec = sqlx::query!("
+		UPDATE api_tokens
+		SET last_used_at = now()
+		WHERE token_hash = $1
+		  AND (expires_at IS NULL OR expires_at > now())
+		RETURNING id, user_id, scopes
+		;",
+		hash_token(token)
+	)
+		.fetch_optional(&state.db_pool)
+		.await?
+		.ok_or(AuthError::Auth(LocalAuthError::NotAuthenticated))?
+	;
+
+	if !rec.scopes.contains(&scope.to_string()) {
+		return Err(AuthError::Auth(LocalAuthError::MissingScope));
+	}
+	Ok((rec.user_id, rec.id))
+}
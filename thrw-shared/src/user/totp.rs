@@ -0,0 +1,455 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use sqlx::{Pool, Postgres};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+use super::auth::{AuthError, LocalAuthError};
+use crate::user::api::{TotpEnrollment, TotpStatus};
+use crate::util::{hash, verify_hash};
+
+mod consts {
+	pub const ISSUER: &str = "thrw";
+	pub const DIGITS: usize = 6;
+	pub const STEP: u64 = 30;
+	/// codes of the neighbouring steps are accepted as well, clocks drift
+	pub const SKEW: u64 = 1;
+
+	pub const RECOVERY_CODE_COUNT: usize = 10;
+	/// lowercase letters and digits without the easily confused ones
+	pub const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+	pub const RECOVERY_CODE_LENGTH: usize = 10;
+
+	pub const CHALLENGE_MINUTES: i64 = 5;
+	pub const MAX_CHALLENGE_ATTEMPTS: i16 = 5;
+}
+
+fn totp_error<T: std::fmt::Debug>(err: T) -> AuthError {
+	AuthError::Auth(LocalAuthError::Totp(format!("{err:?}")))
+}
+
+fn build_totp(secret: &str, account_name: String) -> Result<TOTP, AuthError> {
+	let secret = Secret::Encoded(secret.to_string())
+		.to_bytes()
+		.map_err(totp_error)?
+	;
+	TOTP::new(
+		Algorithm::SHA1,
+		consts::DIGITS,
+		consts::SKEW as u8,
+		consts::STEP,
+		secret,
+		Some(consts::ISSUER.to_string()),
+		account_name,
+	)
+		.map_err(totp_error)
+}
+
+/// the time step the code was generated for, if it is valid right now
+fn matching_step(totp: &TOTP, code: &str) -> Option<u64> {
+	let now = std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.ok()?
+		.as_secs()
+	;
+	let current = now / consts::STEP;
+	(current.saturating_sub(consts::SKEW)..=current + consts::SKEW)
+		.find(|step| totp.generate(step * consts::STEP) == code)
+}
+
+/// recovery codes are compared without dashes, spaces or case
+fn normalize_code(code: &str) -> String {
+	code
+		.chars()
+		.filter(char::is_ascii_alphanumeric)
+		.map(|c| c.to_ascii_lowercase())
+		.collect()
+}
+
+fn generate_recovery_code() -> String {
+	let mut bytes = [0u8; consts::RECOVERY_CODE_LENGTH];
+	OsRng.fill_bytes(&mut bytes);
+	let code: String = bytes
+		.iter()
+		.map(|byte| consts::RECOVERY_ALPHABET[*byte as usize % consts::RECOVERY_ALPHABET.len()] as char)
+		.collect()
+	;
+	let (first, second) = code.split_at(consts::RECOVERY_CODE_LENGTH / 2);
+	format!("{first}-{second}")
+}
+
+pub async fn is_totp_enabled(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+) -> Result<bool, AuthError> {
+	let rec = sqlx::query!("
+		SELECT confirmed
+		FROM user_totp
+		WHERE id = $1
+		;",
+		user_id
+	)
+		.fetch_optional(db_pool)
+		.await?
+	;
+	Ok(rec.is_some_and(|rec| rec.confirmed))
+}
+
+pub async fn get_totp_status_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+) -> Result<TotpStatus, AuthError> {
+	let rec = sqlx::query!(r#"
+		SELECT COUNT(*) AS "count!"
+		FROM user_recovery_codes
+		WHERE user_id = $1
+		  AND used_at IS NULL
+		;"#,
+		user_id
+	)
+		.fetch_one(db_pool)
+		.await?
+	;
+
+	Ok(TotpStatus {
+		enabled: is_totp_enabled(db_pool, user_id).await?,
+		recovery_codes_left: rec.count,
+	})
+}
+
+/// a new secret, replacing any enrollment that was not confirmed yet
+pub async fn begin_totp_enrollment_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+) -> Result<TotpEnrollment, AuthError> {
+	if is_totp_enabled(db_pool, user_id).await? {
+		return Err(AuthError::Auth(LocalAuthError::TotpEnabled));
+	}
+
+	let secret = Secret::generate_secret().to_encoded().to_string();
+	let email = sqlx::query!("
+		SELECT email
+		FROM users
+		WHERE id = $1
+		;",
+		user_id
+	)
+		.fetch_one(db_pool)
+		.await?
+		.email
+	;
+	let totp = build_totp(&secret, email)?;
+
+	sqlx::query!("
+		INSERT INTO user_totp
+			(id, secret)
+		VALUES
+			($1, $2)
+		ON CONFLICT (id) DO UPDATE
+			SET secret = EXCLUDED.secret, last_step = NULL, created_at = now()
+			WHERE user_totp.confirmed = false
+		;",
+		user_id,
+		secret
+	)
+		.execute(db_pool)
+		.await?
+	;
+
+	Ok(TotpEnrollment {
+		otpauth_uri: totp.get_url(),
+		secret,
+	})
+}
+
+/// accept a totp code once, `confirmed` tells whether the enrollment has to be confirmed already
+async fn accept_totp_code(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	code: &str,
+	confirmed: bool,
+) -> Result<bool, AuthError> {
+	let rec = sqlx::query!("
+		SELECT user_totp.secret, users.email
+		FROM user_totp
+		JOIN users ON users.id = user_totp.id
+		WHERE user_totp.id = $1
+		  AND user_totp.confirmed = $2
+		;",
+		user_id,
+		confirmed
+	)
+		.fetch_optional(db_pool)
+		.await?
+		.ok_or(AuthError::Auth(LocalAuthError::TotpNotEnrolled))?
+	;
+	let totp = build_totp(&rec.secret, rec.email)?;
+	let Some(step) = matching_step(&totp, code) else {
+		return Ok(false);
+	};
+
+	// the step only moves forward, a code seen before is rejected
+	let res = sqlx::query!("
+		UPDATE user_totp
+		SET last_step = $2
+		WHERE id = $1
+		  AND (last_step IS NULL OR last_step < $2)
+		;",
+		user_id,
+		step as i64
+	)
+		.execute(db_pool)
+		.await?
+	;
+	Ok(res.rows_affected() > 0)
+}
+
+/// replaces all recovery codes of the user, the codes are only ever returned here
+pub async fn generate_recovery_codes(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+) -> Result<Vec<String>, AuthError> {
+	let codes: Vec<String> = (0..consts::RECOVERY_CODE_COUNT)
+		.map(|_| generate_recovery_code())
+		.collect()
+	;
+	let hashes: Vec<String> = codes
+		.iter()
+		.map(|code| hash(normalize_code(code)))
+		.collect()
+	;
+
+	let mut tx = db_pool.begin().await?;
+	sqlx::query!("
+		DELETE FROM user_recovery_codes
+		WHERE user_id = $1
+		;",
+		user_id
+	)
+		.execute(&mut *tx)
+		.await?
+	;
+	sqlx::query!("
+		INSERT INTO user_recovery_codes
+			(user_id, code_hash)
+		SELECT $1, code_hash
+		FROM UNNEST($2::TEXT[]) AS code_hash
+		;",
+		user_id,
+		&hashes
+	)
+		.execute(&mut *tx)
+		.await?
+	;
+	tx.commit().await?;
+
+	Ok(codes)
+}
+
+pub async fn confirm_totp_enrollment_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	code: &str,
+) -> Result<Vec<String>, AuthError> {
+	if !accept_totp_code(db_pool, user_id, code.trim(), false).await? {
+		return Err(AuthError::Auth(LocalAuthError::WrongCode));
+	}
+
+	sqlx::query!("
+		UPDATE user_totp
+		SET confirmed = true
+		WHERE id = $1
+		;",
+		user_id
+	)
+		.execute(db_pool)
+		.await?
+	;
+	println!("user {user_id} enabled two-factor authentication");
+
+	generate_recovery_codes(db_pool, user_id).await
+}
+
+async fn use_recovery_code(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	code: &str,
+) -> Result<bool, AuthError> {
+	let code = normalize_code(code);
+	let recs = sqlx::query!("
+		SELECT id, code_hash
+		FROM user_recovery_codes
+		WHERE user_id = $1
+		  AND used_at IS NULL
+		;",
+		user_id
+	)
+		.fetch_all(db_pool)
+		.await?
+	;
+	let Some(rec) = recs
+		.into_iter()
+		.find(|rec| verify_hash(&code, &rec.code_hash).unwrap_or(false))
+	else {
+		return Ok(false);
+	};
+
+	let res = sqlx::query!("
+		UPDATE user_recovery_codes
+		SET used_at = now()
+		WHERE id = $1
+		  AND used_at IS NULL
+		;",
+		rec.id
+	)
+		.execute(db_pool)
+		.await?
+	;
+	if res.rows_affected() > 0 {
+		println!("user {user_id} used a recovery code");
+	}
+	Ok(res.rows_affected() > 0)
+}
+
+/// a totp code or, failing that, an unused recovery code
+pub async fn verify_second_factor(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+	code: &str,
+) -> Result<(), AuthError> {
+	let code = code.trim();
+	let is_totp = code.len() == consts::DIGITS && code.chars().all(|c| c.is_ascii_digit());
+	let accepted = match is_totp {
+		true => accept_totp_code(db_pool, user_id, code, true).await?,
+		false => use_recovery_code(db_pool, user_id, code).await?,
+	};
+	accepted.ok_or(AuthError::Auth(LocalAuthError::WrongCode))
+}
+
+/// removes the secret and recovery codes, the user logs in with the password alone afterwards
+pub async fn disable_totp_internal(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+) -> Result<(), AuthError> {
+	let mut tx = db_pool.begin().await?;
+	sqlx::query!("
+		DELETE FROM user_totp
+		WHERE id = $1
+		;",
+		user_id
+	)
+		.execute(&mut *tx)
+		.await?
+	;
+	sqlx::query!("
+		DELETE FROM user_recovery_codes
+		WHERE user_id = $1
+		;",
+		user_id
+	)
+		.execute(&mut *tx)
+		.await?
+	;
+	tx.commit().await?;
+
+	Ok(())
+}
+
+/// a login that passed the password and has to be finished with the second factor
+pub async fn create_login_challenge(
+	db_pool: &Pool<Postgres>,
+	user_id: i32,
+) -> Result<Uuid, AuthError> {
+	sqlx::query!("
+		DELETE FROM login_challenges
+		WHERE expires_at < now()
+		;"
+	)
+		.execute(db_pool)
+		.await?
+	;
+
+	let rec = sqlx::query!("
+		INSERT INTO login_challenges
+			(user_id, expires_at)
+		VALUES
+			($1, $2)
+		RETURNING id
+		;",
+		user_id,
+		chrono::Utc::now() + chrono::TimeDelta::minutes(consts::CHALLENGE_MINUTES)
+	)
+		.fetch_one(db_pool)
+		.await?
+	;
+
+	Ok(rec.id)
+}
+
+/// the user an unexpired challenge was created for
+pub async fn get_challenge_user(
+	db_pool: &Pool<Postgres>,
+	challenge: Uuid,
+) -> Result<i32, AuthError> {
+	sqlx::query!("
+		SELECT user_id
+		FROM login_challenges
+		WHERE id = $1
+		  AND expires_at > now()
+		;",
+		challenge
+	)
+		.fetch_optional(db_pool)
+		.await?
+		.map(|rec| rec.user_id)
+		.ok_or(AuthError::Auth(LocalAuthError::Expired))
+}
+
+/// the user the challenge was created for, once the code is accepted
+pub async fn pass_login_challenge(
+	db_pool: &Pool<Postgres>,
+	challenge: Uuid,
+	code: &str,
+) -> Result<i32, AuthError> {
+	// counted before checking, parallel guesses can't get around the limit
+	let rec = sqlx::query!("
+		UPDATE login_challenges
+		SET attempts = attempts + 1
+		WHERE id = $1
+		  AND expires_at > now()
+		RETURNING user_id, attempts
+		;",
+		challenge
+	)
+		.fetch_optional(db_pool)
+		.await?
+		.ok_or(AuthError::Auth(LocalAuthError::Expired))?
+	;
+
+	if rec.attempts > consts::MAX_CHALLENGE_ATTEMPTS {
+		sqlx::query!("
+			DELETE FROM login_challenges
+			WHERE id = $1
+			;",
+			challenge
+		)
+			.execute(db_pool)
+			.await?
+		;
+		println!("too many second factor attempts for user {}, login has to be restarted", rec.user_id);
+		return Err(AuthError::Auth(LocalAuthError::TooManyAttempts));
+	}
+
+	verify_second_factor(db_pool, rec.user_id, code).await?;
+
+	sqlx::query!("
+		DELETE FROM login_challenges
+		WHERE id = $1
+		;",
+		challenge
+	)
+		.execute(db_pool)
+		.await?
+	;
+
+	Ok(rec.user_id)
+}
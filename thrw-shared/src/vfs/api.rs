@@ -17,7 +17,7 @@ pub async fn get_vfs_nodes(
 	at: VfsTarget,
 	args: Option<VfsGetNodeArgs>,
 ) -> Result<Vec<PubVfsNode>, ServerFnError> {
-	let (id, _) = require_auth().await?;
+	let (user_id, _) = require_scope(ApiScope::Files).await?;
 	let db = extract_db()?;
 	
 	// println!("getting nodes at {at:?}");
@@ -40,6 +40,9 @@ pub async fn get_vfs_nodes(
 	};
 
 	let show_hidden = args.map(|args| args.show_hidden).unwrap_or(false);
+	if show_hidden {
+		check_permission(&db, user_id, Permission::ViewAllFiles).await?;
+	}
 
 	let vals = sqlx::query!("
 		SELECT * FROM vfs_nodes
@@ -52,7 +55,7 @@ pub async fn get_vfs_nodes(
 		.fetch_all(&db)
 		.await?
 	;
-	futures::future::join_all(vals
+	let mut nodes: Vec<PubVfsNode> = futures::future::join_all(vals
 		.iter()
 		.map(async |rec| get_pub_vfs_node(&db, rec.id)
 			.await
@@ -60,7 +63,19 @@ pub async fn get_vfs_nodes(
 		))
 		.await
 		.into_iter()
-		.collect()
+		.collect::<Result<_, _>>()?
+	;
+
+	let node_ids: Vec<uuid::Uuid> = nodes.iter().map(|node| node.id).collect();
+	let states = crate::playback::util::get_playback_states_for(&db, user_id, &node_ids)
+		.await
+		.map_err(make_server_err)?
+	;
+	for node in nodes.iter_mut() {
+		node.playback = states.iter().find(|state| state.node_id == node.id).cloned();
+	}
+
+	Ok(nodes)
 }
 
 #[server]
@@ -68,7 +83,7 @@ pub async fn create_vfs_node(
 	at: VfsTarget,
 	name: String,
 ) -> Result<PubVfsNode, ServerFnError> {
-	let (id, _) = require_auth().await?;
+	let _ = require_scoped_permission(ApiScope::Files, Permission::Upload).await?;
 	let db = extract_db()?;
 
 	let parent = match at {
@@ -93,4 +108,16 @@ pub async fn create_vfs_node(
 	get_pub_vfs_node(&db, id)
 		.await
 		.map_err(make_server_err)
+}
+
+#[server]
+pub async fn get_media_extras(
+	node: uuid::Uuid,
+) -> Result<PubMediaExtras, ServerFnError> {
+	let _ = require_scope(ApiScope::Files).await?;
+	let db = extract_db()?;
+
+	get_media_extras_internal(&db, node)
+		.await
+		.map_err(make_server_err)
 }
\ No newline at end of file
@@ -49,6 +49,62 @@ pub mod shared {
 		Image,
 		Text,
 	}
+	impl From<String> for PubVfsNodeType {
+		fn from(value: String) -> Self {
+			match value.as_str() {
+				"video" => Self::Video,
+				"audio" => Self::Audio,
+				"image" => Self::Image,
+				"text" => Self::Text,
+				_ => Self::Text,
+			}
+		}
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+	pub enum PubTrackKind {
+		Subtitles,
+		Captions,
+	}
+	impl ToString for PubTrackKind {
+		fn to_string(&self) -> String {
+			match self {
+				PubTrackKind::Subtitles => "subtitles",
+				PubTrackKind::Captions => "captions",
+			}.to_string()
+		}
+	}
+	impl From<String> for PubTrackKind {
+		fn from(value: String) -> Self {
+			match value.as_str() {
+				"captions" => Self::Captions,
+				_ => Self::Subtitles,
+			}
+		}
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubVfsTrack {
+		pub node_id: uuid::Uuid,
+		pub src: String,
+		pub kind: PubTrackKind,
+		pub lang: Option<String>,
+		pub label: String,
+	}
+
+	#[derive(Debug, Clone, Serialize, Deserialize)]
+	pub struct PubVfsChapter {
+		pub start: f64,
+		pub end: Option<f64>,
+		pub title: Option<String>,
+	}
+
+	/// text tracks and chapters belonging to a media node
+	#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+	pub struct PubMediaExtras {
+		pub tracks: Vec<PubVfsTrack>,
+		pub chapters: Vec<PubVfsChapter>,
+	}
 
 	#[derive(Debug, Clone, Serialize, Deserialize)]
 	pub struct PubVfsNode {
@@ -57,6 +113,10 @@ pub mod shared {
 		pub path: PathBuf,
 		pub node_type: PubVfsNodeType,
 		pub thumbnail: Option<String>,
+		pub file_path: Option<String>,
+		pub duration: Option<f64>,
+		/// playback state of the requesting user, if any
+		pub playback: Option<crate::playback::shared::PlaybackState>,
 	}
 }
 
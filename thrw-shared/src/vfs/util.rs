@@ -41,6 +41,11 @@ fn get_site_folder() -> PathBuf {
 	}
 }
 
+/// resolve a stored `vfs_files.file_path` to the file on disk
+pub fn get_abs_file_path(file_path: &str) -> PathBuf {
+	get_site_folder().join(file_path.trim_start_matches('/'))
+}
+
 pub fn get_vfs_dir() -> PathBuf {
 	PathBuf::from(consts::VFS_DIR_PATH)
 }
@@ -58,10 +63,7 @@ impl ToString for VFSFileType {
 				ffo.streams.iter()
 					.find(|stream| matches!(stream.codec_type, crate::media::util::MediaCodecType::Video))
 					.map(|stream| stream.codec_type.to_string())
-					.unwrap_or(ffo.streams.first()
-						.map(|stream| stream.codec_type.to_string())
-						.unwrap_or("audio".to_string())
-					)
+					.unwrap_or("audio".to_string())
 			},
 			VFSFileType::Image(_) => "image".to_string(),
 			VFSFileType::Text => "text".to_string(),
@@ -316,19 +318,55 @@ pub async fn get_vfs_node_data(
 		.map_err(VFSError::Sql)
 }
 
+pub struct VfsFileInfo {
+	pub file_type: String,
+	pub file_path: String,
+	pub duration: Option<f64>,
+}
+
+pub async fn get_vfs_file_info(
+	db_pool: &Pool<Postgres>,
+	file_id: uuid::Uuid,
+) -> Result<VfsFileInfo, VFSError> {
+	sqlx::query_as!(
+		VfsFileInfo,
+		"SELECT
+			file.file_type,
+			file.file_path,
+			COALESCE(video.duration, audio.duration) AS duration
+		FROM vfs_files			AS file
+		LEFT JOIN video_files	AS video	ON video.id = file.id
+		LEFT JOIN audio_files	AS audio	ON audio.id = file.id
+		WHERE file.id = $1
+		;",
+		file_id
+	)
+		.fetch_one(db_pool)
+		.await
+		.map_err(VFSError::Sql)
+}
+
 pub async fn get_pub_vfs_node(
 	db_pool: &Pool<Postgres>,
 	id: uuid::Uuid,
 ) -> Result<PubVfsNode, VFSError> {
 	let node_data = get_vfs_node_data(db_pool, id).await?;
+	let file_info = match node_data.vfs_file {
+		Some(file_id) => Some(get_vfs_file_info(db_pool, file_id).await?),
+		None => None,
+	};
+
 	Ok(PubVfsNode {
 		id: node_data.id,
 		name: node_data.node_name.clone(),
 		path: get_vfs_path_to(db_pool, node_data.id).await?,
-		node_type: node_data.vfs_file
-			.map(|file_id| PubVfsNodeType::Audio)
+		node_type: file_info.as_ref()
+			.map(|info| info.file_type.clone().into())
 			.unwrap_or(PubVfsNodeType::Folder),
-		thumbnail: get_thumbnail(db_pool, id).await?.map(|th| th.1)
+		thumbnail: get_thumbnail(db_pool, id).await?.map(|th| th.1),
+		file_path: file_info.as_ref().map(|info| info.file_path.clone()),
+		duration: file_info.and_then(|info| info.duration),
+		playback: None,
 	})
 }
 
@@ -640,6 +678,185 @@ pub async fn commit_file_to_vfs(
 	Ok((file_id, node_id))
 }
 
+/// add a node for an already committed file, the file itself is shared rather than copied
+pub async fn link_vfs_file(
+	db_pool: &Pool<Postgres>,
+	file_id: uuid::Uuid,
+	name: String,
+	vfs_target: Option<VfsTarget>,
+) -> Result<uuid::Uuid, VFSError> {
+	let parent = match vfs_target {
+		Some(VfsTarget::Node(node)) => node,
+		Some(VfsTarget::Path(vfs_path)) => ensure_vfs_path(db_pool, vfs_path).await?,
+		None => ensure_vfs_root(db_pool).await?,
+	};
+
+	let node_id = create_vfs_node_internal(
+		db_pool,
+		VfsNodeCreateArgs {
+			name,
+			hide: false,
+		},
+		Some(parent)
+	).await?;
+
+	set_vfs_file_to_node(db_pool, file_id, node_id).await?;
+	Ok(node_id)
+}
+
+#[derive(Debug, Clone)]
+pub struct VfsTrackData {
+	pub kind: PubTrackKind,
+	pub lang: Option<String>,
+	pub file: FileRef,
+}
+
+/// commit a webvtt track as a hidden child of the media node, returning the track node id
+pub async fn commit_track_to_vfs(
+	db_pool: &Pool<Postgres>,
+	media_file_id: uuid::Uuid,
+	media_node_id: uuid::Uuid,
+	media_name: &str,
+	track: VfsTrackData,
+) -> Result<uuid::Uuid, VFSError> {
+	let label = match &track.lang {
+		Some(lang) => format!("{lang} ({})", track.kind.to_string()),
+		None => track.kind.to_string(),
+	};
+	let file_data = VfsFileData {
+		name: format!("{media_name} [{label}]"),
+		file: track.file,
+		file_type: VFSFileType::Text,
+		hide: true,
+	};
+
+	let (_, track_node) = commit_file_to_vfs(
+		file_data,
+		db_pool,
+		Some(VfsTarget::Node(media_node_id))
+	).await?;
+
+	sqlx::query!("
+		INSERT INTO vfs_tracks
+			(file_id, track_node, kind, lang, label)
+		VALUES
+			($1, $2, $3, $4, $5)
+		;",
+		media_file_id,
+		track_node,
+		track.kind.to_string(),
+		track.lang,
+		label
+	)
+		.execute(db_pool)
+		.await
+		.map_err(VFSError::Sql)?
+	;
+
+	Ok(track_node)
+}
+
+pub async fn set_media_chapters(
+	db_pool: &Pool<Postgres>,
+	file_id: uuid::Uuid,
+	chapters: &[PubVfsChapter],
+) -> Result<(), VFSError> {
+	let mut tx = db_pool.begin()
+		.await
+		.map_err(VFSError::Sql)?;
+
+	sqlx::query!("
+		DELETE FROM vfs_chapters
+		WHERE file_id = $1
+		;",
+		file_id
+	)
+		.execute(&mut *tx)
+		.await
+		.map_err(VFSError::Sql)?
+	;
+
+	for (index, chapter) in chapters.iter().enumerate() {
+		sqlx::query!("
+			INSERT INTO vfs_chapters
+				(file_id, chapter_index, start_time, end_time, title)
+			VALUES
+				($1, $2, $3, $4, $5)
+			;",
+			file_id,
+			index as i32,
+			chapter.start,
+			chapter.end,
+			chapter.title
+		)
+			.execute(&mut *tx)
+			.await
+			.map_err(VFSError::Sql)?
+		;
+	}
+
+	tx.commit()
+		.await
+		.map_err(VFSError::Sql)
+}
+
+pub async fn get_media_extras_internal(
+	db_pool: &Pool<Postgres>,
+	node_id: uuid::Uuid,
+) -> Result<PubMediaExtras, VFSError> {
+	let Some(file_id) = get_vfs_node_data(db_pool, node_id).await?.vfs_file else {
+		return Ok(PubMediaExtras::default());
+	};
+
+	let tracks = sqlx::query!("
+		SELECT
+			track.track_node,
+			track.kind,
+			track.lang,
+			track.label,
+			file.file_path
+		FROM vfs_tracks			AS track
+		JOIN vfs_nodes			AS node	ON node.id = track.track_node
+		JOIN vfs_files			AS file	ON file.id = node.vfs_file
+		WHERE track.file_id = $1
+		ORDER BY track.label
+		;",
+		file_id
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(VFSError::Sql)?
+		.into_iter()
+		.map(|rec| PubVfsTrack {
+			node_id: rec.track_node,
+			src: rec.file_path,
+			kind: rec.kind.into(),
+			lang: rec.lang,
+			label: rec.label,
+		})
+		.collect()
+	;
+
+	let chapters = sqlx::query_as!(
+		PubVfsChapter,
+		r#"SELECT
+			start_time	AS start,
+			end_time	AS "end",
+			title
+		FROM vfs_chapters
+		WHERE file_id = $1
+		ORDER BY chapter_index
+		;"#,
+		file_id
+	)
+		.fetch_all(db_pool)
+		.await
+		.map_err(VFSError::Sql)?
+	;
+
+	Ok(PubMediaExtras { tracks, chapters })
+}
+
 async fn mark_vfs_node_updated(
 	db_pool: &Pool<Postgres>,
 	node_id: uuid::Uuid,
@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
 
+use crate::downloader::shared::PubDownloadProgress;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ThrwSocketMessage {
 	Intoduce(i32),
 	String(String),
+	DownloadProgress(PubDownloadProgress),
 }
\ No newline at end of file
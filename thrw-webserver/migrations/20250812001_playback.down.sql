@@ -0,0 +1 @@
+DROP TABLE playback_states;
\ No newline at end of file
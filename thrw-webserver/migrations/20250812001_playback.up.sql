@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS playback_states(
+	user_id		INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE
+,	node_id		UUID NOT NULL REFERENCES vfs_nodes(id) ON DELETE CASCADE
+,	position	FLOAT NOT NULL DEFAULT 0
+,	completed	BOOLEAN NOT NULL DEFAULT false
+,	play_count	INTEGER NOT NULL DEFAULT 0
+,	last_played	TIMESTAMPTZ NOT NULL DEFAULT now()
+
+,	PRIMARY KEY (user_id, node_id)
+);
+
+CREATE INDEX IF NOT EXISTS playback_states_last_played
+	ON playback_states (user_id, last_played DESC)
+;
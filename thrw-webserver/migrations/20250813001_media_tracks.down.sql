@@ -0,0 +1,2 @@
+DROP TABLE vfs_chapters;
+DROP TABLE vfs_tracks;
\ No newline at end of file
@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS vfs_tracks(
+	id			UUID PRIMARY KEY DEFAULT gen_random_uuid()
+,	file_id		UUID NOT NULL REFERENCES vfs_files(id) ON DELETE CASCADE
+,	track_node	UUID NOT NULL REFERENCES vfs_nodes(id) ON DELETE CASCADE
+,	kind		TEXT NOT NULL CHECK (kind IN ('subtitles', 'captions'))
+,	lang		TEXT
+,	label		TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS vfs_chapters(
+	file_id			UUID NOT NULL REFERENCES vfs_files(id) ON DELETE CASCADE
+,	chapter_index	INTEGER NOT NULL
+,	start_time		FLOAT NOT NULL
+,	end_time		FLOAT
+,	title			TEXT
+
+,	PRIMARY KEY (file_id, chapter_index)
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS media_analysis(
+	id				UUID PRIMARY KEY REFERENCES vfs_files(id) ON DELETE CASCADE
+,	peaks			BYTEA NOT NULL
+,	integrated_lufs	FLOAT
+,	loudness_range	FLOAT
+,	true_peak		FLOAT
+,	replay_gain		FLOAT
+,	analyzed_at		TIMESTAMPTZ NOT NULL DEFAULT now()
+);
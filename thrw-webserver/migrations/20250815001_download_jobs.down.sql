@@ -0,0 +1 @@
+DROP TABLE download_jobs;
\ No newline at end of file
@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS download_jobs(
+	id				UUID PRIMARY KEY DEFAULT gen_random_uuid()
+,	user_id			INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE
+,	url				TEXT NOT NULL
+,	audio_only		BOOLEAN NOT NULL DEFAULT false
+,	target_node		UUID REFERENCES vfs_nodes(id) ON DELETE SET NULL
+,	target_path		TEXT
+,	state			TEXT NOT NULL DEFAULT 'queued' CHECK (state IN ('queued', 'running', 'completed', 'failed'))
+,	error			TEXT
+,	media_file		UUID REFERENCES vfs_files(id) ON DELETE SET NULL
+,	created_at		TIMESTAMPTZ NOT NULL DEFAULT now()
+,	started_at		TIMESTAMPTZ
+,	finished_at		TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS download_jobs_state
+	ON download_jobs (state, created_at)
+;
+
+CREATE INDEX IF NOT EXISTS download_jobs_user
+	ON download_jobs (user_id, created_at DESC)
+;
\ No newline at end of file
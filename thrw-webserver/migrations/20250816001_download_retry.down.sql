@@ -0,0 +1,8 @@
+DELETE FROM download_jobs WHERE state = 'cancelled';
+
+ALTER TABLE download_jobs
+	DROP CONSTRAINT IF EXISTS download_jobs_state_check
+,	ADD CONSTRAINT download_jobs_state_check CHECK (state IN ('queued', 'running', 'completed', 'failed'))
+,	DROP COLUMN IF EXISTS retry_count
+,	DROP COLUMN IF EXISTS next_attempt_at
+;
\ No newline at end of file
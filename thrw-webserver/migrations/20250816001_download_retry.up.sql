@@ -0,0 +1,6 @@
+ALTER TABLE download_jobs
+	DROP CONSTRAINT IF EXISTS download_jobs_state_check
+,	ADD CONSTRAINT download_jobs_state_check CHECK (state IN ('queued', 'running', 'completed', 'failed', 'cancelled'))
+,	ADD COLUMN IF NOT EXISTS retry_count		INTEGER NOT NULL DEFAULT 0
+,	ADD COLUMN IF NOT EXISTS next_attempt_at	TIMESTAMPTZ
+;
@@ -0,0 +1,11 @@
+DELETE FROM download_jobs WHERE parent_job IS NOT NULL OR state = 'expanded';
+
+DROP INDEX IF EXISTS download_jobs_parent;
+
+ALTER TABLE download_jobs
+	DROP CONSTRAINT IF EXISTS download_jobs_state_check
+,	ADD CONSTRAINT download_jobs_state_check CHECK (state IN ('queued', 'running', 'completed', 'failed', 'cancelled'))
+,	DROP COLUMN IF EXISTS parent_job
+,	DROP COLUMN IF EXISTS playlist_index
+,	DROP COLUMN IF EXISTS title
+;
\ No newline at end of file
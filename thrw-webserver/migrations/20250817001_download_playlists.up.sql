@@ -0,0 +1,11 @@
+ALTER TABLE download_jobs
+	DROP CONSTRAINT IF EXISTS download_jobs_state_check
+,	ADD CONSTRAINT download_jobs_state_check CHECK (state IN ('queued', 'running', 'completed', 'failed', 'cancelled', 'expanded'))
+,	ADD COLUMN IF NOT EXISTS parent_job		UUID REFERENCES download_jobs(id) ON DELETE CASCADE
+,	ADD COLUMN IF NOT EXISTS playlist_index	INTEGER
+,	ADD COLUMN IF NOT EXISTS title			TEXT
+;
+
+CREATE INDEX IF NOT EXISTS download_jobs_parent
+	ON download_jobs (parent_job, playlist_index)
+;
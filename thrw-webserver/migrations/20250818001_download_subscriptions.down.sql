@@ -0,0 +1,6 @@
+ALTER TABLE download_jobs
+	DROP COLUMN IF EXISTS subscription
+;
+
+DROP TABLE IF EXISTS download_archive;
+DROP TABLE IF EXISTS download_subscriptions;
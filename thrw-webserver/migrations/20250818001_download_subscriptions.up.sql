@@ -0,0 +1,36 @@
+CREATE TABLE IF NOT EXISTS download_subscriptions(
+	id				UUID PRIMARY KEY DEFAULT gen_random_uuid()
+,	user_id			INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE
+,	url				TEXT NOT NULL
+,	title			TEXT
+,	target_node		UUID REFERENCES vfs_nodes(id) ON DELETE SET NULL
+,	audio_only		BOOLEAN NOT NULL DEFAULT false
+,	max_duration	INTEGER CHECK (max_duration > 0)
+,	title_regex		TEXT
+,	check_interval	INTEGER NOT NULL CHECK (check_interval > 0)
+,	enabled			BOOLEAN NOT NULL DEFAULT true
+,	last_checked_at	TIMESTAMPTZ
+,	next_check_at	TIMESTAMPTZ NOT NULL DEFAULT now()
+,	last_error		TEXT
+,	created_at		TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS download_subscriptions_due
+	ON download_subscriptions (next_check_at)
+	WHERE enabled
+;
+
+CREATE INDEX IF NOT EXISTS download_subscriptions_user
+	ON download_subscriptions (user_id, created_at)
+;
+
+CREATE TABLE IF NOT EXISTS download_archive(
+	subscription	UUID NOT NULL REFERENCES download_subscriptions(id) ON DELETE CASCADE
+,	entry_id		TEXT NOT NULL
+,	created_at		TIMESTAMPTZ NOT NULL DEFAULT now()
+,	PRIMARY KEY (subscription, entry_id)
+);
+
+ALTER TABLE download_jobs
+	ADD COLUMN IF NOT EXISTS subscription	UUID REFERENCES download_subscriptions(id) ON DELETE SET NULL
+;
@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS media_provenance(
+	id				UUID PRIMARY KEY REFERENCES vfs_files(id) ON DELETE CASCADE
+,	extractor		TEXT NOT NULL
+,	source_id		TEXT NOT NULL
+,	webpage_url		TEXT
+,	uploader		TEXT
+,	upload_date		DATE
+,	description		TEXT
+,	created_at		TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS media_provenance_source
+	ON media_provenance (extractor, source_id)
+;
+
+CREATE INDEX IF NOT EXISTS media_provenance_url
+	ON media_provenance (webpage_url)
+;
@@ -0,0 +1,3 @@
+ALTER TABLE download_jobs
+	DROP COLUMN IF EXISTS format_options
+;
@@ -0,0 +1,3 @@
+ALTER TABLE download_jobs
+	ADD COLUMN IF NOT EXISTS format_options	TEXT
+;
@@ -0,0 +1,3 @@
+DROP VIEW IF EXISTS effective_download_quotas;
+DROP TABLE IF EXISTS user_download_quotas;
+DROP TABLE IF EXISTS download_quotas;
@@ -0,0 +1,40 @@
+-- a missing row or NULL limit means unlimited
+CREATE TABLE IF NOT EXISTS download_quotas(
+	level_id		SMALLINT PRIMARY KEY REFERENCES user_levels(level_id) ON DELETE CASCADE
+,	max_concurrent	INTEGER CHECK (max_concurrent > 0)
+,	max_daily_jobs	INTEGER CHECK (max_daily_jobs >= 0)
+,	max_total_bytes	BIGINT CHECK (max_total_bytes >= 0)
+,	max_file_bytes	BIGINT CHECK (max_file_bytes > 0)
+);
+
+-- NULL limits fall back to the user's level
+CREATE TABLE IF NOT EXISTS user_download_quotas(
+	user_id			INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE
+,	max_concurrent	INTEGER CHECK (max_concurrent > 0)
+,	max_daily_jobs	INTEGER CHECK (max_daily_jobs >= 0)
+,	max_total_bytes	BIGINT CHECK (max_total_bytes >= 0)
+,	max_file_bytes	BIGINT CHECK (max_file_bytes > 0)
+);
+
+CREATE OR REPLACE VIEW effective_download_quotas AS
+SELECT
+	users.id												AS user_id
+,	COALESCE(user_quota.max_concurrent, level_quota.max_concurrent)		AS max_concurrent
+,	COALESCE(user_quota.max_daily_jobs, level_quota.max_daily_jobs)		AS max_daily_jobs
+,	COALESCE(user_quota.max_total_bytes, level_quota.max_total_bytes)	AS max_total_bytes
+,	COALESCE(user_quota.max_file_bytes, level_quota.max_file_bytes)		AS max_file_bytes
+FROM users
+LEFT JOIN download_quotas		AS level_quota	ON level_quota.level_id = users.user_level
+LEFT JOIN user_download_quotas	AS user_quota	ON user_quota.user_id = users.id
+;
+
+INSERT INTO download_quotas
+	(level_id, max_concurrent, max_daily_jobs, max_total_bytes, max_file_bytes)
+SELECT
+	level_id, 2, 50, 50::BIGINT * 1024 * 1024 * 1024, 4::BIGINT * 1024 * 1024 * 1024
+FROM
+	user_levels
+WHERE
+	level_name = 'user'
+ON CONFLICT DO NOTHING
+;
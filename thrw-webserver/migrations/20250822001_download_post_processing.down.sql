@@ -0,0 +1,6 @@
+ALTER TABLE download_subscriptions
+	DROP COLUMN IF EXISTS post_processing
+;
+ALTER TABLE download_jobs
+	DROP COLUMN IF EXISTS post_processing
+;
@@ -0,0 +1,7 @@
+-- json of the steps run after the download, NULL runs none
+ALTER TABLE download_jobs
+	ADD COLUMN IF NOT EXISTS post_processing	TEXT
+;
+ALTER TABLE download_subscriptions
+	ADD COLUMN IF NOT EXISTS post_processing	TEXT
+;
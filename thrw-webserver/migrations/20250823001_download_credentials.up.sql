@@ -0,0 +1,12 @@
+-- cookies files and logins yt-dlp uses for a user's downloads, the secret part is encrypted by the server
+CREATE TABLE IF NOT EXISTS download_credentials(
+	id			UUID PRIMARY KEY DEFAULT gen_random_uuid()
+,	user_id		INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE
+,	kind		TEXT NOT NULL CHECK (kind IN ('cookies', 'login'))
+,	site		TEXT NOT NULL
+,	username	TEXT
+,	nonce		BYTEA NOT NULL
+,	secret		BYTEA NOT NULL
+,	created_at	TIMESTAMPTZ NOT NULL DEFAULT now()
+,	UNIQUE (user_id, kind, site)
+);
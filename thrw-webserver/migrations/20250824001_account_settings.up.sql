@@ -0,0 +1,4 @@
+-- shown instead of the email where set
+ALTER TABLE users
+	ADD COLUMN IF NOT EXISTS display_name	TEXT
+;
@@ -0,0 +1,4 @@
+-- as sent on login, shown when listing sessions
+ALTER TABLE sessions
+	ADD COLUMN IF NOT EXISTS user_agent	TEXT
+;
@@ -0,0 +1,25 @@
+-- the totp secret of a user, only asked for on login once the enrollment is confirmed
+CREATE TABLE IF NOT EXISTS user_totp(
+	id			INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE
+,	secret		TEXT NOT NULL
+,	confirmed	BOOLEAN NOT NULL DEFAULT false
+-- the time step of the last accepted code, a code is never accepted twice
+,	last_step	BIGINT
+,	created_at	TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- argon2 hashes of single-use codes that replace a totp code
+CREATE TABLE IF NOT EXISTS user_recovery_codes(
+	id			UUID PRIMARY KEY DEFAULT gen_random_uuid()
+,	user_id		INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE
+,	code_hash	TEXT NOT NULL
+,	used_at		TIMESTAMPTZ
+);
+
+-- logins that passed the password and wait for the second factor
+CREATE TABLE IF NOT EXISTS login_challenges(
+	id			UUID PRIMARY KEY DEFAULT gen_random_uuid()
+,	user_id		INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE
+,	attempts	SMALLINT NOT NULL DEFAULT 0
+,	expires_at	TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,13 @@
+-- tokens scripts authenticate with instead of a session, only the sha-256 of the token is kept
+CREATE TABLE IF NOT EXISTS api_tokens(
+	id				UUID PRIMARY KEY DEFAULT gen_random_uuid()
+,	user_id			INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE
+,	token_name		TEXT NOT NULL
+,	token_hash		TEXT NOT NULL UNIQUE
+,	scopes			TEXT[] NOT NULL
+-- NULL never expires
+,	expires_at		TIMESTAMPTZ
+,	last_used_at	TIMESTAMPTZ
+,	created_at		TIMESTAMPTZ NOT NULL DEFAULT now()
+,	UNIQUE (user_id, token_name)
+);
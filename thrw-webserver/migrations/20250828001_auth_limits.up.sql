@@ -0,0 +1,22 @@
+-- failed attempts per ip or email, only written when the limits are persisted
+CREATE TABLE IF NOT EXISTS auth_limits(
+	limit_key		TEXT PRIMARY KEY
+,	failures		INTEGER NOT NULL
+,	last_failure	TIMESTAMPTZ NOT NULL
+,	locked_until	TIMESTAMPTZ
+);
+
+-- logins, registrations and lockouts for admins to review
+CREATE TABLE IF NOT EXISTS auth_events(
+	id			BIGSERIAL PRIMARY KEY
+,	event		TEXT NOT NULL
+,	email		TEXT
+,	user_id		INTEGER REFERENCES users(id) ON DELETE SET NULL
+,	ip_address	VARCHAR(64)
+,	detail		TEXT
+,	created_at	TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS auth_events_created
+	ON auth_events (created_at)
+;
@@ -0,0 +1,20 @@
+ALTER TABLE users
+	ADD COLUMN IF NOT EXISTS email_verified_at	TIMESTAMPTZ
+;
+-- accounts from before verification existed are trusted as they are
+UPDATE users
+	SET email_verified_at = now()
+	WHERE email_verified_at IS NULL
+;
+
+-- one-time tokens sent by mail, the token itself is signed by the server and only its id is kept
+CREATE TABLE IF NOT EXISTS email_tokens(
+	id			UUID PRIMARY KEY
+,	user_id		INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE
+,	purpose		TEXT NOT NULL CHECK (purpose IN ('verify_email', 'reset_password'))
+-- the address the token was sent to, verifying a changed address does nothing
+,	email		TEXT NOT NULL
+,	expires_at	TIMESTAMPTZ NOT NULL
+,	used_at		TIMESTAMPTZ
+,	created_at	TIMESTAMPTZ NOT NULL DEFAULT now()
+);
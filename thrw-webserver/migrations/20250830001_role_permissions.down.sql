@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS level_permissions;
+ALTER TABLE user_levels
+	DROP CONSTRAINT IF EXISTS user_levels_level_name_key
+;
@@ -0,0 +1,37 @@
+-- user levels are used as roles, what a role may do is no longer derived from its id
+ALTER TABLE user_levels
+	ADD CONSTRAINT user_levels_level_name_key UNIQUE (level_name)
+;
+
+CREATE TABLE IF NOT EXISTS level_permissions(
+	level_id	SMALLINT NOT NULL REFERENCES user_levels(level_id) ON DELETE CASCADE
+,	permission	TEXT NOT NULL
+,	PRIMARY KEY (level_id, permission)
+);
+
+-- every level could download before
+INSERT INTO level_permissions
+	(level_id, permission)
+SELECT
+	user_levels.level_id, perms.permission
+FROM
+	user_levels
+CROSS JOIN unnest(ARRAY['download', 'upload']) AS perms(permission)
+ON CONFLICT DO NOTHING
+;
+
+-- and levels from admin upwards could do everything
+INSERT INTO level_permissions
+	(level_id, permission)
+SELECT
+	user_levels.level_id, perms.permission
+FROM
+	user_levels
+CROSS JOIN unnest(ARRAY['view_all_files', 'manage_keys', 'manage_users', 'manage_roles', 'manage_server']) AS perms(permission)
+WHERE user_levels.level_id >= (
+	SELECT level_id
+	FROM user_levels
+	WHERE level_name = 'admin'
+)
+ON CONFLICT DO NOTHING
+;
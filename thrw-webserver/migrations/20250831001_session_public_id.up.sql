@@ -0,0 +1,4 @@
+-- sessions are listed and revoked by this id, the session_id is the cookie and never leaves the server
+ALTER TABLE sessions
+	ADD COLUMN IF NOT EXISTS id	UUID NOT NULL DEFAULT gen_random_uuid() UNIQUE
+;
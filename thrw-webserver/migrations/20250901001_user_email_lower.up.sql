@@ -0,0 +1,10 @@
+-- addresses are normalized before they are stored or looked up, this catches up on the ones stored before
+-- fails if two accounts only differ in the case of their address, those have to be merged or changed by hand
+UPDATE users
+	SET email = lower(trim(email))
+	WHERE email <> lower(trim(email))
+;
+
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_lower_idx
+	ON users (lower(email))
+;
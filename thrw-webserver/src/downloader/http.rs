@@ -0,0 +1,289 @@
+use std::{path::Path, sync::LazyLock, time::Instant};
+
+use futures::future::BoxFuture;
+use percent_encoding::percent_decode_str;
+use reqwest::{header::{CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE}, StatusCode};
+use sqlx::{Pool, Postgres};
+use thrw_shared::{app::media_request::DirectFile, downloader::{shared::{DownloaderError, PubDownloadPhase, PubDownloadProgress}, util::{is_transient_error, DownloadJob}}, vfs::util::FileRef};
+use tokio::io::AsyncWriteExt;
+
+use super::{Downloaded, MediaDownloader};
+
+mod consts {
+	use std::time::Duration;
+
+	/// links ending in these are fetched directly instead of asking yt-dlp about them
+	pub const DIRECT_EXTENSIONS: &[&str] = &[
+		"mp3", "m4a", "aac", "flac", "ogg", "oga", "opus", "wav",
+		"mp4", "m4v", "mov", "webm", "mkv",
+		"png", "jpg", "jpeg", "gif", "webp", "avif",
+		"pdf", "txt",
+	];
+	/// prefixes of the content types a server may announce, the content itself is checked on import
+	pub const ALLOWED_CONTENT_TYPES: &[&str] = &[
+		"audio/",
+		"video/",
+		"image/",
+		"application/pdf",
+		"text/plain",
+		"application/octet-stream",
+	];
+
+	pub const MAX_SIZE_ENV: &str = "THRW_MAX_DIRECT_DOWNLOAD_MB";
+	pub const DEFAULT_MAX_SIZE_MB: u64 = 2048;
+
+	pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+	/// between two chunks, a whole download may take as long as it needs
+	pub const READ_TIMEOUT: Duration = Duration::from_secs(30);
+	/// broken off transfers are continued this often within one attempt of the job
+	pub const MAX_RESUMES: usize = 5;
+
+	pub const DOWNLOAD_FILENAME: &str = "download";
+}
+
+pub(super) static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| reqwest::Client::builder()
+	.connect_timeout(consts::CONNECT_TIMEOUT)
+	.read_timeout(consts::READ_TIMEOUT)
+	.build()
+	.expect("unable to build the http client")
+);
+
+/// the decoded name and lowercase extension of the file an http(s) url points at
+fn split_file_name(url: &str) -> Option<(String, String)> {
+	let url = reqwest::Url::parse(url).ok()?;
+	if !matches!(url.scheme(), "http" | "https") {
+		return None;
+	}
+	let segment = url.path_segments()?.next_back()?;
+	let segment = percent_decode_str(segment).decode_utf8_lossy();
+	let (name, extension) = segment.rsplit_once('.')?;
+	if name.trim().is_empty() {
+		return None;
+	}
+	Some((name.to_string(), extension.to_ascii_lowercase()))
+}
+
+fn max_download_size() -> u64 {
+	let megabytes = std::env::var(consts::MAX_SIZE_ENV)
+		.ok()
+		.and_then(|val| val.parse::<u64>().ok())
+		.filter(|megabytes| *megabytes > 0)
+		.unwrap_or(consts::DEFAULT_MAX_SIZE_MB)
+	;
+	megabytes * 1024 * 1024
+}
+
+pub(super) fn map_http_error(err: reqwest::Error) -> DownloaderError {
+	// connecting, timing out and broken off bodies are worth another try
+	let transient = err.is_connect() || err.is_timeout() || err.is_body();
+	DownloaderError::Http(err.to_string(), transient)
+}
+
+/// the announced content type without its parameters, rejecting what can't end up in the vfs
+fn check_content_type(response: &reqwest::Response) -> Result<Option<String>, DownloaderError> {
+	let Some(content_type) = response.headers()
+		.get(CONTENT_TYPE)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.split(';').next())
+		.map(|value| value.trim().to_ascii_lowercase())
+	else {
+		return Ok(None);
+	};
+
+	match consts::ALLOWED_CONTENT_TYPES.iter().any(|allowed| content_type.starts_with(allowed)) {
+		true => Ok(Some(content_type)),
+		false => Err(DownloaderError::UnsupportedType(content_type)),
+	}
+}
+
+/// start and total length of a `Content-Range: bytes <start>-<end>/<total>` answer
+fn parse_content_range(response: &reqwest::Response) -> Option<(u64, Option<u64>)> {
+	let value = response.headers()
+		.get(CONTENT_RANGE)?
+		.to_str()
+		.ok()?
+	;
+	let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+	let (start, _) = range.split_once('-')?;
+	Some((start.trim().parse().ok()?, total.trim().parse().ok()))
+}
+
+/// what a range request has to match to continue the same file, weak etags don't promise the same bytes
+fn get_validator(response: &reqwest::Response) -> Option<String> {
+	let headers = response.headers();
+	headers.get(ETAG)
+		.and_then(|value| value.to_str().ok())
+		.filter(|etag| !etag.starts_with("W/"))
+		.or_else(|| headers.get(LAST_MODIFIED).and_then(|value| value.to_str().ok()))
+		.map(ToString::to_string)
+}
+
+fn make_progress(
+	job_id: uuid::Uuid,
+	downloaded: u64,
+	total: Option<u64>,
+	started: Instant,
+) -> PubDownloadProgress {
+	let elapsed = started.elapsed().as_secs_f64();
+	let speed = (elapsed > 0.0).then(|| downloaded as f64 / elapsed);
+	let total = total.filter(|total| *total > 0);
+
+	PubDownloadProgress {
+		job_id,
+		phase: PubDownloadPhase::Downloading,
+		percent: total.map(|total| (downloaded as f64 / total as f64 * 100.0).clamp(0.0, 100.0)),
+		speed,
+		eta: total
+			.zip(speed.filter(|speed| *speed > 0.0))
+			.map(|(total, speed)| (total.saturating_sub(downloaded) as f64 / speed) as i64),
+	}
+}
+
+/// fetch the url into the file, continuing with a range request whenever the transfer breaks off
+async fn fetch_to_file(
+	url: &str,
+	path: &Path,
+	job_id: uuid::Uuid,
+	max_size: Option<u64>,
+	report: &(dyn Fn(PubDownloadProgress) + Send + Sync),
+) -> Result<Option<String>, DownloaderError> {
+	let max_size = max_download_size().min(max_size.unwrap_or(u64::MAX));
+	let started = Instant::now();
+	let mut file = tokio::fs::File::create(path).await.map_err(DownloaderError::Io)?;
+	let mut mime_type = None;
+	let mut downloaded: u64 = 0;
+	let mut total: Option<u64> = None;
+	let mut resumes = 0;
+	let mut last_report: Option<Instant> = None;
+
+	let mut validator: Option<String> = None;
+
+	loop {
+		// without a validator a changed file can't be told apart, so it is fetched from the start
+		let resume = validator.as_ref().filter(|_| downloaded > 0);
+		let mut request = CLIENT.get(url);
+		if let Some(validator) = resume {
+			request = request
+				.header(RANGE, format!("bytes={downloaded}-"))
+				.header(IF_RANGE, validator)
+			;
+		}
+		let mut response = request
+			.send()
+			.await
+			.map_err(map_http_error)?
+		;
+
+		match response.status() {
+			StatusCode::PARTIAL_CONTENT if resume.is_some() => match parse_content_range(&response) {
+				Some((start, range_total)) if start == downloaded => total = range_total.or(total),
+				_ => {
+					// not the part that was asked for, start over with a full request
+					if resumes >= consts::MAX_RESUMES {
+						return Err(DownloaderError::Http(format!("unexpected content range when resuming at {downloaded} bytes"), false));
+					}
+					resumes += 1;
+					file = tokio::fs::File::create(path).await.map_err(DownloaderError::Io)?;
+					downloaded = 0;
+					validator = None;
+					continue;
+				},
+			},
+			status if status.is_success() => {
+				// the server ignored the range or the file changed, so everything is sent again
+				if downloaded > 0 {
+					file = tokio::fs::File::create(path).await.map_err(DownloaderError::Io)?;
+					downloaded = 0;
+				}
+				total = response.content_length();
+				validator = get_validator(&response);
+			},
+			status => return Err(DownloaderError::HttpStatus(status.as_u16())),
+		}
+		mime_type = check_content_type(&response)?.or(mime_type);
+		if total.is_some_and(|total| total > max_size) {
+			return Err(DownloaderError::TooLarge(max_size));
+		}
+
+		let res = loop {
+			match response.chunk().await {
+				Ok(Some(chunk)) => {
+					downloaded += chunk.len() as u64;
+					// the announced length can't be trusted
+					if downloaded > max_size {
+						return Err(DownloaderError::TooLarge(max_size));
+					}
+					file.write_all(&chunk).await.map_err(DownloaderError::Io)?;
+
+					if last_report.is_none_or(|last| last.elapsed() >= super::consts::PROGRESS_INTERVAL) {
+						last_report = Some(Instant::now());
+						report(make_progress(job_id, downloaded, total, started));
+					}
+				},
+				Ok(None) => break Ok(()),
+				Err(err) => break Err(map_http_error(err)),
+			}
+		};
+
+		let err = match res {
+			Ok(()) if total.is_none_or(|total| downloaded >= total) => break,
+			Ok(()) => DownloaderError::Http(format!("connection closed after {downloaded} of {total:?} bytes"), true),
+			Err(err) => err,
+		};
+		if resumes >= consts::MAX_RESUMES || !is_transient_error(&err) {
+			return Err(err);
+		}
+		resumes += 1;
+		println!("download of '{url}' broke off at {downloaded} bytes, resuming: {err:?}");
+	}
+
+	file.flush().await.map_err(DownloaderError::Io)?;
+	Ok(mime_type)
+}
+
+/// direct links to media, images and documents, fetched without yt-dlp
+pub struct HttpDownloader;
+impl MediaDownloader for HttpDownloader {
+	fn name(&self) -> &'static str {
+		"http"
+	}
+
+	fn handles(&self, url: &str) -> bool {
+		split_file_name(url).is_some_and(|(_, extension)| consts::DIRECT_EXTENSIONS.contains(&extension.as_str()))
+	}
+
+	/// the file is stored as it is served, audio only and format options don't apply
+	fn download<'a>(
+		&'a self,
+		_db_pool: &'a Pool<Postgres>,
+		job: &'a DownloadJob,
+		job_dir: &'a Path,
+		max_size: Option<u64>,
+		report: &'a (dyn Fn(PubDownloadProgress) + Send + Sync),
+	) -> BoxFuture<'a, Result<Downloaded, DownloaderError>> {
+		Box::pin(async move {
+			let url = &job.request.url;
+			let (name, extension) = split_file_name(url)
+				.ok_or(DownloaderError::Http("the url does not point at a file".to_string(), false))?
+			;
+			let path = job_dir.join(format!("{}.{extension}", consts::DOWNLOAD_FILENAME));
+
+			println!("downloading file at '{url}' into '{job_dir:?}'");
+			let mime_type = fetch_to_file(url, &path, job.id, max_size, report).await?;
+			let file_size = std::fs::metadata(&path)
+				.map_err(DownloaderError::Io)?
+				.len()
+			;
+
+			Ok(Downloaded::File(DirectFile {
+				url: url.clone(),
+				name,
+				file: FileRef {
+					path,
+					file_size: file_size as i64,
+				},
+				mime_type,
+			}))
+		})
+	}
+}
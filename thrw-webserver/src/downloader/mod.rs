@@ -1,143 +1,315 @@
-use std::{ffi::{OsStr, OsString}, future::{join, Future}};
+use std::{future::Future, path::{Path, PathBuf}};
 
+use futures::future::BoxFuture;
 use sqlx::{Pool, Postgres};
-use thrw_shared::{app::media_request::{DownloaderContext, MediaRequest, MediaRequestError, YtdlRequest, YtdlResult}, downloader::shared::DownloaderError, make_error_type, media::util::get_media_file_metadata, vfs::util::{commit_file_to_vfs, FileRef, VFSFileType}};
-use tokio::{sync::{mpsc::{unbounded_channel, UnboundedReceiver}, oneshot}, try_join};
+use thrw_shared::{app::media_request::{DirectFile, DownloaderContext, YtdlResult}, downloader::{quota::get_max_download_size, shared::{DownloaderError, PubDownloadPhase, PubDownloadProgress}, util::{claim_next_download_job, complete_download_job, fail_download_job, get_playlist_progress, get_retry_delay, get_root_job, import_direct_file, import_ytdl_result, is_transient_error, requeue_interrupted_jobs, schedule_download_retry, DownloadJob}}, media::binaries::binary_config};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+mod http;
+mod postprocess;
+mod subscriptions;
+mod ytdl;
 
 mod consts {
-	pub const YT_DL_DIR: &str = "./ytdl";
-	#[cfg(target_family = "windows")]
-	pub const YT_DL_BIN: &str = "yt-dlp.exe";
-	#[cfg(target_family = "unix")]
-	pub const YT_DL_BIN: &str = "yt-dlp_linux";
-	pub const YT_DL_PATH: &str = const_format::concatcp!(YT_DL_DIR, "/", YT_DL_BIN);
-
-	pub const YT_DL_TEMP_FILENAME: &str = "TEMP";
+	use std::time::Duration;
+
+	pub const JOB_POLL_INTERVAL: Duration = Duration::from_secs(30);
+	/// number of downloads running at the same time
+	pub const MAX_DOWNLOADS_ENV: &str = "THRW_MAX_DOWNLOADS";
+	pub const DEFAULT_MAX_DOWNLOADS: usize = 2;
+	/// every job downloads into `<ytdl dir>/jobs/<job id>`
+	pub const JOB_DIR: &str = "jobs";
+	pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
 }
 
-async fn handle_yt_dl(
-	req: YtdlRequest,
-	db_pool: Pool<Postgres>,
-) -> Result<YtdlResult, DownloaderError> {
-	if let Err(err) = dl_ytdl_if_needed().await {
-		println!("error downloading ytdl: {err:?}");
-		return Err(DownloaderError::YtdlInitError.into());
+/// a directory only used by a single job, removed again once the job is done with it in any way
+struct JobDir(PathBuf);
+impl JobDir {
+	fn jobs_root() -> PathBuf {
+		binary_config().ytdl_dir.join(consts::JOB_DIR)
+	}
+
+	fn create(job_id: uuid::Uuid) -> Result<Self, DownloaderError> {
+		let path = Self::jobs_root().join(job_id.to_string());
+		// leftovers of an interrupted attempt
+		if path.exists() {
+			std::fs::remove_dir_all(&path).map_err(DownloaderError::Io)?;
+		}
+		std::fs::create_dir_all(&path).map_err(DownloaderError::Io)?;
+		Ok(Self(path))
+	}
+
+	fn path(&self) -> &Path {
+		&self.0
+	}
+}
+impl Drop for JobDir {
+	fn drop(&mut self) {
+		match std::fs::remove_dir_all(&self.0) {
+			Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+				println!("unable to remove job directory '{:?}': {err:?}", self.0);
+			},
+			_ => (),
+		}
 	}
+}
+
+/// resolves once the given job is cancelled
+async fn wait_for_cancel(
+	mut cancel_recv: broadcast::Receiver<uuid::Uuid>,
+	job_id: uuid::Uuid,
+) {
+	loop {
+		match cancel_recv.recv().await {
+			Ok(id) if id == job_id => return,
+			Ok(_) | Err(RecvError::Lagged(_)) => continue,
+			Err(RecvError::Closed) => std::future::pending().await,
+		}
+	}
+}
+
+enum JobOutcome {
+	/// the file the job ends up with, along with the error of its post-processing
+	Imported(uuid::Uuid, Option<String>),
+	/// the job was a playlist and now has this many child jobs
+	Expanded(usize),
+}
+
+/// what a downloader leaves behind for the worker to import
+enum Downloaded {
+	Ytdl(YtdlResult),
+	File(DirectFile),
+	/// the url was a playlist and now has this many child jobs
+	Expanded(usize),
+}
+
+/// a way of pulling the url of a job into its job directory
+trait MediaDownloader: Send + Sync {
+	fn name(&self) -> &'static str;
+
+	/// whether the url is meant for this downloader
+	fn handles(&self, url: &str) -> bool;
 
-	println!("downloading media at '{}'", req.url);
-	let path = std::path::Path::new(consts::YT_DL_DIR);
-	let mut output_b = youtube_dl::YoutubeDl::new(req.url.clone());
-	let output_b = output_b
-		.socket_timeout("15")
-		.youtube_dl_path(consts::YT_DL_PATH)
-	;
-
-	let mut dl_b = youtube_dl::YoutubeDl::new(req.url);
-	let dl_b = dl_b
-		.socket_timeout("15")
-		.youtube_dl_path(consts::YT_DL_PATH)
-		.extract_audio(req.audio_only)
-		.extra_arg("--write-thumbnail")
-		.output_template(format!("{}.%(ext)s", consts::YT_DL_TEMP_FILENAME))
-	;
-
-	let (output, _) = try_join!(
-		output_b.run_async(),
-		dl_b.download_to_async(path)
-	)
-		.map_err(DownloaderError::Ytdl)?
-	;
-	let output = output.into_single_video()
-		.ok_or(DownloaderError::YtdlNotSingle)?
-	;
-
-	let files: Result<Vec<_>, std::io::Error> = std::fs::read_dir(path)
-		.map_err(DownloaderError::Io)?
-		.collect()
-	;
-	let files = files.map_err(DownloaderError::Io)?;
-	let file_refs: Vec<FileRef> = files
+	/// files larger than `max_size` bytes are given up on as early as possible
+	fn download<'a>(
+		&'a self,
+		db_pool: &'a Pool<Postgres>,
+		job: &'a DownloadJob,
+		job_dir: &'a Path,
+		max_size: Option<u64>,
+		report: &'a (dyn Fn(PubDownloadProgress) + Send + Sync),
+	) -> BoxFuture<'a, Result<Downloaded, DownloaderError>>;
+}
+
+/// asked in order, yt-dlp takes whatever none of them wants
+const DOWNLOADERS: &[&dyn MediaDownloader] = &[
+	&http::HttpDownloader,
+];
+
+fn select_downloader(url: &str) -> &'static dyn MediaDownloader {
+	DOWNLOADERS
 		.iter()
-		.filter(|en| en.path().with_extension("").file_name().map(OsStr::to_str).unwrap_or(Some("")).unwrap_or("") == consts::YT_DL_TEMP_FILENAME)
-		.map(|entry| entry.into())
-		.collect()
-	;
+		.copied()
+		.find(|downloader| downloader.handles(url))
+		.unwrap_or(&ytdl::YtdlDownloader)
+}
 
-	let mut media = None;
-	let mut thumbnail = None;
+async fn run_job(
+	db_pool: &Pool<Postgres>,
+	context: &DownloaderContext,
+	job: DownloadJob,
+	cancel_recv: broadcast::Receiver<uuid::Uuid>,
+) -> Result<JobOutcome, DownloaderError> {
+	let (job_id, user_id) = (job.id, job.user_id);
+	context.report_progress(user_id, PubDownloadProgress::phase(job_id, PubDownloadPhase::Downloading));
 
-	for file in file_refs {
-		let data = std::fs::read(&file.path)
-			.map_err(DownloaderError::Io)?
-		;
-		let infer_data = infer::get(&data);
-		match infer_data {
-			Some(itype) => match itype.matcher_type() {
-				  infer::MatcherType::Video
-				| infer::MatcherType::Audio => media = Some(file),
-				infer::MatcherType::Image => thumbnail = Some(file),
-				_ => {
-					println!("invalid file type received from ytdl, removing");
-					let _ = file.delete_file();
-					continue;
-				},
+	let max_size = get_max_download_size(db_pool, user_id).await?;
+	let downloader = select_downloader(&job.request.url);
+	println!("job {job_id} is downloaded with {}", downloader.name());
+
+	// dropped on every exit, including cancellation
+	let job_dir = JobDir::create(job_id)?;
+	let report = |progress: PubDownloadProgress| context.report_progress(user_id, progress);
+	let download = downloader.download(db_pool, &job, job_dir.path(), max_size, &report);
+	// dropping the download stops it, the import is not interrupted
+	let downloaded = tokio::select! {
+		res = download => res?,
+		_ = wait_for_cancel(cancel_recv, job_id) => return Err(DownloaderError::Cancelled),
+	};
+
+	// downloaders can only stop early when they know the size up front
+	let file_size = match &downloaded {
+		Downloaded::Ytdl(ytdl_res) => Some(ytdl_res.media.file_size),
+		Downloaded::File(direct) => Some(direct.file.file_size),
+		Downloaded::Expanded(_) => None,
+	};
+	if let (Some(max_size), Some(file_size)) = (max_size, file_size) {
+		if file_size as u64 > max_size {
+			return Err(DownloaderError::TooLarge(max_size));
+		}
+	}
+
+	let media_file = match downloaded {
+		Downloaded::Expanded(count) => {
+			context.notify_job();
+			return Ok(JobOutcome::Expanded(count));
+		},
+		Downloaded::Ytdl(ytdl_res) => {
+			context.report_progress(user_id, PubDownloadProgress::phase(job_id, PubDownloadPhase::Importing));
+			import_ytdl_result(db_pool, ytdl_res, job.target.clone(), job.playlist_index).await?
+		},
+		Downloaded::File(direct) => {
+			context.report_progress(user_id, PubDownloadProgress::phase(job_id, PubDownloadPhase::Importing));
+			import_direct_file(db_pool, direct, job.target.clone(), job.playlist_index).await?
+		},
+	};
+	if job.post_processing.steps.is_empty() {
+		return Ok(JobOutcome::Imported(media_file, None));
+	}
+
+	// the download is in the vfs already, a failing step must not get it downloaded again
+	match postprocess::run_post_processing(db_pool, context, &job, job_dir.path(), media_file).await {
+		Ok(processed) => Ok(JobOutcome::Imported(processed, None)),
+		Err(err) => {
+			println!("post-processing of job {job_id} failed: {err:?}");
+			Ok(JobOutcome::Imported(media_file, Some(format!("post-processing failed: {err:?}"))))
+		},
+	}
+}
+
+/// push the combined progress of the playlist a finished entry belongs to
+async fn report_playlist_progress(
+	db_pool: &Pool<Postgres>,
+	context: &DownloaderContext,
+	user_id: i32,
+	job_id: uuid::Uuid,
+) -> Result<(), DownloaderError> {
+	let root = get_root_job(db_pool, job_id).await?;
+	let progress = get_playlist_progress(db_pool, root).await?;
+	context.report_progress(user_id, PubDownloadProgress::phase(root, PubDownloadPhase::Playlist(progress)));
+	Ok(())
+}
+
+async fn run_worker(
+	worker: usize,
+	db_pool: Pool<Postgres>,
+	context: DownloaderContext,
+) {
+	loop {
+		let job = match claim_next_download_job(&db_pool).await {
+			Ok(Some(job)) => job,
+			Ok(None) => {
+				// jobs might be queued by another process, so don't rely on the notification alone
+				let _ = tokio::time::timeout(consts::JOB_POLL_INTERVAL, context.job_notify.notified()).await;
+				continue;
 			},
-			None => {
-				println!("unable to determine ytdl file type, removing");
-				let _ = file.delete_file();
+			Err(err) => {
+				println!("unable to fetch download jobs: {err:?}");
+				tokio::time::sleep(consts::JOB_POLL_INTERVAL).await;
 				continue;
 			},
 		};
-	}
-	
-	let result = YtdlResult {
-		media: media.ok_or(DownloaderError::NoTempFile)?,
-		thumbnail,
-		output: output,
-	};
-	Ok(result)
-}
 
-async fn dl_ytdl_if_needed() -> Result<(), DownloaderError> {
-	let path = std::path::Path::new(consts::YT_DL_PATH);
-	if path.exists() {
-		return Ok(());
-	}
+		let (job_id, user_id, retry_count, parent_job) = (job.id, job.user_id, job.retry_count, job.parent_job);
+		println!("download worker {worker} picked up job {job_id}");
+		let cancel_recv = context.cancel.subscribe();
+		let res = match run_job(&db_pool, &context, job, cancel_recv).await {
+			Ok(JobOutcome::Imported(media_file, post_error)) => match complete_download_job(&db_pool, job_id, media_file, post_error).await {
+				Ok(()) => {
+					context.report_progress(user_id, PubDownloadProgress::phase(job_id, PubDownloadPhase::Completed));
+					Ok(())
+				},
+				// cancelled after the download finished, the imported file is kept
+				Err(DownloaderError::InvalidJobState) => {
+					println!("download job {job_id} was cancelled while importing");
+					Ok(())
+				},
+				Err(err) => Err(err),
+			},
+			Ok(JobOutcome::Expanded(count)) => {
+				println!("download job {job_id} expanded into {count} entries");
+				get_playlist_progress(&db_pool, job_id)
+					.await
+					.map(|progress| context.report_progress(user_id, PubDownloadProgress::phase(job_id, PubDownloadPhase::Playlist(progress))))
+			},
+			Err(DownloaderError::Cancelled) => {
+				println!("download job {job_id} cancelled");
+				context.report_progress(user_id, PubDownloadProgress::phase(job_id, PubDownloadPhase::Cancelled));
+				Ok(())
+			},
+			Err(err) => {
+				let error = format!("{err:?}");
+				match get_retry_delay(retry_count).filter(|_| is_transient_error(&err)) {
+					Some(delay) => {
+						println!("download job {job_id} failed, retrying in {delay:?}: {err:?}");
+						let res = schedule_download_retry(&db_pool, job_id, error, delay).await;
+						context.report_progress(user_id, PubDownloadProgress::phase(job_id, PubDownloadPhase::RetryScheduled(delay.as_secs() as i64)));
+						res
+					},
+					None => {
+						println!("download job {job_id} failed: {err:?}");
+						let res = fail_download_job(&db_pool, job_id, error.clone()).await;
+						context.report_progress(user_id, PubDownloadProgress::phase(job_id, PubDownloadPhase::Failed(error)));
+						res
+					},
+				}
+			},
+		};
+		if let Err(err) = res {
+			println!("unable to update download job {job_id}: {err:?}");
+		}
 
-	println!("downloading ytdl...");
-	youtube_dl::download_yt_dlp(consts::YT_DL_DIR)
-		.await
-		.map(|_| ())
-		.map_err(DownloaderError::Ytdl)
+		if parent_job.is_some() {
+			if let Err(err) = report_playlist_progress(&db_pool, &context, user_id, job_id).await {
+				println!("unable to report playlist progress for job {job_id}: {err:?}");
+			}
+		}
+	}
 }
 
 fn make_handler(
 	db_pool: Pool<Postgres>,
-	mut message_recv: UnboundedReceiver<MediaRequest>
+	context: DownloaderContext,
 ) -> impl Future<Output = ()> {
 	async move {
-		while let Some(message) = message_recv.recv().await {
-			match message {
-				MediaRequest::Ytdl(req, ret) => {
-					let _ = ret.send(
-						handle_yt_dl(req, db_pool.clone())
-							.await
-							.map_err(MediaRequestError::Dl)
-					);
-				},
-			}
+		match requeue_interrupted_jobs(&db_pool).await {
+			Ok(job_ids) if job_ids.is_empty() => (),
+			Ok(job_ids) => {
+				println!("resuming {} interrupted downloads", job_ids.len());
+				// only the directories of the requeued jobs, anything else there isn't ours to remove
+				for job_id in job_ids {
+					let path = JobDir::jobs_root().join(job_id.to_string());
+					match std::fs::remove_dir_all(&path) {
+						Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+							println!("unable to clear job directory '{path:?}': {err:?}");
+						},
+						_ => (),
+					}
+				}
+			},
+			Err(err) => println!("unable to resume interrupted downloads: {err:?}"),
 		}
 
-		println!("media downloader thread closing");
+		let worker_count = std::env::var(consts::MAX_DOWNLOADS_ENV)
+			.ok()
+			.and_then(|val| val.parse::<usize>().ok())
+			.filter(|count| *count > 0)
+			.unwrap_or(consts::DEFAULT_MAX_DOWNLOADS)
+		;
+		println!("starting {worker_count} download workers");
+		tokio::spawn(subscriptions::run_scheduler(db_pool.clone(), context.clone()));
+
+		let workers = (0..worker_count).map(|worker| tokio::spawn(run_worker(worker, db_pool.clone(), context.clone())));
+		futures::future::join_all(workers).await;
+
+		println!("media downloader closing");
 	}
 }
 
-pub fn init_downloader(
+/// only once the database is migrated, the workers pick up jobs right away
+pub fn start_downloader(
 	db_pool: &Pool<Postgres>,
-) -> DownloaderContext {
-	let (send, receive) = unbounded_channel();
-
-	tokio::spawn(make_handler(db_pool.clone(),receive));
-
-	return DownloaderContext { request_channel: send }
-}
\ No newline at end of file
+	context: DownloaderContext,
+) {
+	tokio::spawn(make_handler(db_pool.clone(), context));
+}
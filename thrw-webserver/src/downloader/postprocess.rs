@@ -0,0 +1,268 @@
+use std::{path::{Path, PathBuf}, process::Stdio};
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use thrw_shared::{app::media_request::DownloaderContext, downloader::{postprocess::{commit_post_processed, get_file_node, move_to_uploader_folder, PostProcessed}, provenance::get_file_source_info, shared::{DownloaderError, PubAudioCodec, PubDownloadPhase, PubDownloadProgress, PubPostStep}, util::DownloadJob}, media::{binaries::binary_path, shared::PubBinaryKind}, vfs::util::{get_abs_file_path, get_vfs_file_info}};
+use tokio::process::Command;
+
+use super::http::{map_http_error, CLIENT};
+
+mod consts {
+	pub const STDERR_TAIL_LINES: usize = 20;
+	/// outputs are named `<prefix><step index>.<extension>` inside the job directory
+	pub const OUTPUT_PREFIX: &str = "post_";
+
+	/// everything below this counts as silence
+	pub const SILENCE_THRESHOLD: &str = "-50dB";
+
+	pub const SPONSORBLOCK_API_ENV: &str = "THRW_SPONSORBLOCK_API";
+	pub const DEFAULT_SPONSORBLOCK_API: &str = "https://sponsor.ajay.app";
+	pub const SPONSOR_CATEGORIES: &str = r#"["sponsor","selfpromo","interaction"]"#;
+	/// the only extractor SponsorBlock has segments for
+	pub const YOUTUBE_EXTRACTOR: &str = "Youtube";
+}
+
+#[derive(Debug, Deserialize)]
+struct SponsorSegment {
+	/// start and end in seconds
+	segment: (f64, f64),
+}
+
+/// the ffmpeg encoder and file extension of an audio codec
+fn audio_encoder(codec: PubAudioCodec) -> Option<(&'static str, &'static str)> {
+	match codec {
+		PubAudioCodec::Best => None,
+		PubAudioCodec::Aac | PubAudioCodec::M4a => Some(("aac", "m4a")),
+		PubAudioCodec::Flac => Some(("flac", "flac")),
+		PubAudioCodec::Mp3 => Some(("libmp3lame", "mp3")),
+		PubAudioCodec::Opus => Some(("libopus", "opus")),
+		PubAudioCodec::Vorbis => Some(("libvorbis", "ogg")),
+		PubAudioCodec::Wav => Some(("pcm_s16le", "wav")),
+	}
+}
+
+async fn run_ffmpeg(
+	input: &Path,
+	args: Vec<String>,
+	output: &Path,
+) -> Result<(), DownloaderError> {
+	let res = Command::new(binary_path(&PubBinaryKind::Ffmpeg))
+		.args(["-hide_banner", "-nostats", "-loglevel", "error", "-y", "-i"])
+		.arg(input)
+		.args(["-map_metadata", "0"])
+		.args(args)
+		.arg(output)
+		.stdin(Stdio::null())
+		.kill_on_drop(true)
+		.output()
+		.await
+		.map_err(DownloaderError::Io)?
+	;
+
+	if !res.status.success() {
+		let stderr = String::from_utf8_lossy(&res.stderr);
+		let lines: Vec<&str> = stderr.lines().collect();
+		let tail = lines[lines.len().saturating_sub(consts::STDERR_TAIL_LINES)..].join("\n");
+		return Err(DownloaderError::FfmpegProcess(res.status.code(), tail));
+	}
+	Ok(())
+}
+
+/// segments to cut out of the youtube video, none if SponsorBlock doesn't know it
+async fn get_sponsor_segments(
+	video_id: &str,
+) -> Result<Vec<(f64, f64)>, DownloaderError> {
+	let api = std::env::var(consts::SPONSORBLOCK_API_ENV)
+		.unwrap_or(consts::DEFAULT_SPONSORBLOCK_API.to_string())
+	;
+	let url = reqwest::Url::parse_with_params(
+		&format!("{}/api/skipSegments", api.trim_end_matches('/')),
+		&[("videoID", video_id), ("categories", consts::SPONSOR_CATEGORIES)],
+	)
+		.map_err(|err| DownloaderError::Http(err.to_string(), false))?
+	;
+
+	let response = CLIENT.get(url)
+		.send()
+		.await
+		.map_err(map_http_error)?
+	;
+	match response.status() {
+		StatusCode::NOT_FOUND => return Ok(vec![]),
+		status if !status.is_success() => return Err(DownloaderError::HttpStatus(status.as_u16())),
+		_ => (),
+	}
+	let body = response.text()
+		.await
+		.map_err(map_http_error)?
+	;
+	let segments: Vec<SponsorSegment> = serde_json::from_str(&body)
+		.map_err(|err| DownloaderError::Http(format!("invalid SponsorBlock response: {err}"), false))?
+	;
+
+	Ok(segments
+		.into_iter()
+		.map(|segment| segment.segment)
+		.filter(|(start, end)| end > start)
+		.collect()
+	)
+}
+
+/// what the transforms have to know about the file they are handed
+struct TransformInput<'a> {
+	path: &'a Path,
+	/// the committed file the input was made of
+	source: PostProcessed,
+	has_video: bool,
+}
+
+/// run a single ffmpeg step, `None` if it doesn't apply to the file
+async fn run_transform(
+	db_pool: &Pool<Postgres>,
+	step: &PubPostStep,
+	input: TransformInput<'_>,
+	output_stem: PathBuf,
+) -> Result<Option<PathBuf>, DownloaderError> {
+	let extension = input.path
+		.extension()
+		.map(|extension| extension.to_string_lossy().into_owned())
+		.unwrap_or_default()
+	;
+
+	match step {
+		PubPostStep::ConvertAudio { codec, bitrate } => {
+			let (encoder, extension) = audio_encoder(*codec)
+				.ok_or(DownloaderError::InvalidPostProcessing("pick a codec to convert to".to_string()))?
+			;
+			let output = output_stem.with_extension(extension);
+			let mut args = vec!["-vn".to_string(), "-c:a".to_string(), encoder.to_string()];
+			if let Some(bitrate) = bitrate {
+				args.extend(["-b:a".to_string(), format!("{bitrate}k")]);
+			}
+			run_ffmpeg(input.path, args, &output).await?;
+			Ok(Some(output))
+		},
+		PubPostStep::TrimSilence => {
+			// cutting the audio alone would put a video out of sync
+			if input.has_video {
+				println!("not trimming silence of '{}', it has video", input.source.file);
+				return Ok(None);
+			}
+			let trim = format!("silenceremove=start_periods=1:start_threshold={}", consts::SILENCE_THRESHOLD);
+			let output = output_stem.with_extension(extension);
+			// the end is trimmed by trimming the start of the reversed audio
+			let args = vec!["-af".to_string(), format!("{trim},areverse,{trim},areverse")];
+			run_ffmpeg(input.path, args, &output).await?;
+			Ok(Some(output))
+		},
+		PubPostStep::RemoveSponsors => {
+			let Some(source) = get_file_source_info(db_pool, input.source.file)
+				.await?
+				.filter(|source| source.extractor == consts::YOUTUBE_EXTRACTOR)
+			else {
+				println!("'{}' is no youtube video, no sponsors to remove", input.source.file);
+				return Ok(None);
+			};
+			let segments = get_sponsor_segments(&source.source_id).await?;
+			if segments.is_empty() {
+				return Ok(None);
+			}
+
+			let cut = segments
+				.iter()
+				.map(|(start, end)| format!("between(t,{start:.3},{end:.3})"))
+				.collect::<Vec<_>>()
+				.join("+")
+			;
+			let output = output_stem.with_extension(extension);
+			let mut args = vec![
+				"-af".to_string(),
+				format!("aselect='not({cut})',asetpts=N/SR/TB"),
+			];
+			if input.has_video {
+				args.extend(["-vf".to_string(), format!("select='not({cut})',setpts=N/FRAME_RATE/TB")]);
+			}
+			run_ffmpeg(input.path, args, &output).await?;
+			Ok(Some(output))
+		},
+		PubPostStep::MoveByUploader => Ok(None),
+	}
+}
+
+async fn get_file_path(
+	db_pool: &Pool<Postgres>,
+	file_id: uuid::Uuid,
+) -> Result<PathBuf, DownloaderError> {
+	get_vfs_file_info(db_pool, file_id)
+		.await
+		.map(|info| get_abs_file_path(&info.file_path))
+		.map_err(DownloaderError::Vfs)
+}
+
+/// run the post-processing steps of a job on the file it imported, returning the file the job ends up with
+///
+/// transforms are chained in the job directory and committed once a vfs action or the end of the steps is reached
+pub async fn run_post_processing(
+	db_pool: &Pool<Postgres>,
+	context: &DownloaderContext,
+	job: &DownloadJob,
+	job_dir: &Path,
+	media_file: uuid::Uuid,
+) -> Result<uuid::Uuid, DownloaderError> {
+	let post_processing = &job.post_processing;
+	let file_type = get_vfs_file_info(db_pool, media_file)
+		.await
+		.map_err(DownloaderError::Vfs)?
+		.file_type
+	;
+	let is_media = matches!(file_type.as_str(), "video" | "audio");
+	let mut has_video = file_type == "video";
+	let mut current = PostProcessed {
+		file: media_file,
+		node: get_file_node(db_pool, media_file).await?,
+	};
+	let mut current_path = get_file_path(db_pool, media_file).await?;
+	// the latest transform output that is not committed yet, along with what it went through
+	let mut pending: Option<PathBuf> = None;
+	let mut labels: Vec<String> = vec![];
+
+	for (index, step) in post_processing.steps.iter().enumerate() {
+		context.report_progress(job.user_id, PubDownloadProgress::phase(job.id, PubDownloadPhase::PostProcessing(step.to_string())));
+
+		match step {
+			PubPostStep::MoveByUploader => {
+				if let Some(path) = pending.take() {
+					current = commit_post_processed(db_pool, current, path, &labels.join(", "), post_processing.output).await?;
+					current_path = get_file_path(db_pool, current.file).await?;
+					labels.clear();
+				}
+				move_to_uploader_folder(db_pool, current).await?;
+			},
+			_ if !is_media => {
+				println!("not running '{}' on '{media_file}', it is no media file", step.to_string());
+			},
+			transform => {
+				let input = TransformInput {
+					path: pending.as_deref().unwrap_or(&current_path),
+					source: current,
+					has_video,
+				};
+				let output_stem = job_dir.join(format!("{}{index}", consts::OUTPUT_PREFIX));
+				let Some(output) = run_transform(db_pool, transform, input, output_stem).await? else {
+					continue;
+				};
+				if matches!(transform, PubPostStep::ConvertAudio { .. }) {
+					has_video = false;
+				}
+				pending = Some(output);
+				labels.push(transform.to_string());
+			},
+		}
+	}
+
+	if let Some(path) = pending {
+		current = commit_post_processed(db_pool, current, path, &labels.join(", "), post_processing.output).await?;
+	}
+	Ok(current.file)
+}
@@ -0,0 +1,89 @@
+use sqlx::{Pool, Postgres};
+use thrw_shared::{app::media_request::DownloaderContext, downloader::{shared::DownloaderError, subscription::{claim_due_subscription, fail_subscription_check, queue_subscription_entries, DueSubscription, SubscriptionEntry}, util::get_ytdl_path}};
+use youtube_dl::YoutubeDlOutput;
+
+mod consts {
+	use std::time::Duration;
+
+	pub const SCHEDULER_INTERVAL: Duration = Duration::from_secs(60);
+	/// only the newest entries are listed, older ones were seen by earlier checks
+	pub const PLAYLIST_END: &str = "50";
+}
+
+/// the title and newest entries of a channel or playlist
+async fn list_entries(
+	url: &str,
+) -> Result<(Option<String>, Vec<SubscriptionEntry>), DownloaderError> {
+	let ytdl_path = get_ytdl_path()?;
+	let output = youtube_dl::YoutubeDl::new(url)
+		.socket_timeout("15")
+		.youtube_dl_path(&ytdl_path)
+		.flat_playlist(true)
+		.extra_arg("--playlist-end")
+		.extra_arg(consts::PLAYLIST_END)
+		.run_async()
+		.await
+		.map_err(DownloaderError::Ytdl)?
+	;
+	let playlist = match output {
+		YoutubeDlOutput::Playlist(playlist) => *playlist,
+		YoutubeDlOutput::SingleVideo(_) => return Err(DownloaderError::NotPlaylist),
+	};
+
+	let entries = playlist.entries
+		.unwrap_or_default()
+		.into_iter()
+		.filter_map(|entry| Some(SubscriptionEntry {
+			url: entry.webpage_url.or(entry.url)?,
+			duration: entry.duration.as_ref().and_then(serde_json::Value::as_f64),
+			title: entry.title,
+			id: entry.id,
+		}))
+		.collect()
+	;
+	Ok((playlist.title.or(playlist.id), entries))
+}
+
+async fn check_subscription(
+	db_pool: &Pool<Postgres>,
+	subscription: &DueSubscription,
+) -> Result<usize, DownloaderError> {
+	let (title, entries) = list_entries(&subscription.url).await?;
+	queue_subscription_entries(db_pool, subscription, title, entries).await
+}
+
+/// check due subscriptions one after another and queue their new entries
+pub async fn run_scheduler(
+	db_pool: Pool<Postgres>,
+	context: DownloaderContext,
+) {
+	loop {
+		let subscription = match claim_due_subscription(&db_pool).await {
+			Ok(Some(subscription)) => subscription,
+			Ok(None) => {
+				let _ = tokio::time::timeout(consts::SCHEDULER_INTERVAL, context.subscription_notify.notified()).await;
+				continue;
+			},
+			Err(err) => {
+				println!("unable to fetch due subscriptions: {err:?}");
+				tokio::time::sleep(consts::SCHEDULER_INTERVAL).await;
+				continue;
+			},
+		};
+
+		let sub_id = subscription.id;
+		match check_subscription(&db_pool, &subscription).await {
+			Ok(0) => (),
+			Ok(count) => {
+				println!("subscription {sub_id} queued {count} new entries");
+				context.notify_job();
+			},
+			Err(err) => {
+				println!("checking subscription {sub_id} failed: {err:?}");
+				if let Err(err) = fail_subscription_check(&db_pool, sub_id, format!("{err:?}")).await {
+					println!("unable to update subscription {sub_id}: {err:?}");
+				}
+			},
+		}
+	}
+}
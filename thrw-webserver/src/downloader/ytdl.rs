@@ -0,0 +1,361 @@
+use std::{collections::VecDeque, ffi::OsStr, path::Path, process::Stdio, time::Instant};
+
+use futures::future::BoxFuture;
+use sqlx::{Pool, Postgres};
+use thrw_shared::{app::media_request::{YtdlAuth, YtdlRequest, YtdlResult, YtdlSubtitle}, downloader::{credentials::write_ytdl_auth, shared::{DownloaderError, PubAudioCodec, PubDownloadFormat, PubDownloadPhase, PubDownloadProgress}, util::{ensure_playlist_folder, expand_download_job, get_ytdl_path, probe_ytdl_url, DownloadJob, PlaylistEntry}}, media::{binaries::binary_path, shared::PubBinaryKind}, vfs::util::FileRef};
+use youtube_dl::{Playlist, SingleVideo, YoutubeDlOutput};
+use tokio::{io::{AsyncBufReadExt, BufReader}, process::Command};
+
+use super::{Downloaded, MediaDownloader};
+
+mod consts {
+	pub const STDERR_TAIL_LINES: usize = 20;
+
+	pub const DL_PROGRESS_PREFIX: &str = "[thrw:dl]";
+	pub const PP_PROGRESS_PREFIX: &str = "[thrw:pp]";
+	/// downloaded|total|estimated total|bytes per second|eta seconds, missing values are printed as NA
+	pub const DL_PROGRESS_TEMPLATE: &str = const_format::concatcp!(
+		"download:", DL_PROGRESS_PREFIX,
+		"%(progress.downloaded_bytes)s|%(progress.total_bytes)s|%(progress.total_bytes_estimate)s|%(progress.speed)s|%(progress.eta)s"
+	);
+	pub const PP_PROGRESS_TEMPLATE: &str = const_format::concatcp!(
+		"postprocess:", PP_PROGRESS_PREFIX,
+		"%(progress.postprocessor)s"
+	);
+
+	pub const YT_DL_TEMP_FILENAME: &str = "TEMP";
+	pub const YT_DL_TEMP_PREFIX: &str = const_format::concatcp!(YT_DL_TEMP_FILENAME, ".");
+
+	/// what yt-dlp picks on its own, used as the fallback of a picked format
+	pub const DEFAULT_VIDEO_FORMAT: &str = "bestvideo*+bestaudio/best";
+	pub const DEFAULT_AUDIO_FORMAT: &str = "bestaudio/best";
+
+	pub const SUB_LANGS_ENV: &str = "YTDL_SUB_LANGS";
+	pub const DEFAULT_SUB_LANGS: &str = "en.*";
+	pub const SUB_FORMAT: &str = "vtt";
+
+	/// printed instead of downloading a file over `--max-filesize`
+	pub const MAX_FILESIZE_MESSAGE: &str = "larger than max-filesize";
+}
+
+/// parse a `[thrw:...]` line printed through our progress templates
+fn parse_progress_line(job_id: uuid::Uuid, line: &str) -> Option<PubDownloadProgress> {
+	let number = |value: &str| value.trim().parse::<f64>().ok();
+
+	if let Some(rest) = line.strip_prefix(consts::DL_PROGRESS_PREFIX) {
+		let mut fields = rest.split('|');
+		let downloaded = fields.next().and_then(number);
+		let total = fields.next().and_then(number);
+		let estimate = fields.next().and_then(number);
+		let speed = fields.next().and_then(number);
+		let eta = fields.next().and_then(number);
+
+		let percent = downloaded
+			.zip(total.or(estimate).filter(|total| *total > 0.0))
+			.map(|(downloaded, total)| (downloaded / total * 100.0).clamp(0.0, 100.0))
+		;
+		return Some(PubDownloadProgress {
+			job_id,
+			phase: PubDownloadPhase::Downloading,
+			percent,
+			speed,
+			eta: eta.map(|eta| eta as i64),
+		});
+	}
+
+	let postprocessor = line.strip_prefix(consts::PP_PROGRESS_PREFIX)?;
+	Some(PubDownloadProgress::phase(job_id, PubDownloadPhase::PostProcessing(postprocessor.trim().to_string())))
+}
+
+fn add_format_args(command: &mut Command, audio_only: bool, format: &PubDownloadFormat) {
+	// a picked format overrides the sorting, yt-dlp still falls back to its default if it disappeared
+	if let Some(format_id) = &format.format_id {
+		command.arg("--format").arg(format!("{format_id}/{}", match audio_only {
+			true => consts::DEFAULT_AUDIO_FORMAT,
+			false => consts::DEFAULT_VIDEO_FORMAT,
+		}));
+	}
+	let mut sort = vec![];
+	if let Some(height) = format.max_height.filter(|_| !audio_only) {
+		sort.push(format!("res:{height}"));
+	}
+
+	if audio_only {
+		command
+			.arg("--extract-audio")
+			.arg("--audio-format")
+			.arg(format.audio_codec.unwrap_or(PubAudioCodec::Best).to_string())
+		;
+		if let Some(bitrate) = format.audio_bitrate {
+			command.arg("--audio-quality").arg(format!("{bitrate}K"));
+		}
+	} else if let Some(container) = format.container {
+		let container = container.to_string();
+		// prefer streams that fit the container so remuxing rarely has to happen
+		sort.push(format!("ext:{container}"));
+		command
+			.arg("--merge-output-format")
+			.arg(&container)
+			.arg("--remux-video")
+			.arg(&container)
+		;
+	}
+
+	if !sort.is_empty() {
+		command.arg("--format-sort").arg(sort.join(","));
+	}
+	if format.embed_thumbnail {
+		// the thumbnail is still written separately since --write-thumbnail is given explicitly
+		command.arg("--embed-thumbnail");
+	}
+	if format.embed_metadata {
+		command.arg("--embed-metadata");
+	}
+}
+
+/// run yt-dlp for the actual download, reporting its progress as it goes
+async fn run_yt_dl_download(
+	ytdl_path: &Path,
+	req: &YtdlRequest,
+	auth: &YtdlAuth,
+	out_dir: &Path,
+	job_id: uuid::Uuid,
+	max_size: Option<u64>,
+	mut report: impl FnMut(PubDownloadProgress),
+) -> Result<(), DownloaderError> {
+	let mut command = Command::new(ytdl_path);
+	command
+		.arg("--newline")
+		.arg("--no-colors")
+		.args(["--socket-timeout", "15"])
+		.arg("--paths")
+		.arg(out_dir)
+		.arg("--output")
+		.arg(format!("{}.%(ext)s", consts::YT_DL_TEMP_FILENAME))
+		.arg("--write-thumbnail")
+		.arg("--progress-template")
+		.arg(consts::DL_PROGRESS_TEMPLATE)
+		.arg("--progress-template")
+		.arg(consts::PP_PROGRESS_TEMPLATE)
+	;
+	add_format_args(&mut command, req.audio_only, &req.format);
+	command.args(auth.args());
+	if let Some(max_size) = max_size {
+		command.arg("--max-filesize").arg(max_size.to_string());
+	}
+	if let Ok(ffmpeg_path) = binary_path(&PubBinaryKind::Ffmpeg).canonicalize() {
+		command
+			.arg("--ffmpeg-location")
+			.arg(ffmpeg_path)
+		;
+	}
+	if !req.audio_only {
+		let sub_langs = std::env::var(consts::SUB_LANGS_ENV)
+			.unwrap_or(consts::DEFAULT_SUB_LANGS.to_string())
+		;
+		command
+			.arg("--write-subs")
+			.arg("--write-auto-subs")
+			.arg("--sub-langs")
+			.arg(sub_langs)
+			.arg("--convert-subs")
+			.arg(consts::SUB_FORMAT)
+		;
+	}
+	command
+		.arg("--")
+		.arg(&req.url)
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.kill_on_drop(true)
+	;
+
+	let mut child = command.spawn().map_err(DownloaderError::Io)?;
+	let stdout = child.stdout.take().ok_or(DownloaderError::YtdlInitError)?;
+	let stderr = child.stderr.take().ok_or(DownloaderError::YtdlInitError)?;
+
+	// keep the end of stderr around for the error message
+	let stderr_task = tokio::spawn(async move {
+		let mut lines = BufReader::new(stderr).lines();
+		let mut tail: VecDeque<String> = VecDeque::new();
+		while let Ok(Some(line)) = lines.next_line().await {
+			if tail.len() == consts::STDERR_TAIL_LINES {
+				tail.pop_front();
+			}
+			tail.push_back(line);
+		}
+		Vec::from(tail).join("\n")
+	});
+
+	let mut lines = BufReader::new(stdout).lines();
+	let mut last_report: Option<Instant> = None;
+	let mut too_large = false;
+	while let Some(line) = lines.next_line().await.map_err(DownloaderError::Io)? {
+		// yt-dlp skips such files, possibly without failing
+		if line.contains(consts::MAX_FILESIZE_MESSAGE) {
+			too_large = true;
+		}
+		let Some(progress) = parse_progress_line(job_id, &line) else {
+			continue;
+		};
+		// progress lines come in several times a second, phase changes always go through
+		let throttled = progress.phase == PubDownloadPhase::Downloading
+			&& last_report.is_some_and(|last| last.elapsed() < super::consts::PROGRESS_INTERVAL)
+		;
+		if !throttled {
+			last_report = Some(Instant::now());
+			report(progress);
+		}
+	}
+
+	let status = child.wait().await.map_err(DownloaderError::Io)?;
+	let stderr_tail = stderr_task.await.unwrap_or_default();
+	if let (true, Some(max_size)) = (too_large, max_size) {
+		return Err(DownloaderError::TooLarge(max_size));
+	}
+	if !status.success() {
+		return Err(DownloaderError::YtdlProcess(status.code(), stderr_tail));
+	}
+	Ok(())
+}
+
+async fn handle_yt_dl(
+	req: &YtdlRequest,
+	auth: &YtdlAuth,
+	output: SingleVideo,
+	job_id: uuid::Uuid,
+	path: &Path,
+	max_size: Option<u64>,
+	report: impl FnMut(PubDownloadProgress),
+) -> Result<YtdlResult, DownloaderError> {
+	let ytdl_path = get_ytdl_path()?;
+
+	println!("downloading media at '{}' into '{path:?}'", req.url);
+	run_yt_dl_download(&ytdl_path, req, auth, path, job_id, max_size, report).await?;
+
+	let files: Result<Vec<_>, std::io::Error> = std::fs::read_dir(path)
+		.map_err(DownloaderError::Io)?
+		.collect()
+	;
+	let files = files.map_err(DownloaderError::Io)?;
+	// subtitles are written as TEMP.<lang>.vtt
+	let subtitles: Vec<YtdlSubtitle> = files
+		.iter()
+		.filter_map(|entry| {
+			let path = entry.path();
+			(path.extension()? == consts::SUB_FORMAT).then_some(())?;
+			let lang = path
+				.file_stem()?
+				.to_str()?
+				.strip_prefix(consts::YT_DL_TEMP_PREFIX)?
+				.to_string()
+			;
+			Some(YtdlSubtitle { lang, file: entry.into() })
+		})
+		.collect()
+	;
+	let file_refs: Vec<FileRef> = files
+		.iter()
+		.filter(|en| en.path().with_extension("").file_name().map(OsStr::to_str).unwrap_or(Some("")).unwrap_or("") == consts::YT_DL_TEMP_FILENAME)
+		.map(|entry| entry.into())
+		.collect()
+	;
+
+	let mut media = None;
+	let mut thumbnail = None;
+
+	for file in file_refs {
+		// only the header is read, the media may be gigabytes
+		let infer_data = infer::get_from_path(&file.path)
+			.map_err(DownloaderError::Io)?
+		;
+		match infer_data {
+			Some(itype) => match itype.matcher_type() {
+				  infer::MatcherType::Video
+				| infer::MatcherType::Audio => media = Some(file),
+				infer::MatcherType::Image => thumbnail = Some(file),
+				_ => {
+					println!("invalid file type received from ytdl, removing");
+					let _ = file.delete_file();
+					continue;
+				},
+			},
+			None => {
+				println!("unable to determine ytdl file type, removing");
+				let _ = file.delete_file();
+				continue;
+			},
+		};
+	}
+	
+	let result = YtdlResult {
+		media: media.ok_or(DownloaderError::NoTempFile)?,
+		thumbnail,
+		subtitles,
+		output,
+	};
+	Ok(result)
+}
+
+async fn expand_playlist(
+	db_pool: &Pool<Postgres>,
+	job: &DownloadJob,
+	playlist: Playlist,
+) -> Result<usize, DownloaderError> {
+	let entries: Vec<PlaylistEntry> = playlist.entries
+		.unwrap_or_default()
+		.into_iter()
+		.filter_map(|entry| Some(PlaylistEntry {
+			url: entry.webpage_url.or(entry.url)?,
+			title: entry.title,
+		}))
+		.collect()
+	;
+	if entries.is_empty() {
+		return Err(DownloaderError::EmptyPlaylist);
+	}
+
+	let title = playlist.title
+		.or(playlist.id)
+		.unwrap_or("UNKNOWN PLAYLIST??".to_string())
+	;
+	// nested playlists of a channel end up in subfolders of the channel's folder
+	let folder = ensure_playlist_folder(db_pool, job.target.clone(), &title).await?;
+	expand_download_job(db_pool, job, Some(title), folder, entries).await
+}
+
+/// everything yt-dlp has an extractor for, playlists and channels included
+pub struct YtdlDownloader;
+impl MediaDownloader for YtdlDownloader {
+	fn name(&self) -> &'static str {
+		"yt-dlp"
+	}
+
+	fn handles(&self, _url: &str) -> bool {
+		// its generic extractor takes anything else
+		true
+	}
+
+	fn download<'a>(
+		&'a self,
+		db_pool: &'a Pool<Postgres>,
+		job: &'a DownloadJob,
+		job_dir: &'a Path,
+		max_size: Option<u64>,
+		report: &'a (dyn Fn(PubDownloadProgress) + Send + Sync),
+	) -> BoxFuture<'a, Result<Downloaded, DownloaderError>> {
+		Box::pin(async move {
+			// only the logins of the job's user for the site of the url end up in its directory
+			let auth = write_ytdl_auth(db_pool, job.user_id, &job.request.url, job_dir).await?;
+			match probe_ytdl_url(&job.request.url, &auth).await? {
+				YoutubeDlOutput::SingleVideo(output) => handle_yt_dl(&job.request, &auth, *output, job.id, job_dir, max_size, |progress| report(progress))
+					.await
+					.map(Downloaded::Ytdl),
+				YoutubeDlOutput::Playlist(playlist) => expand_playlist(db_pool, job, *playlist)
+					.await
+					.map(Downloaded::Expanded),
+			}
+		})
+	}
+}
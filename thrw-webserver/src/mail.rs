@@ -0,0 +1,128 @@
+use std::{env, sync::Arc};
+
+use futures::future::BoxFuture;
+use lettre::{message::{header::ContentType, Mailbox}, transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use thrw_shared::app::mail::{LogMailer, Mail, MailError, Mailer};
+
+mod consts {
+	pub const HOST_ENV: &str = "THRW_SMTP_HOST";
+	pub const PORT_ENV: &str = "THRW_SMTP_PORT";
+	pub const USER_ENV: &str = "THRW_SMTP_USER";
+	pub const PASSWORD_ENV: &str = "THRW_SMTP_PASSWORD";
+	/// `tls`, `starttls` or `none`, the latter is meant for local sinks like mailpit
+	pub const TLS_ENV: &str = "THRW_SMTP_TLS";
+	pub const FROM_ENV: &str = "THRW_MAIL_FROM";
+
+	pub const DEFAULT_FROM: &str = "7sins <noreply@localhost>";
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SmtpTls {
+	None,
+	StartTls,
+	Tls,
+}
+impl TryFrom<&str> for SmtpTls {
+	type Error = MailError;
+
+	fn try_from(value: &str) -> Result<Self, Self::Error> {
+		match value.to_ascii_lowercase().as_str() {
+			"none" => Ok(Self::None),
+			"starttls" => Ok(Self::StartTls),
+			"tls" => Ok(Self::Tls),
+			other => Err(MailError::Config(format!("unknown smtp tls mode '{other}'"))),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpMailer {
+	transport: AsyncSmtpTransport<Tokio1Executor>,
+	from: Mailbox,
+}
+impl SmtpMailer {
+	/// `None` when no smtp host is configured
+	pub fn from_env() -> Result<Option<Self>, MailError> {
+		let Some(host) = env::var(consts::HOST_ENV).ok().filter(|host| !host.trim().is_empty()) else {
+			return Ok(None);
+		};
+		let tls = env::var(consts::TLS_ENV)
+			.map(|tls| SmtpTls::try_from(tls.as_str()))
+			.unwrap_or(Ok(SmtpTls::StartTls))?
+		;
+
+		let mut builder = match tls {
+			SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host),
+			SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+				.map_err(|err| MailError::Config(err.to_string()))?,
+			SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&host)
+				.map_err(|err| MailError::Config(err.to_string()))?,
+		};
+		if let Ok(port) = env::var(consts::PORT_ENV) {
+			let port = port
+				.parse::<u16>()
+				.map_err(|err| MailError::Config(format!("invalid smtp port: {err}")))?
+			;
+			builder = builder.port(port);
+		}
+		if let (Ok(user), Ok(password)) = (env::var(consts::USER_ENV), env::var(consts::PASSWORD_ENV)) {
+			builder = builder.credentials(Credentials::new(user, password));
+		}
+
+		let from = env::var(consts::FROM_ENV)
+			.unwrap_or(consts::DEFAULT_FROM.to_string())
+			.parse::<Mailbox>()
+			.map_err(|err| MailError::Config(format!("invalid sender address: {err}")))?
+		;
+
+		Ok(Some(Self {
+			transport: builder.build(),
+			from,
+		}))
+	}
+}
+impl Mailer for SmtpMailer {
+	fn send<'a>(&'a self, mail: Mail) -> BoxFuture<'a, Result<(), MailError>> {
+		Box::pin(async move {
+			let to = mail.to
+				.parse::<Mailbox>()
+				.map_err(|err| MailError::Address(err.to_string()))?
+			;
+			let message = Message::builder()
+				.from(self.from.clone())
+				.to(to)
+				.subject(mail.subject)
+				.header(ContentType::TEXT_PLAIN)
+				.body(mail.body)
+				.map_err(|err| MailError::Send(err.to_string()))?
+			;
+			self.transport
+				.send(message)
+				.await
+				.map_err(|err| MailError::Send(err.to_string()))?
+			;
+			Ok(())
+		})
+	}
+}
+
+/// smtp when configured, otherwise mails only end up in the log; a configured server that can't be used stops the startup
+pub async fn init_mailer() -> Result<Arc<dyn Mailer>, MailError> {
+	let Some(mailer) = SmtpMailer::from_env()? else {
+		println!("==================================================");
+		println!("WARNING: {} is not set, mails are NOT sent!", consts::HOST_ENV);
+		println!("verification and password reset links never reach anyone,");
+		println!("only the recipient and subject of each mail are logged");
+		println!("==================================================");
+		return Ok(Arc::new(LogMailer));
+	};
+
+	let reachable = mailer.transport
+		.test_connection()
+		.await
+		.map_err(|err| MailError::Config(format!("unable to reach the smtp server: {err}")))?
+	;
+	reachable
+		.then_some(Arc::new(mailer) as Arc<dyn Mailer>)
+		.ok_or(MailError::Config("the smtp server refused the connection".to_string()))
+}
@@ -7,17 +7,19 @@ use axum::{response::Html, routing::get, Router, ServiceExt};
 use leptos::{config::{errors::LeptosConfigError, get_configuration}, html::Var};
 use leptos_axum::{file_and_error_handler, generate_route_list, LeptosRoutes};
 use sqlx::{migrate::MigrateError, postgres::PgPoolOptions, Pool, Postgres};
+use thrw_shared::app::mail::MailError;
 use leptos::{*, prelude::*};
 #[cfg(debug_assertions)]
 use tower_http::cors::CorsLayer;
 
-use crate::{downloader::init_downloader, state::AppState, ws::WebSocketState};
+use crate::{downloader::start_downloader, mail::init_mailer, state::AppState, ws::WebSocketState};
 
 mod user;
 mod ws;
 mod state;
 mod cookie;
 mod downloader;
+mod mail;
 
 thrw_shared::make_error_type!(
 	StartupError {
@@ -27,6 +29,7 @@ thrw_shared::make_error_type!(
 		Migrate(MigrateError),
 		LeptosConfig(LeptosConfigError),
 		IO(std::io::Error),
+		Mail(MailError),
 	}
 );
 
@@ -60,15 +63,15 @@ async fn main() -> Result<(), StartupError> {
 			db_pool: db_pool.clone(),
 			leptos_options,
 			user_data: Default::default(),
-			dl_context: init_downloader(
-				&db_pool
-			),
+			dl_context: Default::default(),
+			mailer: init_mailer().await?,
 		},
 		socket: Default::default(),
 	};
 
 	// Reset DB if requested
 	let reset_needed = args.contains(&"--revert".to_string());
+	let maintenance = reset_needed || args.contains(&"--migrate".to_string());
 	if reset_needed {
 		println!("Resetting DB...");
 		sqlx::migrate!("./migrations")
@@ -93,6 +96,13 @@ async fn main() -> Result<(), StartupError> {
 		;
 	}
 
+	// the downloader stays off while the database is being reset or migrated by hand
+	if maintenance {
+		println!("started for maintenance, downloads are not processed");
+	} else {
+		start_downloader(&db_pool, app_state.shared.dl_context.clone());
+	}
+
 	if let Err(err) = thrw_shared::vfs::util::init_vfs(&db_pool).await {
 		println!("error setting up vfs: {err:?}")
 	}
@@ -1,13 +1,20 @@
-use std::{net::SocketAddr, str::FromStr};
+use std::{net::SocketAddr, str::FromStr, time::Duration};
 
 use axum::{extract::{ws::{Message, Utf8Bytes, WebSocket}, ConnectInfo, State, WebSocketUpgrade}, http::{HeaderMap, StatusCode}, response::IntoResponse};
 use axum_extra::extract::CookieJar;
+use futures::{SinkExt, StreamExt};
 use leptos::prelude::use_context;
-use thrw_shared::{app::{cookie::values::SESSION_TOKEN, state::server::SharedAppState}, user::auth::{check_token_validity_and_refresh, check_token_validity_and_refresh_with_state}, ws::ThrwSocketMessage};
+use thrw_shared::{app::{cookie::values::SESSION_TOKEN, state::server::SharedAppState}, user::{api::ApiScope, auth::{bearer_from_headers, check_token_validity_and_refresh, check_token_validity_and_refresh_with_state, check_token_validity_with_state, Caller}, tokens::{check_api_token_id_with_state, check_api_token_with_state}}, ws::ThrwSocketMessage};
+use tokio::sync::broadcast::error::RecvError;
 use uuid::Uuid;
 
 use crate::state::AppState;
 
+mod consts {
+	/// how often the session or api token of an open socket is checked again
+	pub const REVALIDATE_SECS: u64 = 30;
+}
+
 #[derive(Debug, Clone)]
 pub struct WebSocketState {
 	broadcaster: tokio::sync::broadcast::Sender<(ThrwSocketMessage, i32, SocketAddr)>,
@@ -69,6 +76,19 @@ pub async fn handle_ws(
 	State(state): State<AppState>,
 ) -> impl IntoResponse {
 	leptos::logging::log!("websocket connection requested for '{addr}'...");
+	// scripts follow their downloads with an api token instead of the session cookie
+	if let Some(api_token) = bearer_from_headers(&headers) {
+		return match check_api_token_with_state(&api_token, ApiScope::Downloads, state.clone().shared).await {
+			Ok((user_id, token_id)) => ws
+				.on_upgrade(move |socket| handle_socket(socket, addr, Caller::ApiToken(token_id), user_id, state))
+				.into_response(),
+			Err(err) => {
+				leptos::logging::log!("user authentication error (invalid api token): '{err:#?}'");
+				StatusCode::UNAUTHORIZED.into_response()
+			},
+		};
+	}
+
 	let token = match crate::cookie::try_extract_cookie(&headers, SESSION_TOKEN) {
 		Ok(token) => Uuid::from_str(&token),
 		Err(err) => {
@@ -85,21 +105,37 @@ pub async fn handle_ws(
 		},
 	};
 
-	if let Err(err) = check_token_validity_and_refresh_with_state(token, state.clone().shared).await {
-		leptos::logging::log!("user authentication error (invalid session): '{err:#?}'");
-		return StatusCode::UNAUTHORIZED.into_response();
-	}
+	let user_id = match check_token_validity_and_refresh_with_state(token, addr.ip(), state.clone().shared).await {
+		Ok((user_id, _)) => user_id,
+		Err(err) => {
+			leptos::logging::log!("user authentication error (invalid session): '{err:#?}'");
+			return StatusCode::UNAUTHORIZED.into_response();
+		},
+	};
 
 	ws
-		.on_upgrade(move |socket| handle_socket(socket, addr, token, state))
+		.on_upgrade(move |socket| handle_socket(socket, addr, Caller::Session(token), user_id, state))
 		.into_response()
 }
 
-async fn handle_socket(mut socket: WebSocket, addr: SocketAddr, token: Uuid, state: AppState) {
+/// sessions can be logged out remotely or end on an ip mismatch, tokens can be revoked or expire while the socket is open
+async fn is_caller_valid(caller: Caller, addr: SocketAddr, state: &AppState) -> bool {
+	let res = match caller {
+		Caller::Session(token) => check_token_validity_with_state(token, addr.ip(), state.shared.clone()).await,
+		Caller::ApiToken(token_id) => check_api_token_id_with_state(token_id, state.shared.clone()).await,
+	};
+	if let Err(err) = &res {
+		leptos::logging::log!("socket '{addr}' lost its authentication: '{err:?}'");
+	}
+	res.is_ok()
+}
+
+async fn handle_socket(mut socket: WebSocket, addr: SocketAddr, caller: Caller, user_id: i32, state: AppState) {
 	leptos::logging::log!("setting up socket for '{addr}'");
 	
-	let ws_state = state.socket;
+	let ws_state = state.socket.clone();
 	let mut receive = ws_state.broadcaster.subscribe();
+	let mut progress_receive = state.shared.dl_context.progress.subscribe();
 	let sender = ws_state.broadcaster.clone();
 	let char_id = {
 		let ClientWebsocketMessage::Ssins(ThrwSocketMessage::Intoduce(char_id)) = ClientWebsocketMessage::from(socket.recv().await) else {
@@ -124,9 +160,11 @@ async fn handle_socket(mut socket: WebSocket, addr: SocketAddr, token: Uuid, sta
 		1
 	};
 
+	let (mut socket_send, mut socket_recv) = socket.split();
+
 	let mut recv_task = tokio::spawn( async move {
 		loop {
-			match ClientWebsocketMessage::from(socket.recv().await) {
+			match ClientWebsocketMessage::from(socket_recv.next().await) {
 				ClientWebsocketMessage::Ssins(thrw_socket_message) => {
 					if let Err(err) = sender.send((thrw_socket_message, char_id, addr)) {
 						leptos::logging::log!("error transmitting client message: '{err}'");
@@ -143,8 +181,40 @@ async fn handle_socket(mut socket: WebSocket, addr: SocketAddr, token: Uuid, sta
 	});
 
 	let mut send_task = tokio::spawn(async move {
-		while let Ok(mes) = receive.recv().await {
-			
+		let mut revalidate = tokio::time::interval(Duration::from_secs(consts::REVALIDATE_SECS));
+		// the first tick completes right away, the caller was just checked on upgrade
+		revalidate.tick().await;
+		loop {
+			let message = tokio::select! {
+				_ = revalidate.tick() => match is_caller_valid(caller, addr, &state).await {
+					true => continue,
+					false => {
+						let _ = socket_send.send(Message::Close(None)).await;
+						break;
+					},
+				},
+				mes = receive.recv() => match mes {
+					// chat messages are not relayed yet
+					Ok(_) | Err(RecvError::Lagged(_)) => continue,
+					Err(RecvError::Closed) => break,
+				},
+				progress = progress_receive.recv() => match progress {
+					Ok((target, progress)) if target == user_id => ThrwSocketMessage::DownloadProgress(progress),
+					Ok(_) | Err(RecvError::Lagged(_)) => continue,
+					Err(RecvError::Closed) => break,
+				},
+			};
+
+			let text = match serde_json::to_string(&message) {
+				Ok(text) => text,
+				Err(err) => {
+					leptos::logging::log!("error serializing socket message: '{err}'");
+					continue;
+				},
+			};
+			if socket_send.send(Message::Text(text.into())).await.is_err() {
+				break;
+			}
 		}
 	});
 